@@ -1,20 +1,39 @@
 use clap::{Parser, Subcommand};
-use tonic::transport::Channel;
-use tonic::Request;
+use std::path::PathBuf;
 
-pub mod proto {
-    tonic::include_proto!("constellation.v1");
-}
+use tonic::metadata::{Ascii, MetadataValue};
+use tonic::service::{interceptor::InterceptedService, Interceptor};
+use tonic::transport::{Certificate, Channel, ClientTlsConfig, Endpoint, Identity};
+use tonic::{Request, Status};
+use tonic_health::pb::health_check_response::ServingStatus;
+use tonic_health::pb::health_client::HealthClient;
+use tonic_health::pb::HealthCheckRequest;
 
-use proto::display_control_client::DisplayControlClient;
-use proto::*;
+use constellation_model::proto::display_control_client::DisplayControlClient;
+use constellation_model::proto::*;
+use constellation_model::{outputs_from_json, project_from_json};
 
 #[derive(Parser, Debug)]
 #[command(name = "constellation", about = "Constellation Display CLI", version)]
 struct Cli {
-    /// Display server address
+    /// Display server address; https:// connects over TLS
     #[arg(short, long, default_value = "http://127.0.0.1:50051")]
     addr: String,
+    /// Bearer token for a Display with authentication on
+    #[arg(long, global = true, env = "CONSTELLATION_TOKEN", hide_env_values = true)]
+    token: Option<String>,
+    /// PEM CA bundle to verify the Display's certificate [default: system roots]
+    #[arg(long, global = true)]
+    ca_cert: Option<PathBuf>,
+    /// PEM client certificate, for a Display that checks client certificates
+    #[arg(long, global = true, requires = "key")]
+    cert: Option<PathBuf>,
+    /// PEM private key for --cert
+    #[arg(long, global = true, requires = "cert")]
+    key: Option<PathBuf>,
+    /// Server name to verify the certificate against, if not the host in --addr
+    #[arg(long, global = true)]
+    domain: Option<String>,
     #[command(subcommand)]
     command: Commands,
 }
@@ -23,158 +42,156 @@ struct Cli {
 enum Commands {
     /// Load a project from JSON file (editor-facing JSON)
     LoadProject { file: String },
+    /// Replace only the scene of the loaded project, taken from a project JSON file
+    LoadScene { file: String },
     /// Transport controls
     Play { #[arg(long)] at: Option<f64> },
     Pause,
     Stop,
     Seek { to: f64 },
     Rate { rate: f64 },
+    /// What happens at the end of the timeline: hold | stop | loop | loop-region | ping-pong
+    EndBehavior { behavior: EndBehaviorArg },
+    /// Set the loop region used by loop-region/ping-pong (out <= in clears it)
+    LoopRegion { #[arg(long = "in")] in_seconds: f64, #[arg(long = "out")] out_seconds: f64 },
+    /// Switch the active timeline (rewinds to 0 unless --keep-position)
+    ActivateTimeline { id: String, #[arg(long)] keep_position: bool },
+    /// Configure per-screen output windows from a JSON file
+    Outputs { file: String },
+    /// Save the current frame (or the frame at --time) as PNG: the 3D preview, or one screen's canvas
+    Capture {
+        #[arg(long)] out: String,
+        #[arg(long)] screen: Option<String>,
+        #[arg(long)] time: Option<f64>,
+        #[arg(long, default_value_t = 0)] width: u32,
+        #[arg(long, default_value_t = 0)] height: u32,
+    },
+    /// Render a timeline offline to numbered PNGs in --out-dir on the Display host
+    Export {
+        #[arg(long)] out_dir: String,
+        /// Timeline to render (default: the active one)
+        #[arg(long)] timeline: Option<String>,
+        #[arg(long = "in", default_value_t = 0.0)] in_seconds: f64,
+        /// End time (default: end of the timeline)
+        #[arg(long = "out", default_value_t = 0.0)] out_seconds: f64,
+        #[arg(long, default_value_t = 30.0)] fps: f64,
+        /// Screen node to render; repeat for several (default: every screen)
+        #[arg(long = "screen")] screens: Vec<String>,
+        /// Also render the 3D preview, at --width x --height
+        #[arg(long)] preview: bool,
+        #[arg(long, default_value_t = 0)] width: u32,
+        #[arg(long, default_value_t = 0)] height: u32,
+        /// Also encode each sequence to MP4 (Display built with --features video)
+        #[arg(long)] video: bool,
+    },
+    /// Render from a camera node (no id: first camera in the scene)
+    SelectCamera { id: Option<String> },
     /// Subscribe and print state updates
-    Subscribe,
+    Subscribe {
+        /// Updates per second while playing
+        #[arg(long, default_value_t = 30.0)] rate: f64,
+        /// Leave out render metrics
+        #[arg(long)] no_metrics: bool,
+    },
+    /// Print the Display's health status; exits non-zero unless SERVING
+    Health {
+        /// Keep printing status changes
+        #[arg(long)] watch: bool,
+    },
 }
 
-async fn connect(addr: &str) -> Result<DisplayControlClient<Channel>, Box<dyn std::error::Error>> {
-    let client = DisplayControlClient::connect(addr.to_string()).await?;
-    Ok(client)
-}
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+enum EndBehaviorArg { Hold, Stop, Loop, LoopRegion, PingPong }
 
-// ------------- JSON structures (editor-facing) -------------
-#[derive(Debug, serde::Deserialize)]
-struct Vec3J { x: f32, y: f32, z: f32 }
-#[derive(Debug, serde::Deserialize)]
-struct QuatJ { x: f32, y: f32, z: f32, w: f32 }
-#[derive(Debug, serde::Deserialize)]
-struct TransformJ { position: Vec3J, rotation: QuatJ, scale: Vec3J }
-#[derive(Debug, serde::Deserialize)]
-struct ColorJ { r: f32, g: f32, b: f32, a: f32 }
-
-#[derive(Debug, serde::Deserialize)]
-struct MaterialPbrJ {
-    id: String,
-    name: Option<String>,
-    base_color: Option<ColorJ>,
-    metallic: Option<f32>,
-    roughness: Option<f32>,
-    emissive: Option<ColorJ>,
-}
-#[derive(Debug, serde::Deserialize)]
-struct MeshRefJ { id: String, uri: String, node: Option<String> }
-
-#[derive(Debug, serde::Deserialize)]
-struct MeshCompJ { mesh: MeshRefJ, material_id: Option<String> }
-#[derive(Debug, serde::Deserialize)]
-struct LightCompJ { r#type: String, color: ColorJ, intensity: f32, range: f32, spot_angle: Option<f32> }
-#[derive(Debug, serde::Deserialize)]
-struct ScreenCompJ { pixels_x: i32, pixels_y: i32 }
-#[derive(Debug, serde::Deserialize)]
-struct CameraCompJ { fov_deg: f32, near: f32, far: f32 }
-
-#[derive(Debug, serde::Deserialize)]
-struct NodeJ {
-    id: String,
-    name: Option<String>,
-    transform: TransformJ,
-    #[serde(default)]
-    children: Vec<NodeJ>,
-    mesh: Option<MeshCompJ>,
-    light: Option<LightCompJ>,
-    screen: Option<ScreenCompJ>,
-    camera: Option<CameraCompJ>,
+impl From<EndBehaviorArg> for EndBehavior {
+    fn from(b: EndBehaviorArg) -> Self {
+        match b {
+            EndBehaviorArg::Hold => EndBehavior::EndHold,
+            EndBehaviorArg::Stop => EndBehavior::EndStop,
+            EndBehaviorArg::Loop => EndBehavior::EndLoop,
+            EndBehaviorArg::LoopRegion => EndBehavior::EndLoopRegion,
+            EndBehaviorArg::PingPong => EndBehavior::EndPingPong,
+        }
+    }
 }
 
-#[derive(Debug, serde::Deserialize)]
-struct SceneJ {
-    id: String,
-    name: Option<String>,
-    #[serde(default)]
-    materials: Vec<MaterialPbrJ>,
-    #[serde(default)]
-    meshes: Vec<MeshRefJ>,
-    #[serde(default)]
-    roots: Vec<NodeJ>,
-}
+/// Sends `authorization: Bearer <token>` with every call when a token is set.
+#[derive(Clone)]
+struct BearerToken(Option<MetadataValue<Ascii>>);
 
-#[derive(Debug, serde::Deserialize)]
-struct MediaClipJ { id: String, name: Option<String>, uri: String, duration_seconds: f64 }
-#[derive(Debug, serde::Deserialize)]
-struct TrackMediaJ { target_node_id: String, clip_id: String, in_seconds: f64, out_seconds: f64, start_at_seconds: f64 }
-#[derive(Debug, serde::Deserialize)]
-struct TimelineTrackJ { media: Option<TrackMediaJ> }
-#[derive(Debug, serde::Deserialize)]
-struct TimelineEventJ { t: f64, action: String, #[serde(default)] params: std::collections::HashMap<String, String> }
-#[derive(Debug, serde::Deserialize)]
-struct TimelineJ { id: String, name: Option<String>, #[serde(default)] tracks: Vec<TimelineTrackJ>, #[serde(default)] events: Vec<TimelineEventJ>, duration_seconds: f64 }
-#[derive(Debug, serde::Deserialize)]
-struct ProjectJ { id: String, name: Option<String>, scene: SceneJ, #[serde(default)] media: Vec<MediaClipJ>, timeline: TimelineJ }
-#[derive(Debug, serde::Deserialize)]
-struct ProjectWrapperJ { project: ProjectJ }
-
-fn to_proto_vec3(v: Vec3J) -> Vec3 { Vec3 { x: v.x, y: v.y, z: v.z } }
-fn to_proto_quat(q: QuatJ) -> Quat { Quat { x: q.x, y: q.y, z: q.z, w: q.w } }
-fn to_proto_transform(t: TransformJ) -> Transform { Transform { position: Some(to_proto_vec3(t.position)), rotation: Some(to_proto_quat(t.rotation)), scale: Some(to_proto_vec3(t.scale)) } }
-fn to_proto_color(c: ColorJ) -> ColorRgba { ColorRgba { r: c.r, g: c.g, b: c.b, a: c.a } }
-
-fn to_proto_material(m: MaterialPbrJ) -> MaterialPbr {
-    MaterialPbr {
-        id: m.id,
-        name: m.name.unwrap_or_default(),
-        base_color: m.base_color.map(to_proto_color),
-        base_color_tex: None,
-        metallic: m.metallic.unwrap_or(0.0),
-        roughness: m.roughness.unwrap_or(1.0),
-        mr_tex: None,
-        emissive: m.emissive.map(to_proto_color),
-        emissive_tex: None,
+impl Interceptor for BearerToken {
+    fn call(&mut self, mut req: Request<()>) -> Result<Request<()>, Status> {
+        if let Some(token) = &self.0 {
+            req.metadata_mut().insert("authorization", token.clone());
+        }
+        Ok(req)
     }
 }
-fn to_proto_meshref(m: MeshRefJ) -> MeshRef { MeshRef { id: m.id, uri: m.uri, node: m.node.unwrap_or_default() } }
-fn to_proto_node(n: NodeJ) -> Node {
-    let mut node = Node { id: n.id, name: n.name.unwrap_or_default(), transform: Some(to_proto_transform(n.transform)), children: n.children.into_iter().map(to_proto_node).collect(), comp0: None };
-    if let Some(s) = n.screen { node.comp0 = Some(node::Comp0::Screen(ScreenComponent { pixels_x: s.pixels_x, pixels_y: s.pixels_y })); }
-    if let Some(l) = n.light { 
-        let t = match l.r#type.as_str() { "DIRECTIONAL" => 1, "SPOT" => 2, _ => 0 };
-        node.comp0 = Some(node::Comp0::Light(LightComponent { r#type: t, color: Some(to_proto_color(l.color)), intensity: l.intensity, range: l.range, spot_angle: l.spot_angle.unwrap_or(0.0) }));
+
+type Client = DisplayControlClient<InterceptedService<Channel, BearerToken>>;
+
+async fn channel(cli: &Cli) -> Result<Channel, Box<dyn std::error::Error>> {
+    let mut endpoint = Endpoint::from_shared(cli.addr.clone())?;
+    if cli.addr.starts_with("https://") {
+        let mut tls = ClientTlsConfig::new();
+        if let Some(ca) = &cli.ca_cert {
+            tls = tls.ca_certificate(Certificate::from_pem(std::fs::read(ca)?));
+        }
+        if let (Some(cert), Some(key)) = (&cli.cert, &cli.key) {
+            tls = tls.identity(Identity::from_pem(std::fs::read(cert)?, std::fs::read(key)?));
+        }
+        if let Some(domain) = &cli.domain {
+            tls = tls.domain_name(domain);
+        }
+        endpoint = endpoint.tls_config(tls)?;
+    } else if cli.ca_cert.is_some() || cli.cert.is_some() {
+        return Err("--ca-cert and --cert need an https:// address".into());
     }
-    if let Some(c) = n.camera { node.comp0 = Some(node::Comp0::Camera(CameraComponent { fov_deg: c.fov_deg, near: c.near, far: c.far })); }
-    if let Some(m) = n.mesh { node.comp0 = Some(node::Comp0::Mesh(MeshComponent { mesh: Some(to_proto_meshref(m.mesh)), material_id: m.material_id.unwrap_or_default() })); }
-    node
+    Ok(endpoint.connect().await?)
+}
+
+fn connect(channel: Channel, token: Option<&str>) -> Result<Client, Box<dyn std::error::Error>> {
+    let token = token.map(|t| format!("Bearer {t}").parse()).transpose()?;
+    // Captured frames can exceed the default 4 MB message limit
+    Ok(DisplayControlClient::with_interceptor(channel, BearerToken(token)).max_decoding_message_size(256 * 1024 * 1024))
 }
-fn to_proto_scene(s: SceneJ) -> Scene {
-    Scene {
-        id: s.id,
-        name: s.name.unwrap_or_default(),
-        materials: s.materials.into_iter().map(to_proto_material).collect(),
-        meshes: s.meshes.into_iter().map(to_proto_meshref).collect(),
-        roots: s.roots.into_iter().map(to_proto_node).collect(),
+
+/// Problems found converting a JSON file, before it is sent.
+fn print_warnings(warnings: &[String]) {
+    for w in warnings {
+        eprintln!("warning: {w}");
     }
 }
-fn to_proto_project(p: ProjectJ) -> Project {
-    let media = p.media.into_iter().map(|m| MediaClip { id: m.id, name: m.name.unwrap_or_default(), uri: m.uri, duration_seconds: m.duration_seconds }).collect();
-    let tracks = p.timeline.tracks.into_iter().filter_map(|t| {
-        if let Some(m) = t.media { Some(TimelineTrack { kind: Some(timeline_track::Kind::Media(TrackMedia { target_node_id: m.target_node_id, clip_id: m.clip_id, in_seconds: m.in_seconds, out_seconds: m.out_seconds, start_at_seconds: m.start_at_seconds })) }) } else { None }
-    }).collect();
-    let events = p.timeline.events.into_iter().map(|e| TimelineEvent { t: e.t, action: e.action, params: e.params }).collect();
-    Project {
-        id: p.id,
-        name: p.name.unwrap_or_default(),
-        scene: Some(to_proto_scene(p.scene)),
-        media,
-        timeline: Some(Timeline { id: p.timeline.id, name: p.timeline.name.unwrap_or_default(), tracks, events, duration_seconds: p.timeline.duration_seconds }),
+
+fn print_ack(ack: &Ack) {
+    println!("{}: {}", if ack.ok { "ok" } else { "rejected" }, ack.message);
+    for d in &ack.diagnostics {
+        println!("  {:?} {:?}: {}", d.severity(), d.code(), d.message);
     }
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let cli = Cli::parse();
-    let mut client = connect(&cli.addr).await?;
+    let channel = channel(&cli).await?;
+    let mut client = connect(channel.clone(), cli.token.as_deref())?;
 
     match cli.command {
         Commands::LoadProject { file } => {
             let data = std::fs::read_to_string(file)?;
-            let wrapper: ProjectWrapperJ = serde_json::from_str(&data)?;
-            let project = to_proto_project(wrapper.project);
+            let converted = project_from_json(&data)?;
+            print_warnings(&converted.warnings);
+            let project = converted.value;
             let resp = client.load_project(Request::new(LoadProjectRequest { project: Some(project) })).await?;
-            println!("{:?}", resp.into_inner());
+            print_ack(resp.get_ref());
+        }
+        Commands::LoadScene { file } => {
+            let data = std::fs::read_to_string(file)?;
+            let converted = project_from_json(&data)?;
+            print_warnings(&converted.warnings);
+            let scene = converted.value.scene;
+            let resp = client.load_scene(Request::new(LoadSceneRequest { scene })).await?;
+            print_ack(resp.get_ref());
         }
         Commands::Play { at } => {
             let resp = client.play(Request::new(PlayRequest { at_seconds: at.unwrap_or(0.0) })).await?;
@@ -196,12 +213,88 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             let resp = client.set_rate(Request::new(SetRateRequest { rate })).await?;
             println!("{:?}", resp.into_inner());
         }
-        Commands::Subscribe => {
-            let mut stream = client.subscribe_state(Request::new(SubscribeRequest { include_metrics: true })).await?.into_inner();
+        Commands::EndBehavior { behavior } => {
+            let behavior: EndBehavior = behavior.into();
+            let resp = client.set_end_behavior(Request::new(SetEndBehaviorRequest { behavior: behavior as i32 })).await?;
+            println!("{:?}", resp.into_inner());
+        }
+        Commands::LoopRegion { in_seconds, out_seconds } => {
+            let resp = client.set_loop_region(Request::new(SetLoopRegionRequest { in_seconds, out_seconds })).await?;
+            println!("{:?}", resp.into_inner());
+        }
+        Commands::ActivateTimeline { id, keep_position } => {
+            let resp = client.activate_timeline(Request::new(ActivateTimelineRequest { timeline_id: id, keep_position })).await?;
+            println!("{:?}", resp.into_inner());
+        }
+        Commands::Outputs { file } => {
+            let data = std::fs::read_to_string(file)?;
+            let converted = outputs_from_json(&data)?;
+            print_warnings(&converted.warnings);
+            let config = converted.value;
+            let resp = client.set_outputs(Request::new(SetOutputsRequest { config: Some(config) })).await?;
+            print_ack(resp.get_ref());
+        }
+        Commands::Capture { out, screen, time, width, height } => {
+            let req = CaptureFrameRequest { screen_node_id: screen.unwrap_or_default(), time_seconds: time, width, height };
+            let resp = client.capture_frame(Request::new(req)).await?.into_inner();
+            if !resp.ok {
+                return Err(format!("capture failed: {}", resp.message).into());
+            }
+            std::fs::write(&out, &resp.png)?;
+            println!("{}: {}x{} at {:.3}s -> {out}", resp.message, resp.width, resp.height, resp.time_seconds);
+        }
+        Commands::Export { out_dir, timeline, in_seconds, out_seconds, fps, screens, preview, width, height, video } => {
+            let req = ExportRequest {
+                timeline_id: timeline.unwrap_or_default(),
+                in_seconds,
+                out_seconds,
+                fps,
+                out_dir,
+                screen_node_ids: screens,
+                include_preview: preview,
+                preview_width: width,
+                preview_height: height,
+                encode_video: video,
+            };
+            let mut stream = client.export(Request::new(req)).await?.into_inner();
+            while let Some(p) = stream.message().await? {
+                if p.done {
+                    if !p.ok {
+                        return Err(format!("export failed: {}", p.message).into());
+                    }
+                    println!("{}", p.message);
+                } else {
+                    println!("frame {}/{} at {:.3}s", p.frame, p.total_frames, p.time_seconds);
+                }
+            }
+        }
+        Commands::SelectCamera { id } => {
+            let resp = client.select_camera(Request::new(SelectCameraRequest { node_id: id.unwrap_or_default() })).await?;
+            println!("{:?}", resp.into_inner());
+        }
+        Commands::Subscribe { rate, no_metrics } => {
+            let req = SubscribeRequest { include_metrics: !no_metrics, rate_hz: rate };
+            let mut stream = client.subscribe_state(Request::new(req)).await?.into_inner();
             while let Some(update) = stream.message().await? {
                 println!("update: {:?}", update);
             }
         }
+        Commands::Health { watch } => {
+            let mut health = HealthClient::new(channel);
+            let req = HealthCheckRequest { service: String::new() };
+            if watch {
+                let mut stream = health.watch(Request::new(req)).await?.into_inner();
+                while let Some(resp) = stream.message().await? {
+                    println!("{}", resp.status().as_str_name());
+                }
+            } else {
+                let status = health.check(Request::new(req)).await?.into_inner().status();
+                println!("{}", status.as_str_name());
+                if status != ServingStatus::Serving {
+                    std::process::exit(1);
+                }
+            }
+        }
     }
 
     Ok(())
@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+use tonic::service::Interceptor;
+use tonic::{Request, Status};
+use x509_parser::prelude::{FromDer, X509Certificate};
+
+use crate::config::AuthConfig;
+
+/// What a caller may do. `Read` can watch state and capture frames; `Control`
+/// can also change transport, load projects, configure outputs and export.
+#[derive(Deserialize, clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Read,
+    Control,
+}
+
+/// Resolves callers to roles from a bearer token and/or a TLS client certificate.
+/// With no tokens, clients or anonymous role configured, everyone is `Control`.
+#[derive(Clone, Debug, Default)]
+pub struct Auth {
+    tokens: Vec<(String, Role)>,
+    /// Client certificate subject common name -> role
+    clients: HashMap<String, Role>,
+    anonymous: Option<Role>,
+}
+
+impl Auth {
+    pub fn new(cfg: &AuthConfig) -> Self {
+        Self {
+            tokens: cfg.tokens.iter().map(|t| (t.token.clone(), t.role)).collect(),
+            clients: cfg.clients.iter().map(|c| (c.name.clone(), c.role)).collect(),
+            anonymous: cfg.anonymous,
+        }
+    }
+
+    /// Whether callers are checked at all. An anonymous role on its own counts, so
+    /// `anonymous = "read"` without tokens still makes the display read-only.
+    pub fn enabled(&self) -> bool {
+        !self.tokens.is_empty() || !self.clients.is_empty() || self.anonymous.is_some()
+    }
+
+    fn role_of(&self, req: &Request<()>) -> Result<Role, &'static str> {
+        let authorization = req.metadata().get("authorization").map(|v| v.to_str().unwrap_or_default());
+        self.resolve(authorization, peer_common_name(req).as_deref())
+    }
+
+    /// Role for an `authorization` header value and a client certificate common name.
+    fn resolve(&self, authorization: Option<&str>, common_name: Option<&str>) -> Result<Role, &'static str> {
+        let mut role = None;
+        if let Some(value) = authorization {
+            let token = value.strip_prefix("Bearer ").map(str::trim);
+            // Check every token so the time taken doesn't tell which one matched
+            let matched = token.and_then(|token| {
+                self.tokens.iter().filter(|(t, _)| constant_time_eq(t.as_bytes(), token.as_bytes())).map(|(_, r)| *r).max()
+            });
+            // A wrong token is refused even where anonymous callers are let in
+            role = Some(matched.ok_or("invalid token")?);
+        }
+        if let Some(name) = common_name {
+            role = role.max(self.clients.get(name).copied());
+        }
+        role.or(self.anonymous).ok_or("missing credentials")
+    }
+}
+
+/// Interceptor for `DisplayControlServer`: rejects unknown credentials and records
+/// the caller's `Role` in the request extensions for `require`.
+impl Interceptor for Auth {
+    fn call(&mut self, mut req: Request<()>) -> Result<Request<()>, Status> {
+        let role = if self.enabled() { self.role_of(&req).map_err(Status::unauthenticated)? } else { Role::Control };
+        req.extensions_mut().insert(role);
+        Ok(req)
+    }
+}
+
+/// Fail with PERMISSION_DENIED unless the caller authenticated with at least `role`.
+#[allow(clippy::result_large_err)] // handlers return tonic's Status as is
+pub fn require<T>(req: &Request<T>, role: Role) -> Result<(), Status> {
+    match req.extensions().get::<Role>() {
+        Some(r) if *r >= role => Ok(()),
+        _ => Err(Status::permission_denied(format!("requires the {role:?} role").to_lowercase())),
+    }
+}
+
+fn peer_common_name(req: &Request<()>) -> Option<String> {
+    let certs = req.peer_certs()?;
+    let (_, cert) = X509Certificate::from_der(certs.first()?.get_ref()).ok()?;
+    let cn = cert.subject().iter_common_name().next()?.as_str().ok()?.to_string();
+    Some(cn)
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ClientT, TokenT};
+
+    fn auth(anonymous: Option<Role>) -> Auth {
+        Auth::new(&AuthConfig {
+            tokens: vec![TokenT { token: "ctl".into(), role: Role::Control }, TokenT { token: "ro".into(), role: Role::Read }],
+            clients: vec![ClientT { name: "console".into(), role: Role::Control }, ClientT { name: "kiosk".into(), role: Role::Read }],
+            anonymous,
+        })
+    }
+
+    fn role_with(auth: &Auth, metadata: Option<&str>) -> Result<Role, &'static str> {
+        let mut req = Request::new(());
+        if let Some(v) = metadata {
+            req.metadata_mut().insert("authorization", v.parse().unwrap());
+        }
+        let mut auth = auth.clone();
+        auth.call(req).map(|r| *r.extensions().get::<Role>().unwrap()).map_err(|_| "rejected")
+    }
+
+    #[test]
+    fn valid_tokens_map_to_their_role() {
+        let a = auth(None);
+        assert_eq!(role_with(&a, Some("Bearer ctl")), Ok(Role::Control));
+        assert_eq!(role_with(&a, Some("Bearer ro")), Ok(Role::Read));
+        assert_eq!(a.resolve(Some("Bearer  ro "), None), Ok(Role::Read));
+    }
+
+    #[test]
+    fn wrong_token_is_refused_even_with_anonymous_access() {
+        let a = auth(Some(Role::Read));
+        assert_eq!(a.resolve(Some("Bearer nope"), None), Err("invalid token"));
+        assert_eq!(a.resolve(Some("ctl"), None), Err("invalid token"));
+        assert!(role_with(&a, Some("Bearer nope")).is_err());
+    }
+
+    #[test]
+    fn missing_token_falls_back_to_anonymous() {
+        assert_eq!(auth(None).resolve(None, None), Err("missing credentials"));
+        assert!(role_with(&auth(None), None).is_err());
+        assert_eq!(role_with(&auth(Some(Role::Read)), None), Ok(Role::Read));
+    }
+
+    #[test]
+    fn anonymous_role_alone_enables_checks() {
+        let a = Auth::new(&AuthConfig { anonymous: Some(Role::Read), ..Default::default() });
+        assert!(a.enabled());
+        assert_eq!(role_with(&a, None), Ok(Role::Read));
+        assert!(!Auth::default().enabled());
+        assert_eq!(role_with(&Auth::default(), None), Ok(Role::Control));
+    }
+
+    #[test]
+    fn client_common_names_map_to_roles() {
+        let a = auth(None);
+        assert_eq!(a.resolve(None, Some("console")), Ok(Role::Control));
+        assert_eq!(a.resolve(None, Some("kiosk")), Ok(Role::Read));
+        assert_eq!(a.resolve(None, Some("stranger")), Err("missing credentials"));
+        // Token and certificate together grant the higher of the two
+        assert_eq!(a.resolve(Some("Bearer ro"), Some("console")), Ok(Role::Control));
+        assert_eq!(a.resolve(Some("Bearer ctl"), Some("kiosk")), Ok(Role::Control));
+    }
+
+    #[test]
+    fn require_orders_roles() {
+        let with = |role: Option<Role>| {
+            let mut req = Request::new(());
+            if let Some(r) = role {
+                req.extensions_mut().insert(r);
+            }
+            req
+        };
+        assert!(require(&with(Some(Role::Control)), Role::Control).is_ok());
+        assert!(require(&with(Some(Role::Control)), Role::Read).is_ok());
+        assert!(require(&with(Some(Role::Read)), Role::Read).is_ok());
+        let denied = require(&with(Some(Role::Read)), Role::Control).unwrap_err();
+        assert_eq!(denied.code(), tonic::Code::PermissionDenied);
+        assert_eq!(denied.message(), "requires the control role");
+        assert!(require(&with(None), Role::Read).is_err());
+    }
+}
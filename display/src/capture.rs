@@ -0,0 +1,76 @@
+use std::time::{Duration, Instant};
+
+use tokio::sync::oneshot;
+
+use crate::proto::{CaptureFrameRequest, CaptureFrameResponse};
+
+/// How long a capture waits for the clips in its frame to finish loading before
+/// rendering with placeholders.
+pub const CAPTURE_WAIT: Duration = Duration::from_secs(5);
+
+/// A capture requested over RPC, serviced by the render loop.
+pub struct CaptureJob {
+    pub req: CaptureFrameRequest,
+    pub reply: oneshot::Sender<CaptureFrameResponse>,
+    pub deadline: Instant,
+}
+
+/// Copy a rendered RGBA8/BGRA8 texture back to the CPU as tightly packed RGBA8.
+/// Blocks until the GPU has finished.
+pub fn read_rgba(device: &wgpu::Device, queue: &wgpu::Queue, texture: &wgpu::Texture) -> Result<Vec<u8>, String> {
+    let bgra = match texture.format() {
+        wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => true,
+        wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb => false,
+        f => return Err(format!("cannot read back {f:?} textures")),
+    };
+    let size = texture.size();
+    let row = 4 * size.width;
+    // Buffer rows must be padded to the copy alignment
+    let padded = row.div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT) * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor{
+        label: Some("capture-readback"),
+        size: padded as u64 * size.height as u64,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("capture-copy") });
+    encoder.copy_texture_to_buffer(
+        wgpu::ImageCopyTexture { texture, mip_level: 0, origin: wgpu::Origin3d::ZERO, aspect: wgpu::TextureAspect::All },
+        wgpu::ImageCopyBuffer {
+            buffer: &buffer,
+            layout: wgpu::ImageDataLayout { offset: 0, bytes_per_row: Some(padded), rows_per_image: Some(size.height) },
+        },
+        size,
+    );
+    queue.submit(Some(encoder.finish()));
+
+    let slice = buffer.slice(..);
+    let (tx, rx) = std::sync::mpsc::channel();
+    slice.map_async(wgpu::MapMode::Read, move |r| {
+        let _ = tx.send(r);
+    });
+    device.poll(wgpu::Maintain::Wait);
+    rx.recv().map_err(|e| e.to_string())?.map_err(|e| e.to_string())?;
+
+    let mut out = Vec::with_capacity(row as usize * size.height as usize);
+    {
+        let data = slice.get_mapped_range();
+        for line in data.chunks(padded as usize) {
+            out.extend_from_slice(&line[..row as usize]);
+        }
+    }
+    buffer.unmap();
+    if bgra {
+        for px in out.chunks_exact_mut(4) {
+            px.swap(0, 2);
+        }
+    }
+    Ok(out)
+}
+
+pub fn encode_png(rgba: Vec<u8>, width: u32, height: u32) -> Result<Vec<u8>, String> {
+    let img = image::RgbaImage::from_raw(width, height, rgba).ok_or("pixel data does not match image size")?;
+    let mut out = std::io::Cursor::new(Vec::new());
+    img.write_to(&mut out, image::ImageOutputFormat::Png).map_err(|e| e.to_string())?;
+    Ok(out.into_inner())
+}
@@ -0,0 +1,464 @@
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+
+use clap::Parser;
+use constellation_model::json::{OutputConfigJ, OutputWindowJ};
+use constellation_model::Converted;
+use log::LevelFilter;
+use serde::Deserialize;
+use tonic::transport::{Certificate, Identity, ServerTlsConfig};
+
+use crate::auth::Role;
+use crate::proto::OutputConfig;
+use crate::render::{RenderConfig, Vsync};
+
+/// Command line. Options given here override the config file, also on reload.
+#[derive(Parser, Debug)]
+#[command(name = "constellation-display", about = "Constellation Display server", version)]
+pub struct Cli {
+    /// TOML config file; re-read on SIGHUP
+    #[arg(short, long)]
+    pub config: Option<PathBuf>,
+    /// Address to serve gRPC on [default: 0.0.0.0]
+    #[arg(long)]
+    pub address: Option<String>,
+    /// gRPC port [default: 50051]
+    #[arg(short, long)]
+    pub port: Option<u16>,
+    /// Serve Prometheus metrics over HTTP on this port, at /metrics [default: off]
+    #[arg(long)]
+    pub metrics_port: Option<u16>,
+    /// Preview window title
+    #[arg(long)]
+    pub title: Option<String>,
+    /// Preview window width (or offscreen target width when headless)
+    #[arg(long)]
+    pub width: Option<u32>,
+    /// Preview window height
+    #[arg(long)]
+    pub height: Option<u32>,
+    /// Open the preview window fullscreen
+    #[arg(long)]
+    pub fullscreen: bool,
+    /// Monitor for the preview window, as reported by the OS [default: primary]
+    #[arg(long)]
+    pub monitor: Option<String>,
+    #[arg(long, value_enum)]
+    pub vsync: Option<Vsync>,
+    /// Preview background as r,g,b[,a] in 0..1
+    #[arg(long, value_parser = parse_color)]
+    pub clear_color: Option<[f64; 4]>,
+    /// Directory relative clip and mesh URIs are resolved against; repeat for several, searched in order
+    #[arg(long = "media-root")]
+    pub media_roots: Vec<PathBuf>,
+    /// off | error | warn | info | debug | trace
+    #[arg(long)]
+    pub log_level: Option<LevelFilter>,
+    /// Render offscreen without opening any window
+    #[arg(long)]
+    pub headless: bool,
+    /// Media texture memory budget
+    #[arg(long, env = "CONSTELLATION_TEXTURE_BUDGET_MB")]
+    pub texture_budget_mb: Option<u64>,
+    /// PEM certificate chain; serves gRPC over TLS together with --tls-key
+    #[arg(long)]
+    pub tls_cert: Option<PathBuf>,
+    /// PEM private key for --tls-cert
+    #[arg(long)]
+    pub tls_key: Option<PathBuf>,
+    /// PEM CA bundle client certificates are verified against (mutual TLS)
+    #[arg(long)]
+    pub tls_client_ca: Option<PathBuf>,
+    /// Bearer token granting the control role, in addition to those in the config file
+    #[arg(long, env = "CONSTELLATION_CONTROL_TOKEN", hide_env_values = true)]
+    pub control_token: Option<String>,
+    /// Bearer token granting the read-only role
+    #[arg(long, env = "CONSTELLATION_READ_TOKEN", hide_env_values = true)]
+    pub read_token: Option<String>,
+    /// Role of callers without credentials when authentication is on [default: rejected]
+    #[arg(long, value_enum)]
+    pub anonymous: Option<Role>,
+}
+
+fn parse_color(s: &str) -> Result<[f64; 4], String> {
+    let parts: Vec<f64> = s.split(',').map(|p| p.trim().parse::<f64>().map_err(|e| format!("{p}: {e}"))).collect::<Result<_, _>>()?;
+    match parts[..] {
+        [r, g, b] => Ok([r, g, b, 1.0]),
+        [r, g, b, a] => Ok([r, g, b, a]),
+        _ => Err("expected r,g,b or r,g,b,a".into()),
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub address: String,
+    pub port: u16,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self { address: "0.0.0.0".into(), port: 50051 }
+    }
+}
+
+/// Prometheus endpoint; port 0 turns it off.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetricsConfig {
+    pub address: String,
+    pub port: u16,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self { address: "0.0.0.0".into(), port: 0 }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct WindowConfig {
+    pub title: String,
+    pub width: u32,
+    pub height: u32,
+    pub fullscreen: bool,
+    pub monitor: String,
+    pub vsync: Vsync,
+    pub headless: bool,
+}
+
+impl Default for WindowConfig {
+    fn default() -> Self {
+        let r = RenderConfig::default();
+        Self { title: r.title, width: r.width, height: r.height, fullscreen: false, monitor: String::new(), vsync: r.vsync, headless: false }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct RenderSection {
+    pub clear_color: [f64; 4],
+    pub texture_budget_mb: u64,
+}
+
+impl Default for RenderSection {
+    fn default() -> Self {
+        let r = RenderConfig::default();
+        Self { clear_color: r.clear_color, texture_budget_mb: r.texture_budget_bytes / (1024 * 1024) }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct MediaConfig {
+    pub roots: Vec<PathBuf>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    pub level: String,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self { level: "info".into() }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct TlsConfig {
+    pub cert: Option<PathBuf>,
+    pub key: Option<PathBuf>,
+    pub client_ca: Option<PathBuf>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct TokenT {
+    pub token: String,
+    pub role: Role,
+}
+
+/// A client certificate, matched by its subject common name.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ClientT {
+    pub name: String,
+    pub role: Role,
+}
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    pub tokens: Vec<TokenT>,
+    pub clients: Vec<ClientT>,
+    pub anonymous: Option<Role>,
+}
+
+/// Display settings: built-in defaults, overridden by the config file, overridden
+/// by the command line.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub tls: TlsConfig,
+    pub auth: AuthConfig,
+    pub metrics: MetricsConfig,
+    pub window: WindowConfig,
+    pub render: RenderSection,
+    pub media: MediaConfig,
+    pub log: LogConfig,
+    /// Same shape as the `outputs` JSON
+    pub outputs: Vec<OutputWindowJ>,
+}
+
+impl Config {
+    /// Read `cli.config` (if any) and apply the command line on top.
+    pub fn load(cli: &Cli) -> Result<Self, String> {
+        let mut cfg = match &cli.config {
+            Some(path) => Self::read(path)?,
+            None => Self::default(),
+        };
+        if let Some(v) = &cli.address {
+            cfg.server.address = v.clone();
+        }
+        if let Some(v) = cli.port {
+            cfg.server.port = v;
+        }
+        if let Some(v) = cli.metrics_port {
+            cfg.metrics.port = v;
+        }
+        if let Some(v) = &cli.title {
+            cfg.window.title = v.clone();
+        }
+        if let Some(v) = cli.width {
+            cfg.window.width = v;
+        }
+        if let Some(v) = cli.height {
+            cfg.window.height = v;
+        }
+        cfg.window.fullscreen |= cli.fullscreen;
+        if let Some(v) = &cli.monitor {
+            cfg.window.monitor = v.clone();
+        }
+        if let Some(v) = cli.vsync {
+            cfg.window.vsync = v;
+        }
+        cfg.window.headless |= cli.headless;
+        if let Some(v) = cli.clear_color {
+            cfg.render.clear_color = v;
+        }
+        if let Some(v) = cli.texture_budget_mb {
+            cfg.render.texture_budget_mb = v;
+        }
+        if !cli.media_roots.is_empty() {
+            cfg.media.roots = cli.media_roots.clone();
+        }
+        if let Some(v) = cli.log_level {
+            cfg.log.level = v.to_string();
+        }
+        if let Some(v) = &cli.tls_cert {
+            cfg.tls.cert = Some(v.clone());
+        }
+        if let Some(v) = &cli.tls_key {
+            cfg.tls.key = Some(v.clone());
+        }
+        if let Some(v) = &cli.tls_client_ca {
+            cfg.tls.client_ca = Some(v.clone());
+        }
+        if let Some(v) = &cli.control_token {
+            cfg.auth.tokens.push(TokenT { token: v.clone(), role: Role::Control });
+        }
+        if let Some(v) = &cli.read_token {
+            cfg.auth.tokens.push(TokenT { token: v.clone(), role: Role::Read });
+        }
+        if let Some(v) = cli.anonymous {
+            cfg.auth.anonymous = Some(v);
+        }
+        // Surface bad values now rather than when they are used
+        cfg.bind_addr()?;
+        cfg.metrics_addr()?;
+        cfg.log_level()?;
+        cfg.check_security()?;
+        Ok(cfg)
+    }
+
+    fn check_security(&self) -> Result<(), String> {
+        if self.tls.cert.is_some() != self.tls.key.is_some() {
+            return Err("tls.cert and tls.key must be given together".into());
+        }
+        if self.tls.client_ca.is_some() && self.tls.cert.is_none() {
+            return Err("tls.client_ca needs tls.cert and tls.key".into());
+        }
+        if !self.auth.clients.is_empty() && self.tls.client_ca.is_none() {
+            return Err("auth.clients needs tls.client_ca to verify client certificates".into());
+        }
+        if self.auth.tokens.iter().any(|t| t.token.trim().is_empty()) {
+            return Err("auth.tokens: empty token".into());
+        }
+        Ok(())
+    }
+
+    /// The server's TLS settings, reading the PEM files; `None` serves plaintext.
+    pub fn tls_config(&self) -> Result<Option<ServerTlsConfig>, String> {
+        let (Some(cert), Some(key)) = (&self.tls.cert, &self.tls.key) else {
+            return Ok(None);
+        };
+        let read = |path: &PathBuf| std::fs::read(path).map_err(|e| format!("{}: {e}", path.display()));
+        let mut tls = ServerTlsConfig::new().identity(Identity::from_pem(read(cert)?, read(key)?));
+        if let Some(ca) = &self.tls.client_ca {
+            // Callers that can authenticate with a token may come without a certificate
+            let optional = !self.auth.tokens.is_empty() || self.auth.anonymous.is_some();
+            tls = tls.client_ca_root(Certificate::from_pem(read(ca)?)).client_auth_optional(optional);
+        }
+        Ok(Some(tls))
+    }
+
+    fn read(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        toml::from_str(&text).map_err(|e| format!("{}: {e}", path.display()))
+    }
+
+    pub fn bind_addr(&self) -> Result<SocketAddr, String> {
+        socket_addr(&self.server.address, self.server.port)
+    }
+
+    /// Where to serve Prometheus metrics, if enabled.
+    pub fn metrics_addr(&self) -> Result<Option<SocketAddr>, String> {
+        if self.metrics.port == 0 {
+            return Ok(None);
+        }
+        socket_addr(&self.metrics.address, self.metrics.port).map(Some)
+    }
+
+    pub fn log_level(&self) -> Result<LevelFilter, String> {
+        self.log.level.parse().map_err(|_| format!("invalid log level '{}'", self.log.level))
+    }
+
+    pub fn render_config(&self) -> RenderConfig {
+        RenderConfig {
+            texture_budget_bytes: self.render.texture_budget_mb * 1024 * 1024,
+            headless: self.window.headless,
+            title: self.window.title.clone(),
+            width: self.window.width,
+            height: self.window.height,
+            fullscreen: self.window.fullscreen,
+            monitor: self.window.monitor.clone(),
+            vsync: self.window.vsync,
+            clear_color: self.render.clear_color,
+        }
+    }
+
+    /// The `outputs` windows, converted like the `outputs` JSON: an unknown mode
+    /// is a warning and opens the window `WINDOWED`.
+    pub fn output_config(&self) -> Converted<OutputConfig> {
+        let mut warnings = Vec::new();
+        let value = OutputConfigJ { windows: self.outputs.clone() }.into_proto(&mut warnings);
+        Converted { value, warnings }
+    }
+}
+
+fn socket_addr(address: &str, port: u16) -> Result<SocketAddr, String> {
+    // Bracket bare IPv6 addresses
+    let s = if address.contains(':') && !address.starts_with('[') { format!("[{address}]:{port}") } else { format!("{address}:{port}") };
+    s.parse().map_err(|_| format!("invalid bind address {s}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::output_window::Mode;
+
+    const FILE: &str = r#"
+        [server]
+        port = 6000
+
+        [window]
+        title = "Stage"
+        width = 800
+        vsync = "off"
+
+        [render]
+        clear_color = [0.1, 0.2, 0.3, 1.0]
+
+        [auth]
+        tokens = [{ token = "t", role = "read" }]
+        anonymous = "read"
+
+        [[outputs]]
+        id = "left"
+        mode = "BORDERLESS"
+        x = 1920
+        screens = [{ node_id = "screen-a", x = 10 }]
+    "#;
+
+    fn write(name: &str, text: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("constellation-config-{name}-{}.toml", std::process::id()));
+        std::fs::write(&path, text).unwrap();
+        path
+    }
+
+    fn load(name: &str, text: &str, args: &[&str]) -> Result<Config, String> {
+        let path = write(name, text);
+        let cli = Cli::parse_from(["constellation-display", "--config", path.to_str().unwrap()].iter().chain(args));
+        let cfg = Config::load(&cli);
+        std::fs::remove_file(&path).unwrap();
+        cfg
+    }
+
+    #[test]
+    fn file_sections_override_the_defaults() {
+        let cfg: Config = toml::from_str(FILE).unwrap();
+        assert_eq!(cfg.server, ServerConfig { address: "0.0.0.0".into(), port: 6000 });
+        assert_eq!((cfg.window.title.as_str(), cfg.window.width, cfg.window.vsync), ("Stage", 800, Vsync::Off));
+        assert_eq!(cfg.window.height, WindowConfig::default().height);
+        assert_eq!(cfg.render.clear_color, [0.1, 0.2, 0.3, 1.0]);
+        assert_eq!(cfg.auth.tokens, vec![TokenT { token: "t".into(), role: Role::Read }]);
+        assert_eq!(cfg.auth.anonymous, Some(Role::Read));
+
+        let outputs = cfg.output_config();
+        assert!(outputs.warnings.is_empty());
+        let w = &outputs.value.windows[0];
+        assert_eq!((w.id.as_str(), w.mode(), w.monitor.as_str(), w.x, w.y), ("left", Mode::Borderless, "", 1920, 0));
+        assert_eq!((w.screens[0].node_id.as_str(), w.screens[0].x, w.screens[0].y), ("screen-a", 10, 0));
+    }
+
+    #[test]
+    fn unknown_settings_are_rejected() {
+        let err = load("unknown", "[window]\ntitel = \"x\"\n", &[]).unwrap_err();
+        assert!(err.contains("titel"), "{err}");
+    }
+
+    #[test]
+    fn command_line_overrides_the_file() {
+        let cfg = load("cli", FILE, &["--port", "7000", "--height", "300", "--headless", "--control-token", "c"]).unwrap();
+        assert_eq!(cfg.server.port, 7000);
+        assert_eq!((cfg.window.width, cfg.window.height, cfg.window.headless), (800, 300, true));
+        assert_eq!(cfg.window.title, "Stage");
+        // Command line tokens add to the file's
+        assert_eq!(cfg.auth.tokens.iter().map(|t| (t.token.as_str(), t.role)).collect::<Vec<_>>(), [("t", Role::Read), ("c", Role::Control)]);
+    }
+
+    #[test]
+    fn bad_values_fail_the_load() {
+        let err = load("level", "[log]\nlevel = \"loud\"\n", &[]).unwrap_err();
+        assert_eq!(err, "invalid log level 'loud'");
+        let err = load("tls", "[tls]\ncert = \"cert.pem\"\n", &[]).unwrap_err();
+        assert_eq!(err, "tls.cert and tls.key must be given together");
+    }
+
+    #[test]
+    fn output_modes_follow_the_outputs_json() {
+        let mode = |m: &str| {
+            let cfg: Config = toml::from_str(&format!("[[outputs]]\nid = \"w\"\n{m}\nscreens = []\n")).unwrap();
+            let outputs = cfg.output_config();
+            (outputs.value.windows[0].mode(), outputs.warnings)
+        };
+        assert_eq!(mode(""), (Mode::Windowed, vec![]));
+        assert_eq!(mode("mode = \"FULLSCREEN\""), (Mode::Fullscreen, vec![]));
+        assert_eq!(mode("mode = \"MAXIMIZED\""), (Mode::Windowed, vec!["output 'w': unknown mode 'MAXIMIZED', using WINDOWED".to_string()]));
+    }
+}
@@ -0,0 +1,170 @@
+use std::sync::Arc;
+
+use crate::proto::TimelineEvent;
+use crate::state::AppState;
+
+/// Callback for a `TimelineEvent.action`. Errors are logged, never fatal.
+pub type EventHandler = Arc<dyn Fn(&AppState, &TimelineEvent) -> Result<(), String> + Send + Sync>;
+
+/// How far through the active timeline events have been dispatched.
+///
+/// Events fire once when forward playback crosses their `t`. A transport
+/// discontinuity (seek, stop, play-at, timeline switch, project load) bumps the
+/// transport epoch; the cursor then re-anchors at the landing time without firing
+/// what was jumped over, but events exactly at the landing time still fire.
+pub struct EventCursor {
+    epoch: u64,
+    from: f64,
+    inclusive: bool,
+}
+
+impl EventCursor {
+    pub fn new() -> Self {
+        Self { epoch: 0, from: 0.0, inclusive: true }
+    }
+
+    /// Re-anchor at `anchor` (inclusive) if the transport epoch changed since the last call.
+    pub fn sync(&mut self, epoch: u64, anchor: f64) {
+        if epoch != self.epoch {
+            self.epoch = epoch;
+            self.rewind_to(anchor);
+        }
+    }
+
+    /// Continue from `t` (inclusive), e.g. after a loop wrap.
+    pub fn rewind_to(&mut self, t: f64) {
+        self.from = t;
+        self.inclusive = true;
+    }
+
+    /// Indices into `events` crossed moving to `now`, ordered by time.
+    pub fn advance(&mut self, events: &[TimelineEvent], now: f64, playing: bool) -> Vec<usize> {
+        if !playing {
+            return Vec::new();
+        }
+        if now < self.from {
+            // Reverse playback: follow the playhead so the next forward crossing fires again.
+            self.from = now;
+            self.inclusive = false;
+            return Vec::new();
+        }
+        let mut fired: Vec<usize> = events
+            .iter()
+            .enumerate()
+            .filter(|(_, e)| (e.t > self.from || (self.inclusive && e.t == self.from)) && e.t <= now)
+            .map(|(i, _)| i)
+            .collect();
+        fired.sort_by(|&a, &b| events[a].t.total_cmp(&events[b].t));
+        self.from = now;
+        self.inclusive = false;
+        fired
+    }
+}
+
+fn param_f64(e: &TimelineEvent, key: &str) -> Result<f64, String> {
+    let v = e.params.get(key).ok_or_else(|| format!("missing param '{key}'"))?;
+    v.parse().map_err(|_| format!("param '{key}' is not a number: {v}"))
+}
+
+/// Register the built-in actions: `play` (optional `at`), `pause`, `seek` (`to`),
+/// `set_rate` (`rate`) and `set_opacity` (`node`, `value` in 0..1).
+pub fn register_builtins(app: &AppState) {
+    app.register_event_handler("play", |app, e| {
+        let at = if e.params.contains_key("at") { Some(param_f64(e, "at")?) } else { None };
+        app.play(at);
+        Ok(())
+    });
+    app.register_event_handler("pause", |app, _| {
+        app.pause();
+        Ok(())
+    });
+    app.register_event_handler("seek", |app, e| {
+        app.seek(param_f64(e, "to")?);
+        Ok(())
+    });
+    app.register_event_handler("set_rate", |app, e| {
+        app.set_rate(param_f64(e, "rate")?);
+        Ok(())
+    });
+    app.register_event_handler("set_opacity", |app, e| {
+        let node = e.params.get("node").ok_or("missing param 'node'")?;
+        let value = param_f64(e, "value")?;
+        app.set_node_opacity(node, value.clamp(0.0, 1.0) as f32);
+        Ok(())
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NONE: [f64; 0] = [];
+
+    /// Events at 1s, 2s and 3s, listed out of order.
+    fn events() -> Vec<TimelineEvent> {
+        [3.0, 1.0, 2.0].into_iter().map(|t| TimelineEvent { t, action: format!("at{t}"), ..Default::default() }).collect()
+    }
+
+    /// Times of the events fired moving the cursor to `now` while playing.
+    fn fire(cursor: &mut EventCursor, events: &[TimelineEvent], now: f64) -> Vec<f64> {
+        cursor.advance(events, now, true).into_iter().map(|i| events[i].t).collect()
+    }
+
+    #[test]
+    fn fires_each_crossing_once_in_time_order() {
+        let (ev, mut c) = (events(), EventCursor::new());
+        assert_eq!(fire(&mut c, &ev, 0.5), NONE);
+        assert_eq!(fire(&mut c, &ev, 2.0), [1.0, 2.0]);
+        assert_eq!(fire(&mut c, &ev, 2.0), NONE);
+        assert_eq!(fire(&mut c, &ev, 9.0), [3.0]);
+    }
+
+    #[test]
+    fn pause_and_resume_do_not_fire_again() {
+        let (ev, mut c) = (events(), EventCursor::new());
+        assert_eq!(fire(&mut c, &ev, 1.0), [1.0]);
+        // Paused: time stands still and nothing fires, even past events
+        assert!(c.advance(&ev, 1.0, false).is_empty());
+        assert!(c.advance(&ev, 2.5, false).is_empty());
+        // Resumed where it paused
+        assert_eq!(fire(&mut c, &ev, 1.0), NONE);
+        assert_eq!(fire(&mut c, &ev, 1.5), NONE);
+        assert_eq!(fire(&mut c, &ev, 2.5), [2.0]);
+    }
+
+    #[test]
+    fn seek_skips_what_it_jumps_over() {
+        let (ev, mut c) = (events(), EventCursor::new());
+        c.sync(0, 0.0);
+        assert_eq!(fire(&mut c, &ev, 0.5), NONE);
+        c.sync(1, 2.5);
+        assert_eq!(fire(&mut c, &ev, 2.6), NONE);
+        assert_eq!(fire(&mut c, &ev, 3.0), [3.0]);
+        // Seeking back doesn't replay either, but landing on an event fires it once
+        c.sync(2, 2.0);
+        assert_eq!(fire(&mut c, &ev, 2.0), [2.0]);
+        c.sync(2, 2.0);
+        assert_eq!(fire(&mut c, &ev, 2.2), NONE);
+    }
+
+    #[test]
+    fn events_fire_again_after_a_wrap() {
+        let (ev, mut c) = (events(), EventCursor::new());
+        assert_eq!(fire(&mut c, &ev, 3.5), [1.0, 2.0, 3.0]);
+        // Loop back to the region start as `tick` does on a wrap
+        c.rewind_to(1.0);
+        assert_eq!(fire(&mut c, &ev, 1.5), [1.0]);
+        assert_eq!(fire(&mut c, &ev, 3.5), [2.0, 3.0]);
+    }
+
+    #[test]
+    fn reverse_playback_fires_nothing_and_rearms_forward_crossings() {
+        let (ev, mut c) = (events(), EventCursor::new());
+        assert_eq!(fire(&mut c, &ev, 2.5), [1.0, 2.0]);
+        assert_eq!(fire(&mut c, &ev, 1.5), NONE);
+        assert_eq!(fire(&mut c, &ev, 0.5), NONE);
+        // Forward again: crossings behind the turn fire again
+        assert_eq!(fire(&mut c, &ev, 1.5), [1.0]);
+        assert_eq!(fire(&mut c, &ev, 2.5), [2.0]);
+    }
+}
@@ -0,0 +1,209 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use log::error;
+use tokio::sync::mpsc;
+
+use crate::capture::encode_png;
+#[cfg(feature = "video")]
+use crate::media::encode::VideoWriter;
+use crate::proto::{ExportProgress, ExportRequest};
+use crate::render::{FrameRenderer, Gpu, RenderConfig};
+use crate::state::AppState;
+
+const DEFAULT_FPS: f64 = 30.0;
+const DEFAULT_PREVIEW_SIZE: (u32, u32) = (1920, 1080);
+
+/// One image sequence being written: a screen canvas, or the 3D preview.
+struct Target {
+    /// Screen node id; `None` for the preview
+    screen: Option<String>,
+    size: (u32, u32),
+    dir: PathBuf,
+    #[cfg(feature = "video")]
+    video: Option<VideoWriter>,
+}
+
+impl Target {
+    fn open(out_dir: &Path, name: &str, screen: Option<String>, size: (u32, u32), req: &ExportRequest, fps: f64) -> Result<Self, String> {
+        let dir = out_dir.join(name);
+        std::fs::create_dir_all(&dir).map_err(|e| format!("{}: {e}", dir.display()))?;
+        #[cfg(feature = "video")]
+        let video = if req.encode_video {
+            Some(VideoWriter::create(&out_dir.join(format!("{name}.mp4")), size.0, size.1, fps)?)
+        } else {
+            None
+        };
+        #[cfg(not(feature = "video"))]
+        let _ = (req, fps);
+        Ok(Self {
+            screen,
+            size,
+            dir,
+            #[cfg(feature = "video")]
+            video,
+        })
+    }
+
+    fn write(&mut self, index: u32, rgba: Vec<u8>) -> Result<(), String> {
+        #[cfg(feature = "video")]
+        if let Some(v) = self.video.as_mut() {
+            v.write(&rgba)?;
+        }
+        let png = encode_png(rgba, self.size.0, self.size.1)?;
+        let path = self.dir.join(format!("frame_{index:05}.png"));
+        std::fs::write(&path, png).map_err(|e| format!("{}: {e}", path.display()))
+    }
+
+    fn finish(self) -> Result<(), String> {
+        #[cfg(feature = "video")]
+        if let Some(v) = self.video {
+            v.finish()?;
+        }
+        Ok(())
+    }
+}
+
+/// Node ids become file and directory names: anything but `[A-Za-z0-9_-]` is
+/// replaced with `_`, so `.`, `..` and separators can't leave or hit `out_dir`.
+fn file_name(id: &str) -> String {
+    if id.is_empty() {
+        return "_".into();
+    }
+    id.chars().map(|c| if c.is_ascii_alphanumeric() || c == '_' || c == '-' { c } else { '_' }).collect()
+}
+
+/// Start exporting on a thread of its own, from a snapshot of the project as it is
+/// now, on the render loop's device and settings. Progress is sent per frame; the last message
+/// has `done` set. Dropping the receiver cancels the export.
+pub fn spawn(app: &AppState, req: ExportRequest, progress: mpsc::Sender<ExportProgress>) {
+    let snapshot = app.detached(&req.timeline_id);
+    let gpu = app.gpu();
+    let cfg = app.render_config();
+    let spawned = std::thread::Builder::new().name("export".into()).spawn(move || {
+        let mut last = ExportProgress::default();
+        let result = snapshot.and_then(|(app, duration)| {
+            let gpu = gpu.ok_or("renderer not started")?;
+            run(&app, gpu, &cfg, duration, &req, &progress, &mut last)
+        });
+        let (ok, message) = match result {
+            Ok(m) => (true, m),
+            Err(m) => (false, m),
+        };
+        let _ = progress.blocking_send(ExportProgress { done: true, ok, message, ..last });
+    });
+    if let Err(e) = spawned {
+        error!("export thread not started: {e}");
+    }
+}
+
+fn run(
+    app: &AppState,
+    gpu: Gpu,
+    cfg: &RenderConfig,
+    duration: f64,
+    req: &ExportRequest,
+    progress: &mpsc::Sender<ExportProgress>,
+    last: &mut ExportProgress,
+) -> Result<String, String> {
+    if cfg!(not(feature = "video")) && req.encode_video {
+        return Err("video encoding needs the display built with --features video".into());
+    }
+    if req.out_dir.is_empty() {
+        return Err("missing out_dir".into());
+    }
+    let fps = if req.fps > 0.0 { req.fps } else { DEFAULT_FPS };
+    let t_in = req.in_seconds.max(0.0);
+    let t_out = if req.out_seconds > req.in_seconds { req.out_seconds } else { duration };
+    if t_out <= t_in {
+        return Err(format!("empty range {t_in:.3}s..{t_out:.3}s (timeline without a duration needs out_seconds)"));
+    }
+    // Frames start at `t_in` and stop before `t_out`; the epsilon keeps an exact
+    // multiple of the frame length from adding a frame.
+    let total = ((t_out - t_in) * fps - 1e-9).ceil().max(1.0) as u32;
+
+    let sizes = app.screen_sizes();
+    let screens: Vec<String> = if req.screen_node_ids.is_empty() {
+        let mut all: Vec<String> = sizes.keys().cloned().collect();
+        all.sort();
+        all
+    } else {
+        for id in &req.screen_node_ids {
+            if !sizes.contains_key(id) {
+                return Err(format!("no screen '{id}' with a canvas in the scene"));
+            }
+        }
+        req.screen_node_ids.clone()
+    };
+    if screens.is_empty() && !req.include_preview {
+        return Err("nothing to export: no screen has a canvas and the preview is not included".into());
+    }
+
+    let out_dir = Path::new(&req.out_dir);
+    // Distinct ids may map to the same file name, or to the preview's
+    let mut names = HashSet::new();
+    if req.include_preview {
+        names.insert("preview".to_string());
+    }
+    let mut targets = Vec::new();
+    for id in &screens {
+        let name = file_name(id);
+        if !names.insert(name.clone()) {
+            return Err(format!("screen '{id}' would be written to '{name}', which another sequence already uses"));
+        }
+        targets.push(Target::open(out_dir, &name, Some(id.clone()), sizes[id], req, fps)?);
+    }
+    if req.include_preview {
+        let size = if req.preview_width > 0 && req.preview_height > 0 {
+            (req.preview_width, req.preview_height)
+        } else {
+            DEFAULT_PREVIEW_SIZE
+        };
+        targets.push(Target::open(out_dir, "preview", None, size, req, fps)?);
+    }
+
+    let mut renderer = FrameRenderer::offline(gpu, cfg);
+    for i in 0..total {
+        let t = t_in + i as f64 / fps;
+        let resolved = renderer.resolve_offline(app, t);
+        let frames = resolved.screen_frames();
+        for target in &mut targets {
+            let rgba = match &target.screen {
+                Some(id) => renderer.read_canvas(id, target.size, &sizes, &frames)?,
+                None => renderer.read_preview(app, t, &resolved, target.size)?,
+            };
+            target.write(i, rgba)?;
+        }
+        *last = ExportProgress { frame: i + 1, total_frames: total, time_seconds: t, ..Default::default() };
+        if progress.blocking_send(last.clone()).is_err() {
+            return Err("export cancelled".into());
+        }
+    }
+    let count = targets.len();
+    for target in targets {
+        target.finish()?;
+    }
+    Ok(format!("exported {total} frames of {count} sequence(s) to {}", out_dir.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::file_name;
+
+    #[test]
+    fn hostile_ids_stay_inside_out_dir() {
+        for (id, name) in [
+            ("..", "__"),
+            (".", "_"),
+            ("", "_"),
+            ("../../etc/passwd", "______etc_passwd"),
+            ("C:\\Windows", "C__Windows"),
+            ("/abs", "_abs"),
+            ("wall left\n", "wall_left_"),
+            ("écran", "_cran"),
+            ("wall_left-2", "wall_left-2"),
+        ] {
+            assert_eq!(file_name(id), name, "{id:?}");
+        }
+    }
+}
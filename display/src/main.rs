@@ -1,16 +1,32 @@
 use std::net::SocketAddr;
+use std::sync::{mpsc, RwLock};
 use std::thread;
-use tonic::{transport::Server, Request, Response, Status};
+use std::time::Duration;
+use clap::Parser;
+use log::{error, info, warn, LevelFilter};
+use tonic::{server::NamedService, transport::{Server, ServerTlsConfig}, Request, Response, Status};
+use tonic_health::server::HealthReporter;
+use tonic_health::ServingStatus;
 
+mod auth;
+mod capture;
+mod config;
+mod events;
+mod export;
+mod media;
+mod mesh;
+mod metrics;
+mod output;
 mod render;
 mod scene;
 mod state;
-use render::Renderer;
+mod validate;
+use auth::{Auth, Role};
+use config::{Cli, Config};
+use render::{RenderConfig, Renderer};
 use state::AppState;
 
-pub mod proto {
-    tonic::include_proto!("constellation.v1");
-}
+pub use constellation_model::proto;
 
 use proto::display_control_server::{DisplayControl, DisplayControlServer};
 use futures_core::Stream;
@@ -18,6 +34,9 @@ use std::pin::Pin;
 use async_stream::stream;
 use proto::*;
 
+const DEFAULT_SUBSCRIBE_HZ: f64 = 30.0;
+const MAX_SUBSCRIBE_HZ: f64 = 240.0;
+
 struct DisplaySvc {
     app: AppState,
 }
@@ -25,80 +44,342 @@ struct DisplaySvc {
 #[tonic::async_trait]
 impl DisplayControl for DisplaySvc {
     async fn load_project(&self, req: Request<LoadProjectRequest>) -> Result<Response<Ack>, Status> {
-        if let Some(project) = &req.get_ref().project {
-            self.app.set_project(project);
-            Ok(Response::new(Ack { ok: true, message: "project loaded".into() }))
+        auth::require(&req, Role::Control)?;
+        if let Some(mut project) = req.into_inner().project {
+            project.upgrade_components();
+            let diagnostics = self.app.set_project(&project);
+            let ok = !validate::has_errors(&diagnostics);
+            Ok(Response::new(Ack { ok, message: validate::summary("project", &diagnostics), diagnostics }))
+        } else {
+            Ok(Response::new(Ack { ok: false, message: "missing project".into(), ..Default::default() }))
+        }
+    }
+    async fn load_scene(&self, req: Request<LoadSceneRequest>) -> Result<Response<Ack>, Status> {
+        auth::require(&req, Role::Control)?;
+        let Some(mut scene) = req.into_inner().scene else {
+            return Ok(Response::new(Ack { ok: false, message: "missing scene".into(), ..Default::default() }));
+        };
+        scene.upgrade_components();
+        match self.app.set_scene(&scene) {
+            None => Ok(Response::new(Ack { ok: false, message: "no project loaded".into(), ..Default::default() })),
+            Some(diagnostics) => {
+                let ok = !validate::has_errors(&diagnostics);
+                Ok(Response::new(Ack { ok, message: validate::summary("scene", &diagnostics), diagnostics }))
+            }
+        }
+    }
+    async fn activate_timeline(&self, req: Request<ActivateTimelineRequest>) -> Result<Response<Ack>, Status> {
+        auth::require(&req, Role::Control)?;
+        let r = req.get_ref();
+        if self.app.activate_timeline(&r.timeline_id, r.keep_position) {
+            Ok(Response::new(Ack { ok: true, message: "timeline activated".into(), ..Default::default() }))
         } else {
-            Ok(Response::new(Ack { ok: false, message: "missing project".into() }))
+            Ok(Response::new(Ack { ok: false, message: format!("unknown timeline: {}", r.timeline_id), ..Default::default() }))
+        }
+    }
+    async fn select_camera(&self, req: Request<SelectCameraRequest>) -> Result<Response<Ack>, Status> {
+        auth::require(&req, Role::Control)?;
+        let id = &req.get_ref().node_id;
+        match self.app.select_camera(id) {
+            Ok(()) if id.is_empty() => Ok(Response::new(Ack { ok: true, message: "default camera".into(), ..Default::default() })),
+            Ok(()) => Ok(Response::new(Ack { ok: true, message: format!("camera {id}"), ..Default::default() })),
+            Err(message) => Ok(Response::new(Ack { ok: false, message, ..Default::default() })),
         }
     }
-    async fn load_scene(&self, _req: Request<LoadSceneRequest>) -> Result<Response<Ack>, Status> {
-        Ok(Response::new(Ack { ok: true, message: "scene loaded".into() }))
+    async fn set_outputs(&self, req: Request<SetOutputsRequest>) -> Result<Response<Ack>, Status> {
+        auth::require(&req, Role::Control)?;
+        let Some(cfg) = &req.get_ref().config else {
+            return Ok(Response::new(Ack { ok: false, message: "missing output config".into(), ..Default::default() }));
+        };
+        let diagnostics = self.app.set_outputs(cfg);
+        let ok = !validate::has_errors(&diagnostics);
+        Ok(Response::new(Ack { ok, message: validate::summary("outputs", &diagnostics), diagnostics }))
+    }
+    async fn capture_frame(&self, req: Request<CaptureFrameRequest>) -> Result<Response<CaptureFrameResponse>, Status> {
+        // Rendering another point in time costs a renderer of its own, unlike watching
+        auth::require(&req, if req.get_ref().time_seconds.is_some() { Role::Control } else { Role::Read })?;
+        let rx = self.app.request_capture(req.into_inner());
+        match tokio::time::timeout(capture::CAPTURE_WAIT + Duration::from_secs(5), rx).await {
+            Ok(Ok(resp)) => Ok(Response::new(resp)),
+            _ => Ok(Response::new(CaptureFrameResponse { ok: false, message: "render loop did not answer".into(), ..Default::default() })),
+        }
     }
-    async fn activate_timeline(&self, _req: Request<ActivateTimelineRequest>) -> Result<Response<Ack>, Status> {
-        Ok(Response::new(Ack { ok: true, message: "timeline activated".into() }))
+    type ExportStream = Pin<Box<dyn Stream<Item = Result<ExportProgress, Status>> + Send + 'static>>;
+    async fn export(&self, req: Request<ExportRequest>) -> Result<Response<Self::ExportStream>, Status> {
+        auth::require(&req, Role::Control)?;
+        let (tx, mut rx) = tokio::sync::mpsc::channel(16);
+        export::spawn(&self.app, req.into_inner(), tx);
+        let s = stream! {
+            while let Some(p) = rx.recv().await {
+                yield Ok(p);
+            }
+        };
+        Ok(Response::new(Box::pin(s) as Self::ExportStream))
     }
     async fn play(&self, req: Request<PlayRequest>) -> Result<Response<Ack>, Status> {
+        auth::require(&req, Role::Control)?;
         let at = if req.get_ref().at_seconds > 0.0 { Some(req.get_ref().at_seconds) } else { None };
         self.app.play(at);
-        Ok(Response::new(Ack { ok: true, message: "play".into() }))
+        Ok(Response::new(Ack { ok: true, message: "play".into(), ..Default::default() }))
     }
-    async fn pause(&self, _req: Request<PauseRequest>) -> Result<Response<Ack>, Status> {
+    async fn pause(&self, req: Request<PauseRequest>) -> Result<Response<Ack>, Status> {
+        auth::require(&req, Role::Control)?;
         self.app.pause();
-        Ok(Response::new(Ack { ok: true, message: "pause".into() }))
+        Ok(Response::new(Ack { ok: true, message: "pause".into(), ..Default::default() }))
     }
-    async fn stop(&self, _req: Request<StopRequest>) -> Result<Response<Ack>, Status> {
+    async fn stop(&self, req: Request<StopRequest>) -> Result<Response<Ack>, Status> {
+        auth::require(&req, Role::Control)?;
         self.app.stop();
-        Ok(Response::new(Ack { ok: true, message: "stop".into() }))
+        Ok(Response::new(Ack { ok: true, message: "stop".into(), ..Default::default() }))
     }
     async fn seek(&self, req: Request<SeekRequest>) -> Result<Response<Ack>, Status> {
+        auth::require(&req, Role::Control)?;
         self.app.seek(req.get_ref().to_seconds);
-        Ok(Response::new(Ack { ok: true, message: "seek".into() }))
+        Ok(Response::new(Ack { ok: true, message: "seek".into(), ..Default::default() }))
     }
     async fn set_rate(&self, req: Request<SetRateRequest>) -> Result<Response<Ack>, Status> {
+        auth::require(&req, Role::Control)?;
         self.app.set_rate(req.get_ref().rate);
-        Ok(Response::new(Ack { ok: true, message: "rate set".into() }))
+        Ok(Response::new(Ack { ok: true, message: "rate set".into(), ..Default::default() }))
+    }
+    async fn set_end_behavior(&self, req: Request<SetEndBehaviorRequest>) -> Result<Response<Ack>, Status> {
+        auth::require(&req, Role::Control)?;
+        let behavior = req.get_ref().behavior();
+        self.app.set_end_behavior(behavior);
+        Ok(Response::new(Ack { ok: true, message: format!("end behavior {behavior:?}"), ..Default::default() }))
+    }
+    async fn set_loop_region(&self, req: Request<SetLoopRegionRequest>) -> Result<Response<Ack>, Status> {
+        auth::require(&req, Role::Control)?;
+        let r = req.get_ref();
+        self.app.set_loop_region(r.in_seconds, r.out_seconds);
+        let message = if r.out_seconds > r.in_seconds { "loop region set" } else { "loop region cleared" };
+        Ok(Response::new(Ack { ok: true, message: message.into(), ..Default::default() }))
     }
     type SubscribeStateStream = Pin<Box<dyn Stream<Item = Result<StateUpdate, Status>> + Send + 'static>>;
-    async fn subscribe_state(&self, _req: Request<SubscribeRequest>) -> Result<Response<Self::SubscribeStateStream>, Status> {
-        let mut rx = self.app.watch();
+    async fn subscribe_state(&self, req: Request<SubscribeRequest>) -> Result<Response<Self::SubscribeStateStream>, Status> {
+        auth::require(&req, Role::Read)?;
+        let r = req.into_inner();
+        let rate = if r.rate_hz > 0.0 { r.rate_hz.min(MAX_SUBSCRIBE_HZ) } else { DEFAULT_SUBSCRIBE_HZ };
+        let period = Duration::from_secs_f64(1.0 / rate);
+        let app = self.app.clone();
+        let mut rx = app.watch();
         let s = stream! {
+            let mut last: Option<StateUpdate> = None;
+            let mut sent_at = tokio::time::Instant::now();
             loop {
-                // wait for change
-                let _ = rx.changed().await;
-                let v = rx.borrow().clone();
-                yield Ok(v);
+                if let Some(prev) = &last {
+                    let playing = prev.transport.as_ref().is_some_and(|t| t.status() == transport_state::Status::Playing);
+                    // Wait for a change, or for the next tick while the clock runs
+                    if playing {
+                        tokio::select! {
+                            changed = rx.changed() => if changed.is_err() { break },
+                            _ = tokio::time::sleep_until(sent_at + period) => {}
+                        }
+                    } else if rx.changed().await.is_err() {
+                        break;
+                    }
+                    // Coalesce bursts of changes to at most one update per period
+                    tokio::time::sleep_until(sent_at + period).await;
+                }
+                rx.borrow_and_update();
+                let mut update = app.snapshot();
+                if !r.include_metrics {
+                    update.metrics = None;
+                }
+                // Metrics-only changes are nothing new without metrics
+                if last.as_ref() == Some(&update) {
+                    continue;
+                }
+                sent_at = tokio::time::Instant::now();
+                last = Some(update.clone());
+                yield Ok(update);
             }
         };
         Ok(Response::new(Box::pin(s) as Self::SubscribeStateStream))
     }
 }
 
-fn start_rpc_server(addr: SocketAddr, app: AppState) {
+const DISPLAY_CONTROL: &str = <DisplayControlServer<DisplaySvc> as NamedService>::NAME;
+
+async fn set_health(health: &mut HealthReporter, serving: bool) {
+    let status = if serving { ServingStatus::Serving } else { ServingStatus::NotServing };
+    health.set_service_status("", status).await;
+    health.set_service_status(DISPLAY_CONTROL, status).await;
+}
+
+fn start_rpc_server(addr: SocketAddr, tls: Option<ServerTlsConfig>, auth: Auth, metrics_addr: Option<SocketAddr>, app: AppState) {
     thread::spawn(move || {
         let rt = tokio::runtime::Builder::new_multi_thread()
             .enable_all()
             .build()
             .expect("tokio runtime");
         rt.block_on(async move {
-            let svc = DisplaySvc { app };
-            println!("Display gRPC listening on http://{addr}");
-            if let Err(e) = Server::builder()
-                .add_service(DisplayControlServer::new(svc))
+            if let Some(metrics_addr) = metrics_addr {
+                tokio::spawn(metrics::serve(metrics_addr, app.clone()));
+            }
+            // grpc.health.v1: NOT_SERVING until the render loop can present frames, for
+            // both DisplayControl and the server as a whole ("")
+            let (mut health, health_svc) = tonic_health::server::health_reporter();
+            let mut ready = app.watch_ready();
+            set_health(&mut health, *ready.borrow_and_update()).await;
+            tokio::spawn(async move {
+                while ready.changed().await.is_ok() {
+                    let serving = *ready.borrow_and_update();
+                    set_health(&mut health, serving).await;
+                }
+            });
+            let reflection = match tonic_reflection::server::Builder::configure()
+                .register_encoded_file_descriptor_set(proto::FILE_DESCRIPTOR_SET)
+                .register_encoded_file_descriptor_set(tonic_health::pb::FILE_DESCRIPTOR_SET)
+                .build()
+            {
+                Ok(r) => r,
+                Err(e) => {
+                    error!("reflection service: {e}");
+                    return;
+                }
+            };
+
+            let mut builder = Server::builder();
+            let scheme = if tls.is_some() { "https" } else { "http" };
+            if let Some(tls) = tls {
+                builder = match builder.tls_config(tls) {
+                    Ok(b) => b,
+                    Err(e) => {
+                        error!("TLS setup failed: {e}");
+                        return;
+                    }
+                };
+            } else if auth.enabled() {
+                warn!("authentication is on without TLS: tokens travel in plaintext");
+            }
+            // Health and reflection stay open so monitoring needs no credentials
+            let svc = DisplayControlServer::with_interceptor(DisplaySvc { app }, auth);
+            info!("Display gRPC listening on {scheme}://{addr}");
+            if let Err(e) = builder
+                .add_service(health_svc)
+                .add_service(reflection)
+                .add_service(svc)
                 .serve(addr)
                 .await
             {
-                eprintln!("RPC server error: {e}");
+                error!("RPC server error: {e}");
             }
         });
     });
 }
 
+/// The logger behind `ReloadableLogger`; replaced when a reloaded config changes the level.
+static LOGGER: RwLock<Option<env_logger::Logger>> = RwLock::new(None);
+
+struct ReloadableLogger;
+
+impl log::Log for ReloadableLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        LOGGER.read().unwrap().as_ref().is_some_and(|l| l.enabled(metadata))
+    }
+
+    fn log(&self, record: &log::Record) {
+        if let Some(l) = LOGGER.read().unwrap().as_ref() {
+            l.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(l) = LOGGER.read().unwrap().as_ref() {
+            l.flush();
+        }
+    }
+}
+
+/// Log at the configured `level`, with RUST_LOG directives applied on top of it.
+fn set_log_level(level: LevelFilter) {
+    let logger = env_logger::Builder::new()
+        .filter_level(level)
+        // GPU backends are chatty below warn
+        .filter_module("wgpu_core", LevelFilter::Warn)
+        .filter_module("wgpu_hal", LevelFilter::Warn)
+        .filter_module("naga", LevelFilter::Warn)
+        .parse_default_env()
+        .build();
+    log::set_max_level(logger.filter());
+    *LOGGER.write().unwrap() = Some(logger);
+}
+
+fn init_logging(level: LevelFilter) {
+    set_log_level(level);
+    log::set_logger(&ReloadableLogger).expect("logger already set");
+}
+
+fn apply_outputs(app: &AppState, cfg: &Config) {
+    let outputs = cfg.output_config();
+    for w in &outputs.warnings {
+        warn!("outputs: {w}");
+    }
+    let diagnostics = app.set_outputs(&outputs.value);
+    for d in &diagnostics {
+        warn!("outputs: {}", d.message);
+    }
+    if validate::has_errors(&diagnostics) {
+        error!("{}", validate::summary("outputs", &diagnostics));
+    }
+}
+
+/// Re-read the config file on SIGHUP and apply what can change while running;
+/// the command line still takes precedence.
+#[cfg(unix)]
+fn watch_sighup(cli: Cli, mut current: Config, app: AppState, reload: mpsc::Sender<RenderConfig>) -> std::io::Result<()> {
+    let mut signals = signal_hook::iterator::Signals::new([signal_hook::consts::SIGHUP])?;
+    thread::Builder::new().name("sighup".into()).spawn(move || {
+        for _ in signals.forever() {
+            let Some(path) = &cli.config else {
+                warn!("SIGHUP ignored: no --config file to reload");
+                continue;
+            };
+            let new = match Config::load(&cli) {
+                Ok(new) => new,
+                Err(e) => {
+                    error!("config not reloaded: {e}");
+                    continue;
+                }
+            };
+            if new.server != current.server || new.tls != current.tls || new.auth != current.auth || new.metrics != current.metrics
+                || new.window.headless != current.window.headless
+                || new.render.texture_budget_mb != current.render.texture_budget_mb
+            {
+                warn!("bind addresses, TLS, authentication, headless mode and texture budget changes take effect after a restart");
+            }
+            if let Ok(level) = new.log_level() {
+                set_log_level(level);
+            }
+            app.set_media_roots(new.media.roots.clone());
+            if new.outputs != current.outputs {
+                apply_outputs(&app, &new);
+            }
+            let _ = reload.send(new.render_config());
+            info!("reloaded {}", path.display());
+            current = new;
+        }
+    })?;
+    Ok(())
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let addr: SocketAddr = "0.0.0.0:50051".parse()?;
+    let cli = Cli::parse();
+    let cfg = Config::load(&cli)?;
+    init_logging(cfg.log_level()?);
     let (app, _rx) = AppState::new();
-    start_rpc_server(addr, app.clone());
-    // Run renderer on main thread (required by some platforms)
-    Renderer::run(app)?;
+    app.set_media_roots(cfg.media.roots.clone());
+    if !cfg.outputs.is_empty() {
+        apply_outputs(&app, &cfg);
+    }
+    start_rpc_server(cfg.bind_addr()?, cfg.tls_config()?, Auth::new(&cfg.auth), cfg.metrics_addr()?, app.clone());
+    let (reload_tx, reload_rx) = mpsc::channel();
+    #[cfg(unix)]
+    watch_sighup(cli, cfg.clone(), app.clone(), reload_tx)?;
+    #[cfg(not(unix))]
+    drop((cli, reload_tx));
+    // Run renderer on main thread (required by some platforms for windows)
+    Renderer::run(app, cfg.render_config(), reload_rx)?;
     Ok(())
 }
@@ -0,0 +1,94 @@
+use std::path::Path;
+
+use ffmpeg_next as ffmpeg;
+use ffmpeg::format::Pixel;
+use ffmpeg::software::scaling;
+use ffmpeg::util::frame;
+use ffmpeg::{codec, encoder, format, Packet, Rational};
+
+/// Encodes RGBA8 frames at a fixed rate into an H.264 video file.
+pub struct VideoWriter {
+    octx: format::context::Output,
+    encoder: encoder::Video,
+    scaler: scaling::Context,
+    rgba: frame::Video,
+    time_base: Rational,
+    stream_time_base: Rational,
+    next_pts: i64,
+}
+
+impl VideoWriter {
+    /// Create `path` (container chosen by extension) for `width` x `height` frames.
+    /// Odd sizes are scaled down a pixel, as 4:2:0 chroma needs even dimensions.
+    pub fn create(path: &Path, width: u32, height: u32, fps: f64) -> Result<Self, String> {
+        ffmpeg::init().map_err(|e| format!("ffmpeg init failed: {e}"))?;
+        let err = |e: ffmpeg::Error| format!("{}: {e}", path.display());
+        let mut octx = format::output(path).map_err(err)?;
+        let global_header = octx.format().flags().contains(format::Flags::GLOBAL_HEADER);
+        let codec = encoder::find(codec::Id::H264).ok_or("FFmpeg has no H.264 encoder")?;
+        let mut ost = octx.add_stream(codec).map_err(err)?;
+
+        let (out_w, out_h) = ((width & !1).max(2), (height & !1).max(2));
+        let rate = Rational::from(fps);
+        let time_base = rate.invert();
+        let mut enc = codec::context::Context::new_with_codec(codec).encoder().video().map_err(err)?;
+        enc.set_width(out_w);
+        enc.set_height(out_h);
+        enc.set_format(Pixel::YUV420P);
+        enc.set_frame_rate(Some(rate));
+        enc.set_time_base(time_base);
+        if global_header {
+            enc.set_flags(codec::Flags::GLOBAL_HEADER);
+        }
+        let encoder = enc.open().map_err(err)?;
+        ost.set_parameters(&encoder);
+        ost.set_time_base(time_base);
+        octx.write_header().map_err(err)?;
+        let stream_time_base = octx.stream(0).map(|s| s.time_base()).unwrap_or(time_base);
+
+        let scaler = scaling::Context::get(Pixel::RGBA, width, height, Pixel::YUV420P, out_w, out_h, scaling::Flags::BILINEAR)
+            .map_err(err)?;
+        Ok(Self {
+            octx,
+            encoder,
+            scaler,
+            rgba: frame::Video::new(Pixel::RGBA, width, height),
+            time_base,
+            stream_time_base,
+            next_pts: 0,
+        })
+    }
+
+    /// Append one frame of tightly packed RGBA8 pixels.
+    pub fn write(&mut self, pixels: &[u8]) -> Result<(), String> {
+        let row = self.rgba.width() as usize * 4;
+        let stride = self.rgba.stride(0);
+        let data = self.rgba.data_mut(0);
+        for (y, line) in pixels.chunks_exact(row).enumerate() {
+            data[y * stride..y * stride + row].copy_from_slice(line);
+        }
+        let mut yuv = frame::Video::empty();
+        self.scaler.run(&self.rgba, &mut yuv).map_err(|e| e.to_string())?;
+        yuv.set_pts(Some(self.next_pts));
+        self.next_pts += 1;
+        self.encoder.send_frame(&yuv).map_err(|e| e.to_string())?;
+        self.drain()
+    }
+
+    /// Flush the encoder and close the file.
+    pub fn finish(mut self) -> Result<(), String> {
+        self.encoder.send_eof().map_err(|e| e.to_string())?;
+        self.drain()?;
+        self.octx.write_trailer().map_err(|e| e.to_string())
+    }
+
+    fn drain(&mut self) -> Result<(), String> {
+        let mut packet = Packet::empty();
+        while self.encoder.receive_packet(&mut packet).is_ok() {
+            packet.set_stream(0);
+            packet.rescale_ts(self.time_base, self.stream_time_base);
+            packet.write_interleaved(&mut self.octx).map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,62 @@
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+
+use super::{open, FrameSource};
+
+struct Job {
+    clip_id: String,
+    uri: String,
+    fps: f64,
+}
+
+/// Result of opening a clip; `source` is `None` if it could not be decoded.
+pub struct Loaded {
+    pub clip_id: String,
+    /// The URI and fps the clip was opened with, as requested
+    pub uri: String,
+    pub fps: f64,
+    pub source: Option<Box<dyn FrameSource>>,
+}
+
+/// Worker pool that opens clips (decoding stills, scanning sequences, probing
+/// video) off the render thread.
+pub struct Loader {
+    jobs: mpsc::Sender<Job>,
+    results: mpsc::Receiver<Loaded>,
+}
+
+impl Loader {
+    pub fn new(workers: usize) -> Self {
+        let (jobs, job_rx) = mpsc::channel::<Job>();
+        let (result_tx, results) = mpsc::channel();
+        let job_rx = Arc::new(Mutex::new(job_rx));
+        for i in 0..workers.max(1) {
+            let job_rx = job_rx.clone();
+            let result_tx = result_tx.clone();
+            thread::Builder::new()
+                .name(format!("media-loader-{i}"))
+                .spawn(move || loop {
+                    // The lock is only held while waiting for the next job
+                    let job = match job_rx.lock().unwrap().recv() {
+                        Ok(job) => job,
+                        Err(_) => return,
+                    };
+                    let source = open(&job.uri, job.fps);
+                    if result_tx.send(Loaded { clip_id: job.clip_id, uri: job.uri, fps: job.fps, source }).is_err() {
+                        return;
+                    }
+                })
+                .expect("spawn media loader");
+        }
+        Self { jobs, results }
+    }
+
+    pub fn request(&self, clip_id: &str, uri: &str, fps: f64) {
+        let _ = self.jobs.send(Job { clip_id: clip_id.to_string(), uri: uri.to_string(), fps });
+    }
+
+    /// Clips finished since the last poll. Never blocks.
+    pub fn poll(&self) -> Vec<Loaded> {
+        self.results.try_iter().collect()
+    }
+}
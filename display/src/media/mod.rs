@@ -0,0 +1,113 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use log::warn;
+
+mod loader;
+mod sequence;
+#[cfg(feature = "video")]
+mod video;
+#[cfg(feature = "video")]
+pub mod encode;
+
+pub use loader::{Loaded, Loader};
+pub use sequence::is_sequence;
+
+/// A decoded RGBA8 frame, rows tightly packed.
+pub struct Frame {
+    pub pixels: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+    /// Time it took to decode (video: seek to and decode) this frame
+    pub decode_time: Duration,
+}
+
+/// Produces the frames of one media clip as clip-local media time advances.
+pub trait FrameSource: Send {
+    /// The frame to show at media time `t` (seconds into the clip), or `None` if the
+    /// frame handed out previously is still current or nothing is available yet.
+    fn frame_at(&mut self, t: f64) -> Option<Frame>;
+
+    /// Like `frame_at`, but waits until the frame for `t` has been decoded. Used by
+    /// offline rendering, where every frame must show exactly its own media.
+    fn frame_at_blocking(&mut self, t: f64) -> Option<Frame> {
+        self.frame_at(t)
+    }
+}
+
+/// A still image: yields its single frame once.
+struct StillSource {
+    frame: Option<Frame>,
+}
+
+impl FrameSource for StillSource {
+    fn frame_at(&mut self, _t: f64) -> Option<Frame> {
+        self.frame.take()
+    }
+}
+
+pub fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    // Expect file:// URIs
+    if let Ok(u) = url::Url::parse(uri) {
+        if u.scheme() == "file" {
+            u.to_file_path().ok()
+        } else {
+            None
+        }
+    } else {
+        Some(PathBuf::from(uri))
+    }
+}
+
+/// Resolve a clip URI given as a relative path against the media roots: the first
+/// root it exists under, else the first root. Other URIs are returned unchanged.
+pub fn resolve_uri(uri: &str, roots: &[PathBuf]) -> String {
+    if roots.is_empty() || url::Url::parse(uri).is_ok() || Path::new(uri).is_absolute() {
+        return uri.to_string();
+    }
+    // A sequence pattern isn't a file; look for its directory instead
+    let exists = |p: &Path| if is_sequence(uri) { p.parent().map(Path::is_dir).unwrap_or(false) } else { p.exists() };
+    let path = roots.iter().map(|r| r.join(uri)).find(|p| exists(p)).unwrap_or_else(|| roots[0].join(uri));
+    path.to_string_lossy().into_owned()
+}
+
+pub fn load_image_rgba(uri: &str) -> Option<Frame> {
+    let path = uri_to_path(uri)?;
+    decode_image(&path)
+}
+
+/// Decode an image file into an RGBA8 frame.
+pub fn decode_image(path: &Path) -> Option<Frame> {
+    let started = Instant::now();
+    let img = image::open(path).ok()?.to_rgba8();
+    let (width, height) = img.dimensions();
+    Some(Frame { pixels: img.into_raw(), width, height, decode_time: started.elapsed() })
+}
+
+const VIDEO_EXTENSIONS: &[&str] = &["mp4", "mov", "mkv", "m4v", "webm", "avi"];
+
+pub fn is_video(uri: &str) -> bool {
+    uri_to_path(uri)
+        .and_then(|p| p.extension().map(|e| e.to_string_lossy().to_ascii_lowercase()))
+        .map(|e| VIDEO_EXTENSIONS.contains(&e.as_str()))
+        .unwrap_or(false)
+}
+
+/// Open a frame source for a clip URI, or `None` if it can't be decoded.
+/// `fps` is only used by image sequences.
+pub fn open(uri: &str, fps: f64) -> Option<Box<dyn FrameSource>> {
+    if is_sequence(uri) {
+        return sequence::SequenceSource::open(uri, fps).map(|s| Box::new(s) as Box<dyn FrameSource>);
+    }
+    if is_video(uri) {
+        #[cfg(feature = "video")]
+        return video::VideoSource::open(uri).map(|v| Box::new(v) as Box<dyn FrameSource>);
+        #[cfg(not(feature = "video"))]
+        {
+            warn!("video clip {uri} needs the display built with --features video");
+            return None;
+        }
+    }
+    let frame = load_image_rgba(uri)?;
+    Some(Box::new(StillSource { frame: Some(frame) }))
+}
@@ -0,0 +1,295 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+
+use log::warn;
+
+use super::{decode_image, uri_to_path, Frame, FrameSource};
+
+/// Frames decoded ahead of the playhead.
+const PREFETCH_FRAMES: usize = 8;
+
+/// Stands in for the `%0Nd` token while the URI is turned into a path, since
+/// URL parsing would otherwise percent-decode it.
+const FRAME_MARKER: &str = "CONSTELLATIONFRAMENUMBER";
+
+/// Location of the `%d` / `%0Nd` token in a sequence URI: byte range and zero-pad width.
+fn find_token(uri: &str) -> Option<(usize, usize, usize)> {
+    let bytes = uri.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let mut j = i + 1;
+            while j < bytes.len() && bytes[j].is_ascii_digit() {
+                j += 1;
+            }
+            if j < bytes.len() && bytes[j] == b'd' {
+                let width = uri[i + 1..j].parse().unwrap_or(0);
+                return Some((i, j + 1, width));
+            }
+        }
+        i += 1;
+    }
+    None
+}
+
+pub fn is_sequence(uri: &str) -> bool {
+    find_token(uri).is_some()
+}
+
+/// The frame files of a sequence, in frame-number order. Gaps in the numbering are skipped.
+fn scan(uri: &str) -> Option<Vec<PathBuf>> {
+    let (start, end, width) = find_token(uri)?;
+    let marked = format!("{}{}{}", &uri[..start], FRAME_MARKER, &uri[end..]);
+    let path = uri_to_path(&marked)?;
+    let dir = path.parent()?.to_path_buf();
+    let name = path.file_name()?.to_string_lossy().into_owned();
+    let (prefix, suffix) = name.split_once(FRAME_MARKER)?;
+
+    let mut frames: Vec<(u64, PathBuf)> = std::fs::read_dir(&dir)
+        .ok()?
+        .filter_map(|e| e.ok())
+        .filter_map(|e| {
+            let file = e.file_name().to_string_lossy().into_owned();
+            let digits = file.strip_prefix(prefix)?.strip_suffix(suffix)?;
+            if digits.is_empty() || digits.len() < width || !digits.bytes().all(|b| b.is_ascii_digit()) {
+                return None;
+            }
+            Some((digits.parse().ok()?, e.path()))
+        })
+        .collect();
+    frames.sort_by_key(|(n, _)| *n);
+    Some(frames.into_iter().map(|(_, p)| p).collect())
+}
+
+struct State {
+    want: usize,
+    /// Decoded frames by sequence position; `None` once handed out or if decoding failed.
+    ready: HashMap<usize, Option<Frame>>,
+    shutdown: bool,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    /// Wakes the worker when the wanted frame changes
+    wake: Condvar,
+    /// Signalled by the worker whenever it stores a frame
+    decoded: Condvar,
+}
+
+/// Numbered still frames played back at a fixed rate. A worker thread decodes the
+/// frames just ahead of the playhead; the render thread uploads them into the
+/// clip's reused texture as the frame index changes.
+pub struct SequenceSource {
+    shared: Arc<Shared>,
+    fps: f64,
+    len: usize,
+    shown: Option<usize>,
+}
+
+impl SequenceSource {
+    pub fn open(uri: &str, fps: f64) -> Option<Self> {
+        if fps <= 0.0 {
+            warn!("image sequence {uri} needs a positive fps");
+            return None;
+        }
+        let files = scan(uri)?;
+        if files.is_empty() {
+            warn!("image sequence {uri} has no frames");
+            return None;
+        }
+        let len = files.len();
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State { want: 0, ready: HashMap::new(), shutdown: false }),
+            wake: Condvar::new(),
+            decoded: Condvar::new(),
+        });
+        let worker = shared.clone();
+        thread::Builder::new()
+            .name("sequence-prefetch".into())
+            .spawn(move || prefetch_loop(files, worker))
+            .ok()?;
+        Some(Self { shared, fps, len, shown: None })
+    }
+
+    /// Frame position for media time `t`, holding the first/last frame outside the sequence.
+    fn index_at(&self, t: f64) -> usize {
+        // The epsilon keeps exact frame boundaries (e.g. 3 / 30 * 30) from rounding down a frame.
+        let i = (t * self.fps + 1e-6).floor();
+        (i.max(0.0) as usize).min(self.len - 1)
+    }
+}
+
+impl FrameSource for SequenceSource {
+    fn frame_at(&mut self, t: f64) -> Option<Frame> {
+        let k = self.index_at(t);
+        let mut st = self.shared.state.lock().unwrap();
+        if st.want != k {
+            st.want = k;
+            st.ready.retain(|i, _| *i >= k && *i < k + PREFETCH_FRAMES);
+            self.shared.wake.notify_one();
+        }
+        if self.shown == Some(k) {
+            return None;
+        }
+        let frame = st.ready.get_mut(&k)?.take()?;
+        self.shown = Some(k);
+        Some(frame)
+    }
+
+    fn frame_at_blocking(&mut self, t: f64) -> Option<Frame> {
+        let k = self.index_at(t);
+        if self.shown == Some(k) {
+            return None;
+        }
+        let mut st = self.shared.state.lock().unwrap();
+        if st.want != k {
+            st.want = k;
+            st.ready.retain(|i, _| *i >= k && *i < k + PREFETCH_FRAMES);
+            self.shared.wake.notify_one();
+        }
+        while !st.ready.contains_key(&k) {
+            st = self.shared.decoded.wait(st).unwrap();
+        }
+        let frame = st.ready.get_mut(&k)?.take()?;
+        self.shown = Some(k);
+        Some(frame)
+    }
+}
+
+impl Drop for SequenceSource {
+    fn drop(&mut self) {
+        self.shared.state.lock().unwrap().shutdown = true;
+        self.shared.wake.notify_one();
+    }
+}
+
+fn prefetch_loop(files: Vec<PathBuf>, shared: Arc<Shared>) {
+    loop {
+        let k = {
+            let mut st = shared.state.lock().unwrap();
+            loop {
+                if st.shutdown {
+                    return;
+                }
+                let end = (st.want + PREFETCH_FRAMES).min(files.len());
+                if let Some(k) = (st.want..end).find(|i| !st.ready.contains_key(i)) {
+                    break k;
+                }
+                st = shared.wake.wait(st).unwrap();
+            }
+        };
+        let frame = decode_image(&files[k]);
+        if frame.is_none() {
+            warn!("failed to decode sequence frame {}", files[k].display());
+        }
+        let mut st = shared.state.lock().unwrap();
+        // The playhead may have moved on while decoding
+        if k >= st.want && k < st.want + PREFETCH_FRAMES {
+            st.ready.insert(k, frame);
+            shared.decoded.notify_all();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    /// A fresh directory under the system temp dir, removed when dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("constellation-seq-{name}-{}", std::process::id()));
+            let _ = std::fs::remove_dir_all(&dir);
+            std::fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// A 1x1 PNG whose red channel is `n`, so a decoded frame tells which file it came from.
+    fn write_frame(path: &Path, n: u8) {
+        image::RgbaImage::from_pixel(1, 1, image::Rgba([n, 0, 0, 255])).save(path).unwrap();
+    }
+
+    fn pattern(dir: &TempDir, name: &str) -> String {
+        dir.0.join(name).to_string_lossy().into_owned()
+    }
+
+    fn red(frame: Option<Frame>) -> Option<u8> {
+        frame.map(|f| f.pixels[0])
+    }
+
+    #[test]
+    fn token_parsing() {
+        assert_eq!(find_token("shot_%04d.png"), Some((5, 9, 4)));
+        assert_eq!(find_token("f%d.png"), Some((1, 3, 0)));
+        assert_eq!(find_token("100%.png"), None);
+        assert_eq!(find_token("50%x_%2d.png"), Some((5, 8, 2)));
+        assert!(is_sequence("file:///media/shot_%04d.exr"));
+        assert!(!is_sequence("file:///media/shot.png"));
+    }
+
+    #[test]
+    fn scan_orders_by_frame_number_and_skips_gaps() {
+        let dir = TempDir::new("scan");
+        for n in [10u8, 2, 1, 5] {
+            write_frame(&dir.0.join(format!("f_{n:04}.png")), n);
+        }
+        // Too few digits, not digits, other suffix, other prefix
+        for name in ["f_003.png", "f_abcd.png", "f_0004.jpg", "g_0006.png"] {
+            write_frame(&dir.0.join(name), 0);
+        }
+        let files = scan(&pattern(&dir, "f_%04d.png")).unwrap();
+        let names: Vec<_> = files.iter().map(|p| p.file_name().unwrap().to_string_lossy().into_owned()).collect();
+        assert_eq!(names, ["f_0001.png", "f_0002.png", "f_0005.png", "f_0010.png"]);
+
+        // Without a width any number of digits matches
+        let files = scan(&pattern(&dir, "f_%d.png")).unwrap();
+        assert_eq!(files.len(), 5);
+    }
+
+    #[test]
+    fn index_at_follows_fps_and_holds_the_ends() {
+        let dir = TempDir::new("index");
+        for n in 1..=4u8 {
+            write_frame(&dir.0.join(format!("f_{n}.png")), n);
+        }
+        let seq = SequenceSource::open(&pattern(&dir, "f_%d.png"), 10.0).unwrap();
+        for (t, i) in [(-1.0, 0), (0.0, 0), (0.099, 0), (0.1, 1), (0.29, 2), (3.0 / 10.0, 3), (100.0, 3)] {
+            assert_eq!(seq.index_at(t), i, "t={t}");
+        }
+        // A track with an in-point starts `in_seconds` into the media
+        let (start_at, in_seconds) = (5.0, 0.2);
+        let media_time = |t: f64| t - start_at + in_seconds;
+        assert_eq!(seq.index_at(media_time(5.0)), 2);
+        assert_eq!(seq.index_at(media_time(5.1)), 3);
+    }
+
+    #[test]
+    fn frames_follow_the_playhead_across_gaps_and_loops() {
+        let dir = TempDir::new("frames");
+        for n in [1u8, 2, 5, 9] {
+            write_frame(&dir.0.join(format!("f_{n:02}.png")), n);
+        }
+        let mut seq = SequenceSource::open(&pattern(&dir, "f_%02d.png"), 2.0).unwrap();
+        assert_eq!(red(seq.frame_at_blocking(0.0)), Some(1));
+        // The same frame again is not handed out twice
+        assert_eq!(red(seq.frame_at_blocking(0.4)), None);
+        // Gaps in the numbering don't leave gaps in playback
+        assert_eq!(red(seq.frame_at_blocking(1.0)), Some(5));
+        assert_eq!(red(seq.frame_at_blocking(1.5)), Some(9));
+        // Looping back to the start of the region shows the first frame again
+        assert_eq!(red(seq.frame_at_blocking(0.0)), Some(1));
+        assert_eq!(red(seq.frame_at_blocking(0.5)), Some(2));
+    }
+}
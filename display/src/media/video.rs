@@ -0,0 +1,251 @@
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use ffmpeg_next as ffmpeg;
+use ffmpeg::format::Pixel;
+use ffmpeg::software::scaling;
+use ffmpeg::util::frame;
+use log::warn;
+
+use super::{uri_to_path, Frame, FrameSource};
+
+/// Seeking is cheaper than decoding forward past this many seconds.
+const SEEK_AHEAD_SECONDS: f64 = 2.0;
+
+struct Control {
+    target: Option<f64>,
+    /// Last target the worker finished decoding up to
+    done: Option<f64>,
+    shutdown: bool,
+}
+
+struct Shared {
+    ctl: Mutex<Control>,
+    wake: Condvar,
+    /// Signalled by the worker after each target it serves
+    served: Condvar,
+    latest: Mutex<Option<Frame>>,
+}
+
+/// Video clip decoded on its own thread. The render thread only posts the wanted
+/// media time and picks up the most recent converted frame, so pause, seek and rate
+/// changes all follow from the transport time it is fed.
+pub struct VideoSource {
+    shared: Arc<Shared>,
+}
+
+impl VideoSource {
+    pub fn open(uri: &str) -> Option<Self> {
+        let path = uri_to_path(uri)?;
+        if let Err(e) = ffmpeg::init() {
+            warn!("ffmpeg init failed: {e}");
+            return None;
+        }
+        let shared = Arc::new(Shared {
+            ctl: Mutex::new(Control { target: None, done: None, shutdown: false }),
+            wake: Condvar::new(),
+            served: Condvar::new(),
+            latest: Mutex::new(None),
+        });
+        let worker = shared.clone();
+        // The scaler isn't Send, so the decoder is built on its thread; wait for it to
+        // open so unsupported files fail here rather than silently later.
+        let (opened_tx, opened_rx) = mpsc::channel();
+        let thread_path: PathBuf = path.clone();
+        thread::Builder::new()
+            .name(format!("video:{}", path.display()))
+            .spawn(move || match Decoder::open(&thread_path) {
+                Ok(dec) => {
+                    let _ = opened_tx.send(Ok(()));
+                    decode_loop(dec, worker);
+                }
+                Err(e) => {
+                    let _ = opened_tx.send(Err(e));
+                }
+            })
+            .ok()?;
+        match opened_rx.recv() {
+            Ok(Ok(())) => Some(Self { shared }),
+            Ok(Err(e)) => {
+                warn!("cannot open video {uri}: {e}");
+                None
+            }
+            Err(_) => None,
+        }
+    }
+}
+
+impl FrameSource for VideoSource {
+    fn frame_at(&mut self, t: f64) -> Option<Frame> {
+        {
+            let mut ctl = self.shared.ctl.lock().unwrap();
+            if ctl.target != Some(t) {
+                ctl.target = Some(t);
+                self.shared.wake.notify_one();
+            }
+        }
+        self.shared.latest.lock().unwrap().take()
+    }
+
+    fn frame_at_blocking(&mut self, t: f64) -> Option<Frame> {
+        {
+            let mut ctl = self.shared.ctl.lock().unwrap();
+            if ctl.target != Some(t) {
+                ctl.target = Some(t);
+                self.shared.wake.notify_one();
+            }
+            while !ctl.shutdown && ctl.done != Some(t) {
+                ctl = self.shared.served.wait(ctl).unwrap();
+            }
+        }
+        self.shared.latest.lock().unwrap().take()
+    }
+}
+
+impl Drop for VideoSource {
+    fn drop(&mut self) {
+        self.shared.ctl.lock().unwrap().shutdown = true;
+        self.shared.wake.notify_one();
+    }
+}
+
+struct Decoder {
+    input: ffmpeg::format::context::Input,
+    decoder: ffmpeg::decoder::Video,
+    scaler: scaling::Context,
+    stream_index: usize,
+    time_base: f64,
+    eof: bool,
+}
+
+impl Decoder {
+    fn open(path: &Path) -> Result<Self, ffmpeg::Error> {
+        let input = ffmpeg::format::input(path)?;
+        let stream = input.streams().best(ffmpeg::media::Type::Video).ok_or(ffmpeg::Error::StreamNotFound)?;
+        let stream_index = stream.index();
+        let time_base = f64::from(stream.time_base());
+        let ctx = ffmpeg::codec::context::Context::from_parameters(stream.parameters())?;
+        let decoder = ctx.decoder().video()?;
+        let scaler = scaling::Context::get(
+            decoder.format(),
+            decoder.width(),
+            decoder.height(),
+            Pixel::RGBA,
+            decoder.width(),
+            decoder.height(),
+            scaling::Flags::BILINEAR,
+        )?;
+        Ok(Self { input, decoder, scaler, stream_index, time_base, eof: false })
+    }
+
+    /// Next decoded frame with its presentation time in seconds, or `None` at end of stream.
+    fn next(&mut self) -> Option<(f64, frame::Video)> {
+        loop {
+            let mut decoded = frame::Video::empty();
+            if self.decoder.receive_frame(&mut decoded).is_ok() {
+                let ts = decoded.timestamp().or(decoded.pts()).unwrap_or(0);
+                return Some((ts as f64 * self.time_base, decoded));
+            }
+            if self.eof {
+                return None;
+            }
+            match self.input.packets().next() {
+                Some((stream, packet)) => {
+                    if stream.index() == self.stream_index {
+                        let _ = self.decoder.send_packet(&packet);
+                    }
+                }
+                None => {
+                    let _ = self.decoder.send_eof();
+                    self.eof = true;
+                }
+            }
+        }
+    }
+
+    fn seek(&mut self, t: f64) {
+        let ts = (t.max(0.0) * 1_000_000.0) as i64; // AV_TIME_BASE units
+        if let Err(e) = self.input.seek(ts, ..ts) {
+            warn!("video seek to {t:.3}s failed: {e}");
+        }
+        self.decoder.flush();
+        self.eof = false;
+    }
+
+    fn convert(&mut self, decoded: &frame::Video, decode_time: Duration) -> Option<Frame> {
+        let mut rgba = frame::Video::empty();
+        self.scaler.run(decoded, &mut rgba).ok()?;
+        let (width, height) = (rgba.width(), rgba.height());
+        let stride = rgba.stride(0);
+        let row = width as usize * 4;
+        let data = rgba.data(0);
+        let mut pixels = Vec::with_capacity(row * height as usize);
+        for y in 0..height as usize {
+            pixels.extend_from_slice(&data[y * stride..y * stride + row]);
+        }
+        Some(Frame { pixels, width, height, decode_time })
+    }
+}
+
+fn decode_loop(mut dec: Decoder, shared: Arc<Shared>) {
+    let mut served: Option<f64> = None;
+    // pts of the frame last published, and one decoded frame held back because it lies in the future
+    let mut shown: Option<f64> = None;
+    let mut ahead: Option<(f64, frame::Video)> = None;
+    loop {
+        let target = {
+            let mut ctl = shared.ctl.lock().unwrap();
+            while !ctl.shutdown && (ctl.target.is_none() || ctl.target == served) {
+                ctl = shared.wake.wait(ctl).unwrap();
+            }
+            if ctl.shutdown {
+                return;
+            }
+            ctl.target.unwrap()
+        };
+        served = Some(target);
+        let started = Instant::now();
+
+        let position = ahead.as_ref().map(|(pts, _)| *pts).or(shown);
+        let behind = shown.map(|s| target < s).unwrap_or(false);
+        let far_ahead = position.map(|p| target > p + SEEK_AHEAD_SECONDS).unwrap_or(target > SEEK_AHEAD_SECONDS);
+        if behind || far_ahead {
+            dec.seek(target);
+            ahead = None;
+            shown = None;
+        }
+
+        // Decode forward to the last frame whose pts <= target; only that one is converted.
+        let mut candidate: Option<(f64, frame::Video)> = None;
+        loop {
+            let next = match ahead.take() {
+                Some(f) => Some(f),
+                None => dec.next(),
+            };
+            match next {
+                Some((pts, f)) if pts <= target => candidate = Some((pts, f)),
+                Some(f) => {
+                    ahead = Some(f);
+                    break;
+                }
+                None => break,
+            }
+        }
+        // Before the first frame (or right after a seek) show the earliest available frame.
+        if candidate.is_none() && shown.is_none() {
+            candidate = ahead.take();
+        }
+        if let Some((pts, f)) = candidate {
+            if shown != Some(pts) {
+                if let Some(out) = dec.convert(&f, started.elapsed()) {
+                    *shared.latest.lock().unwrap() = Some(out);
+                    shown = Some(pts);
+                }
+            }
+        }
+        shared.ctl.lock().unwrap().done = Some(target);
+        shared.served.notify_all();
+    }
+}
@@ -0,0 +1,248 @@
+use std::collections::HashMap;
+use std::sync::mpsc;
+use std::thread;
+
+use glam::{Mat3, Mat4, Vec3};
+use log::warn;
+use wgpu::util::DeviceExt;
+
+use crate::media::uri_to_path;
+
+/// Vertex of a loaded mesh, in the space of the glTF node it was loaded from.
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct MeshVertex {
+    pub pos: [f32; 3],
+    pub normal: [f32; 3],
+    /// glTF material base color times vertex color, linear
+    pub color: [f32; 4],
+}
+
+/// The triangles of a glTF node and its descendants, flattened into one indexed list.
+pub struct MeshData {
+    pub vertices: Vec<MeshVertex>,
+    pub indices: Vec<u32>,
+}
+
+/// Load `uri` (glTF or GLB). `node` is a path of node names from a scene root
+/// (`Stage/Floor`); that node's subtree is loaded without the node's own transform,
+/// which is left to the scene node showing it. An empty `node` loads the whole
+/// default scene.
+pub fn load(uri: &str, node: &str) -> Result<MeshData, String> {
+    let path = uri_to_path(uri).ok_or_else(|| format!("not a file URI: {uri}"))?;
+    let bytes = std::fs::read(&path).map_err(|e| format!("{}: {e}", path.display()))?;
+    let gltf = gltf::Gltf::from_slice(&bytes).map_err(|e| format!("{}: {e}", path.display()))?;
+    let buffers = gltf::import_buffers(&gltf.document, path.parent(), gltf.blob.clone()).map_err(|e| format!("{}: {e}", path.display()))?;
+    let scene = gltf.document.default_scene().or_else(|| gltf.document.scenes().next()).ok_or_else(|| format!("{}: no scene", path.display()))?;
+
+    let mut out = MeshData { vertices: Vec::new(), indices: Vec::new() };
+    if node.is_empty() {
+        for root in scene.nodes() {
+            bake(&root, Mat4::from_cols_array_2d(&root.transform().matrix()), &buffers, &mut out);
+        }
+    } else {
+        let mut found: Option<gltf::Node> = None;
+        for name in node.split('/') {
+            let mut level: Box<dyn Iterator<Item = gltf::Node>> = match &found {
+                None => Box::new(scene.nodes()),
+                Some(n) => Box::new(n.children()),
+            };
+            found = Some(level.find(|n| n.name() == Some(name)).ok_or_else(|| format!("{}: no node '{node}'", path.display()))?);
+        }
+        if let Some(n) = found {
+            bake(&n, Mat4::IDENTITY, &buffers, &mut out);
+        }
+    }
+    if out.indices.is_empty() {
+        return Err(format!("{}: no triangles under '{node}'", path.display()));
+    }
+    Ok(out)
+}
+
+/// Append the triangles of `node` and its descendants, transformed by `world`.
+fn bake(node: &gltf::Node, world: Mat4, buffers: &[gltf::buffer::Data], out: &mut MeshData) {
+    if let Some(mesh) = node.mesh() {
+        let normal_m = Mat3::from_mat4(world).inverse().transpose();
+        for prim in mesh.primitives() {
+            if prim.mode() != gltf::mesh::Mode::Triangles {
+                continue;
+            }
+            let reader = prim.reader(|b| buffers.get(b.index()).map(|d| &d.0[..]));
+            let Some(positions) = reader.read_positions() else { continue };
+            let positions: Vec<Vec3> = positions.map(Vec3::from).collect();
+            let indices: Vec<u32> = match reader.read_indices() {
+                Some(i) => whole_triangles(&i.into_u32().collect::<Vec<_>>(), positions.len()),
+                None => (0..positions.len() as u32 / 3 * 3).collect(),
+            };
+            let normals: Vec<Vec3> = match reader.read_normals() {
+                Some(n) => n.map(Vec3::from).collect(),
+                None => smooth_normals(&positions, &indices),
+            };
+            let factor = prim.material().pbr_metallic_roughness().base_color_factor();
+            let colors: Vec<[f32; 4]> = match reader.read_colors(0) {
+                Some(c) => c.into_rgba_f32().map(|c| [c[0] * factor[0], c[1] * factor[1], c[2] * factor[2], c[3] * factor[3]]).collect(),
+                None => vec![factor; positions.len()],
+            };
+            let base = out.vertices.len() as u32;
+            for (i, p) in positions.iter().enumerate() {
+                let n = normals.get(i).copied().unwrap_or(Vec3::Y);
+                out.vertices.push(MeshVertex {
+                    pos: world.transform_point3(*p).into(),
+                    normal: (normal_m * n).normalize_or_zero().into(),
+                    color: colors.get(i).copied().unwrap_or(factor),
+                });
+            }
+            out.indices.extend(indices.iter().map(|i| base + i));
+        }
+    }
+    for child in node.children() {
+        bake(&child, world * Mat4::from_cols_array_2d(&child.transform().matrix()), buffers, out);
+    }
+}
+
+/// `indices` without its triangles that use an index past `vertex_count`, and
+/// without a trailing partial triangle.
+fn whole_triangles(indices: &[u32], vertex_count: usize) -> Vec<u32> {
+    indices.chunks_exact(3).filter(|tri| tri.iter().all(|&i| (i as usize) < vertex_count)).flatten().copied().collect()
+}
+
+/// Per-vertex normals averaged from the faces around each vertex, for primitives without normals.
+fn smooth_normals(positions: &[Vec3], indices: &[u32]) -> Vec<Vec3> {
+    let mut normals = vec![Vec3::ZERO; positions.len()];
+    for tri in indices.chunks_exact(3) {
+        let [a, b, c] = [tri[0] as usize, tri[1] as usize, tri[2] as usize];
+        let n = (positions[b] - positions[a]).cross(positions[c] - positions[a]);
+        for i in [a, b, c] {
+            normals[i] += n;
+        }
+    }
+    normals.into_iter().map(|n| n.try_normalize().unwrap_or(Vec3::Y)).collect()
+}
+
+/// An asset and the node path inside it.
+pub type MeshKey = (String, String);
+
+/// Vertex and index buffers of one loaded mesh.
+pub struct MeshGpu {
+    pub vertices: wgpu::Buffer,
+    pub indices: wgpu::Buffer,
+    pub index_count: u32,
+}
+
+enum Slot {
+    Loading,
+    Ready(Box<MeshGpu>),
+    Failed,
+}
+
+/// Loaded meshes by asset and node path. Assets are parsed on one background
+/// thread, started with the first request (or on the calling thread when
+/// `blocking`, for offline rendering); the render thread only uploads. Failed
+/// loads are logged once and not retried.
+pub struct MeshCache {
+    map: HashMap<MeshKey, Slot>,
+    pub blocking: bool,
+    jobs: Option<mpsc::Sender<MeshKey>>,
+    tx: mpsc::Sender<(MeshKey, Result<MeshData, String>)>,
+    rx: mpsc::Receiver<(MeshKey, Result<MeshData, String>)>,
+}
+
+impl MeshCache {
+    pub fn new() -> Self {
+        let (tx, rx) = mpsc::channel();
+        Self { map: HashMap::new(), blocking: false, jobs: None, tx, rx }
+    }
+
+    /// Start loading `key` unless it is already known.
+    pub fn request(&mut self, key: &MeshKey, device: &wgpu::Device) {
+        if self.map.contains_key(key) {
+            return;
+        }
+        if self.blocking {
+            let slot = self.upload(key, load(&key.0, &key.1), device);
+            self.map.insert(key.clone(), slot);
+            return;
+        }
+        let queued = self.worker().and_then(|jobs| jobs.send(key.clone()).map_err(|_| "mesh loader stopped".to_string()));
+        let slot = match queued {
+            Ok(()) => Slot::Loading,
+            Err(e) => {
+                warn!("mesh {} not loaded: {e}", key.0);
+                Slot::Failed
+            }
+        };
+        self.map.insert(key.clone(), slot);
+    }
+
+    /// The loader thread's job queue, starting the thread if this is the first request.
+    fn worker(&mut self) -> Result<mpsc::Sender<MeshKey>, String> {
+        if let Some(jobs) = &self.jobs {
+            return Ok(jobs.clone());
+        }
+        let (jobs, job_rx) = mpsc::channel::<MeshKey>();
+        let tx = self.tx.clone();
+        thread::Builder::new()
+            .name("mesh-loader".into())
+            .spawn(move || {
+                for key in job_rx {
+                    let data = load(&key.0, &key.1);
+                    if tx.send((key, data)).is_err() {
+                        return;
+                    }
+                }
+            })
+            .map_err(|e| format!("mesh loader thread not started: {e}"))?;
+        Ok(self.jobs.insert(jobs).clone())
+    }
+
+    /// Upload meshes loaded since the last poll.
+    pub fn poll(&mut self, device: &wgpu::Device) {
+        while let Ok((key, data)) = self.rx.try_recv() {
+            let slot = self.upload(&key, data, device);
+            self.map.insert(key, slot);
+        }
+    }
+
+    fn upload(&self, (uri, node): &MeshKey, data: Result<MeshData, String>, device: &wgpu::Device) -> Slot {
+        let data = match data {
+            Ok(data) => data,
+            Err(e) => {
+                warn!("mesh {uri}{}{node} not loaded: {e}", if node.is_empty() { "" } else { "#" });
+                return Slot::Failed;
+            }
+        };
+        let buffer = |label, contents, usage| device.create_buffer_init(&wgpu::util::BufferInitDescriptor { label: Some(label), contents, usage });
+        Slot::Ready(Box::new(MeshGpu {
+            vertices: buffer("mesh-vb", bytemuck::cast_slice(&data.vertices), wgpu::BufferUsages::VERTEX),
+            indices: buffer("mesh-ib", bytemuck::cast_slice(&data.indices), wgpu::BufferUsages::INDEX),
+            index_count: data.indices.len() as u32,
+        }))
+    }
+
+    pub fn get(&self, key: &MeshKey) -> Option<&MeshGpu> {
+        match self.map.get(key) {
+            Some(Slot::Ready(m)) => Some(m),
+            _ => None,
+        }
+    }
+
+    pub fn is_loading(&self, key: &MeshKey) -> bool {
+        matches!(self.map.get(key), Some(Slot::Loading))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn out_of_range_index_drops_its_whole_triangle() {
+        let indices = [0, 1, 2, 2, 9, 3, 1, 2, 3];
+        assert_eq!(whole_triangles(&indices, 4), vec![0, 1, 2, 1, 2, 3]);
+    }
+
+    #[test]
+    fn trailing_partial_triangle_is_dropped() {
+        assert_eq!(whole_triangles(&[0, 1, 2, 3, 0], 4), vec![0, 1, 2]);
+    }
+}
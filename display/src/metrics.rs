@@ -0,0 +1,235 @@
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use log::{info, warn};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+use crate::proto::{transport_state, Metrics, Percentiles, StateUpdate};
+use crate::state::AppState;
+
+/// Samples kept per measurement: about ten seconds of frames at 60 Hz.
+const WINDOW: usize = 600;
+
+/// The most recent durations of one measurement, in milliseconds.
+pub struct Samples {
+    values: VecDeque<f64>,
+}
+
+impl Default for Samples {
+    fn default() -> Self {
+        Self { values: VecDeque::with_capacity(WINDOW) }
+    }
+}
+
+impl Samples {
+    pub fn push(&mut self, d: Duration) {
+        if self.values.len() == WINDOW {
+            self.values.pop_front();
+        }
+        self.values.push_back(d.as_secs_f64() * 1000.0);
+    }
+
+    /// Nearest-rank percentiles, or `None` before the first sample.
+    pub fn percentiles(&self) -> Option<Percentiles> {
+        if self.values.is_empty() {
+            return None;
+        }
+        let mut sorted: Vec<f64> = self.values.iter().copied().collect();
+        sorted.sort_by(f64::total_cmp);
+        let at = |q: f64| sorted[((q * sorted.len() as f64).ceil() as usize).clamp(1, sorted.len()) - 1];
+        Some(Percentiles { p50: at(0.5), p95: at(0.95), p99: at(0.99), max: sorted[sorted.len() - 1], count: sorted.len() as u32 })
+    }
+}
+
+/// Frame timing published by the render loop about once a second.
+#[derive(Clone, Default)]
+pub struct FrameStats {
+    pub fps: f64,
+    pub frames: u64,
+    pub dropped: u64,
+    pub cpu: Option<Percentiles>,
+    pub gpu: Option<Percentiles>,
+    pub interval: Option<Percentiles>,
+    pub upload: Option<Percentiles>,
+    pub decode: Option<Percentiles>,
+}
+
+/// Render `update` in the Prometheus text exposition format.
+pub fn prometheus_text(update: &StateUpdate, ready: bool) -> String {
+    let mut out = String::new();
+    let mut metric = |name: &str, kind: &str, help: &str, value: f64| write_metric(&mut out, name, kind, help, value);
+    metric("ready", "gauge", "1 while the renderer can present frames", if ready { 1.0 } else { 0.0 });
+    if let Some(t) = &update.transport {
+        metric("transport_playing", "gauge", "1 while the transport is playing", if t.status() == transport_state::Status::Playing { 1.0 } else { 0.0 });
+        metric("transport_time_seconds", "gauge", "Timeline position", t.time_seconds);
+        metric("transport_rate", "gauge", "Playback rate", t.rate);
+    }
+    if let Some(m) = &update.media {
+        metric("media_loading", "gauge", "Clips being opened", m.loading as f64);
+        metric("media_ready", "gauge", "Clips opened", m.ready as f64);
+        metric("media_failed", "gauge", "Clips that failed to open", m.failed as f64);
+    }
+    if let Some(m) = &update.metrics {
+        write_metrics(&mut out, m);
+    }
+    out
+}
+
+fn write_metric(out: &mut String, name: &str, kind: &str, help: &str, value: f64) {
+    let _ = writeln!(out, "# HELP constellation_{name} {help}\n# TYPE constellation_{name} {kind}\nconstellation_{name} {value}");
+}
+
+fn write_metrics(out: &mut String, m: &Metrics) {
+    let mut metric = |name: &str, kind: &str, help: &str, value: f64| write_metric(out, name, kind, help, value);
+    metric("fps", "gauge", "Frames rendered per second", m.fps);
+    metric("frames_total", "counter", "Frames rendered", m.frames_total as f64);
+    metric("dropped_frames_total", "counter", "Display refreshes missed by late frames", m.dropped_frames);
+    metric("texture_cache_hits_total", "counter", "Media lookups served from a resident texture", m.texture_cache_hits as f64);
+    metric("texture_cache_misses_total", "counter", "Media clips that had to be (re)loaded", m.texture_cache_misses as f64);
+    metric("texture_resident_bytes", "gauge", "Approximate GPU memory held by media textures", m.texture_resident_bytes as f64);
+    metric("texture_budget_bytes", "gauge", "Media texture memory budget", m.texture_budget_bytes as f64);
+    let timings = [
+        ("frame_cpu_ms", "Render thread time per frame", &m.frame_cpu_ms),
+        ("frame_gpu_ms", "GPU time of the preview pass per frame", &m.frame_gpu_ms),
+        ("frame_interval_ms", "Time between presented frames", &m.frame_interval_ms),
+        ("texture_upload_ms", "Time to upload a media frame into its texture", &m.texture_upload_ms),
+        ("media_decode_ms", "Time to decode a media frame", &m.decode_ms),
+    ];
+    for (name, help, p) in timings {
+        let Some(p) = p else { continue };
+        let _ = writeln!(out, "# HELP constellation_{name} {help}, over the most recent samples\n# TYPE constellation_{name} gauge");
+        for (q, v) in [("0.5", p.p50), ("0.95", p.p95), ("0.99", p.p99), ("1", p.max)] {
+            let _ = writeln!(out, "constellation_{name}{{quantile=\"{q}\"}} {v}");
+        }
+    }
+}
+
+/// Pause after a failed accept before the next.
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Serve `GET /metrics` in the Prometheus text format on `addr`.
+pub async fn serve(addr: SocketAddr, app: AppState) {
+    let listener = match TcpListener::bind(addr).await {
+        Ok(l) => l,
+        Err(e) => {
+            warn!("metrics endpoint not started on {addr}: {e}");
+            return;
+        }
+    };
+    info!("Prometheus metrics on http://{addr}/metrics");
+    loop {
+        let mut stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(e) => {
+                // Out of file descriptors, say: wait rather than spin on the error
+                warn!("metrics endpoint: {e}");
+                tokio::time::sleep(ACCEPT_BACKOFF).await;
+                continue;
+            }
+        };
+        let app = app.clone();
+        tokio::spawn(async move {
+            // Only the request line matters; read until the end of the headers
+            let mut buf = Vec::new();
+            let mut chunk = [0u8; 1024];
+            while !buf.windows(4).any(|w| w == b"\r\n\r\n") && buf.len() < 8192 {
+                match stream.read(&mut chunk).await {
+                    Ok(0) | Err(_) => return,
+                    Ok(n) => buf.extend_from_slice(&chunk[..n]),
+                }
+            }
+            let request = String::from_utf8_lossy(&buf);
+            let path = request.split_whitespace().nth(1).unwrap_or("");
+            let (status, body) = if request.starts_with("GET ") && (path == "/metrics" || path.starts_with("/metrics?")) {
+                ("200 OK", prometheus_text(&app.snapshot(), *app.watch_ready().borrow()))
+            } else {
+                ("404 Not Found", "not found\n".to_string())
+            };
+            let head = format!(
+                "HTTP/1.1 {status}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                body.len()
+            );
+            let _ = stream.write_all(head.as_bytes()).await;
+            let _ = stream.write_all(body.as_bytes()).await;
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::TransportState;
+
+    fn samples(secs: impl IntoIterator<Item = u64>) -> Samples {
+        let mut s = Samples::default();
+        for n in secs {
+            s.push(Duration::from_secs(n));
+        }
+        s
+    }
+
+    fn quantiles(p: Percentiles) -> (f64, f64, f64, f64, u32) {
+        (p.p50, p.p95, p.p99, p.max, p.count)
+    }
+
+    #[test]
+    fn no_samples_no_percentiles() {
+        assert!(Samples::default().percentiles().is_none());
+    }
+
+    #[test]
+    fn a_single_sample_is_every_percentile() {
+        assert_eq!(quantiles(samples([4]).percentiles().unwrap()), (4000.0, 4000.0, 4000.0, 4000.0, 1));
+    }
+
+    #[test]
+    fn percentiles_are_nearest_rank() {
+        // Pushed out of order: ranks come from the sorted values
+        let p = samples((1..=100).rev()).percentiles().unwrap();
+        assert_eq!(quantiles(p), (50_000.0, 95_000.0, 99_000.0, 100_000.0, 100));
+        let p = samples([1, 2, 3]).percentiles().unwrap();
+        assert_eq!(quantiles(p), (2000.0, 3000.0, 3000.0, 3000.0, 3));
+    }
+
+    #[test]
+    fn only_the_most_recent_window_counts() {
+        let p = samples(1..=WINDOW as u64 + 100).percentiles().unwrap();
+        // 101..=700 remain
+        assert_eq!(quantiles(p), (400_000.0, 670_000.0, 694_000.0, 700_000.0, WINDOW as u32));
+    }
+
+    #[test]
+    fn exposition_format() {
+        let update = StateUpdate {
+            transport: Some(TransportState { status: transport_state::Status::Playing as i32, time_seconds: 1.5, rate: 1.0, ..Default::default() }),
+            metrics: Some(Metrics {
+                fps: 60.0,
+                frames_total: 120,
+                frame_cpu_ms: Some(Percentiles { p50: 2.0, p95: 3.5, p99: 4.0, max: 9.25, count: 120 }),
+                ..Default::default()
+            }),
+            media: None,
+        };
+        let text = prometheus_text(&update, false);
+        assert!(text.starts_with(
+            "# HELP constellation_ready 1 while the renderer can present frames\n# TYPE constellation_ready gauge\nconstellation_ready 0\n\
+             # HELP constellation_transport_playing 1 while the transport is playing\n# TYPE constellation_transport_playing gauge\nconstellation_transport_playing 1\n\
+             # HELP constellation_transport_time_seconds Timeline position\n# TYPE constellation_transport_time_seconds gauge\nconstellation_transport_time_seconds 1.5\n"
+        ), "{text}");
+        assert!(text.contains("# TYPE constellation_frames_total counter\nconstellation_frames_total 120\n"), "{text}");
+        assert!(text.ends_with(
+            "# HELP constellation_frame_cpu_ms Render thread time per frame, over the most recent samples\n# TYPE constellation_frame_cpu_ms gauge\n\
+             constellation_frame_cpu_ms{quantile=\"0.5\"} 2\nconstellation_frame_cpu_ms{quantile=\"0.95\"} 3.5\n\
+             constellation_frame_cpu_ms{quantile=\"0.99\"} 4\nconstellation_frame_cpu_ms{quantile=\"1\"} 9.25\n"
+        ), "{text}");
+        // Unsampled timings and a missing media section are left out
+        assert!(!text.contains("frame_gpu_ms") && !text.contains("media_"), "{text}");
+        for line in text.lines().filter(|l| !l.starts_with('#')) {
+            let (name, value) = line.rsplit_once(' ').unwrap();
+            assert!(name.starts_with("constellation_") && value.parse::<f64>().is_ok(), "{line}");
+        }
+    }
+}
@@ -0,0 +1,225 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use glam::{Mat4, Vec3};
+use log::warn;
+use winit::dpi::{PhysicalPosition, PhysicalSize};
+use winit::event_loop::EventLoopWindowTarget;
+use winit::monitor::MonitorHandle;
+use winit::window::{Fullscreen, Window, WindowBuilder, WindowId};
+
+use crate::proto::{output_window::Mode, OutputWindow};
+use crate::scene::InstanceGpu;
+
+/// What a screen shows this frame, resolved once by the render loop for all windows.
+pub struct ScreenFrame {
+    pub bind_group: Rc<wgpu::BindGroup>,
+    pub opacity: f32,
+}
+
+/// GPU objects shared with the 3D preview.
+pub struct CanvasGpu<'a> {
+    pub device: &'a wgpu::Device,
+    pub queue: &'a wgpu::Queue,
+    pub format: wgpu::TextureFormat,
+    pub camera_bgl: &'a wgpu::BindGroupLayout,
+    pub pipeline: &'a wgpu::RenderPipeline,
+    pub quad_vb: &'a wgpu::Buffer,
+}
+
+/// Draws screen canvases flat, one canvas pixel per target pixel, with the preview's
+/// quad pipeline under a pixel-space orthographic projection.
+pub struct CanvasPass {
+    camera_buffer: wgpu::Buffer,
+    camera_bg: wgpu::BindGroup,
+    inst_vb: wgpu::Buffer,
+}
+
+impl CanvasPass {
+    /// A pass for up to `capacity` screens.
+    pub fn new(gpu: &CanvasGpu, capacity: usize) -> Self {
+        let camera_buffer = gpu.device.create_buffer(&wgpu::BufferDescriptor{
+            label: Some("canvas-camera-ubo"),
+            size: std::mem::size_of::<[[f32;4];4]>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let camera_bg = gpu.device.create_bind_group(&wgpu::BindGroupDescriptor{
+            label: Some("canvas-camera-bg"),
+            layout: gpu.camera_bgl,
+            entries: &[wgpu::BindGroupEntry{ binding:0, resource: camera_buffer.as_entire_binding() }],
+        });
+        let inst_vb = gpu.device.create_buffer(&wgpu::BufferDescriptor{
+            label: Some("canvas-inst-vb"),
+            size: (capacity.max(1) * std::mem::size_of::<InstanceGpu>()) as u64,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        Self { camera_buffer, camera_bg, inst_vb }
+    }
+
+    /// Draw `screens` (node id and top-left position) on black into `target` of
+    /// `(width, height)` pixels and submit. Screens missing from `frames` or `sizes`
+    /// (e.g. no longer in the scene) are left black.
+    pub fn draw(
+        &mut self,
+        gpu: &CanvasGpu,
+        target: &wgpu::TextureView,
+        (width, height): (u32, u32),
+        screens: &[(String, i32, i32)],
+        sizes: &HashMap<String, (u32, u32)>,
+        frames: &HashMap<String, ScreenFrame>,
+    ) {
+        let proj = Mat4::orthographic_rh(0.0, width as f32, height as f32, 0.0, -1.0, 1.0);
+        gpu.queue.write_buffer(&self.camera_buffer, 0, bytemuck::bytes_of(&proj.to_cols_array_2d()));
+
+        let mut inst_raw = Vec::new();
+        let mut bind_groups = Vec::new();
+        for (node_id, x, y) in screens {
+            let (Some(&(pw, ph)), Some(f)) = (sizes.get(node_id), frames.get(node_id)) else { continue };
+            let (pw, ph) = (pw as f32, ph as f32);
+            // The unit quad has +Y up; flip it so the canvas' top row lands at `y`
+            let model = Mat4::from_translation(Vec3::new(*x as f32 + pw / 2.0, *y as f32 + ph / 2.0, 0.0))
+                * Mat4::from_scale(Vec3::new(pw, -ph, 1.0));
+            inst_raw.push(InstanceGpu { model: model.to_cols_array_2d(), opacity: f.opacity, _pad: [0.0; 3] });
+            bind_groups.push(f.bind_group.clone());
+        }
+        if !inst_raw.is_empty() {
+            gpu.queue.write_buffer(&self.inst_vb, 0, bytemuck::cast_slice(&inst_raw));
+        }
+
+        let mut encoder = gpu.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("canvas-frame") });
+        {
+            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("canvas-pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: target,
+                    resolve_target: None,
+                    ops: wgpu::Operations { load: wgpu::LoadOp::Clear(wgpu::Color::BLACK), store: wgpu::StoreOp::Store },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            rpass.set_pipeline(gpu.pipeline);
+            rpass.set_bind_group(0, &self.camera_bg, &[]);
+            rpass.set_vertex_buffer(0, gpu.quad_vb.slice(..));
+            rpass.set_vertex_buffer(1, self.inst_vb.slice(..));
+            for (i, bg) in bind_groups.iter().enumerate() {
+                rpass.set_bind_group(1, bg, &[]);
+                let ii = i as u32;
+                rpass.draw(0..6, ii..ii+1);
+            }
+        }
+        gpu.queue.submit(Some(encoder.finish()));
+    }
+}
+
+/// An output window showing one or more screen canvases at native resolution.
+pub struct Output {
+    pub id: String,
+    window: Arc<Window>,
+    surface: wgpu::Surface<'static>,
+    config: wgpu::SurfaceConfiguration,
+    /// Screen node id and top-left position in the window
+    screens: Vec<(String, i32, i32)>,
+    pass: CanvasPass,
+}
+
+/// The monitor named `name`, or the primary one for an empty name.
+pub fn find_monitor(elwt: &EventLoopWindowTarget<()>, name: &str) -> Result<Option<MonitorHandle>, String> {
+    if name.is_empty() {
+        return Ok(elwt.primary_monitor().or_else(|| elwt.available_monitors().next()));
+    }
+    if let Some(m) = elwt.available_monitors().find(|m| m.name().as_deref() == Some(name)) {
+        return Ok(Some(m));
+    }
+    let names: Vec<String> = elwt.available_monitors().filter_map(|m| m.name()).collect();
+    Err(format!("no monitor named '{name}' (available: {})", names.join(", ")))
+}
+
+impl Output {
+    /// Open the window for `cfg`, sized to fit its screens' canvases unless fullscreen.
+    pub fn open(
+        elwt: &EventLoopWindowTarget<()>,
+        cfg: &OutputWindow,
+        sizes: &HashMap<String, (u32, u32)>,
+        instance: &wgpu::Instance,
+        adapter: &wgpu::Adapter,
+        gpu: &CanvasGpu,
+    ) -> Result<Self, String> {
+        let monitor = find_monitor(elwt, &cfg.monitor)?;
+        let (mut w, mut h) = (1u32, 1u32);
+        for s in &cfg.screens {
+            if let Some(&(pw, ph)) = sizes.get(&s.node_id) {
+                w = w.max((s.x.max(0) as u32).saturating_add(pw));
+                h = h.max((s.y.max(0) as u32).saturating_add(ph));
+            }
+        }
+
+        let mode = cfg.mode();
+        let mut builder = WindowBuilder::new().with_title(format!("Constellation Output {}", cfg.id));
+        if mode == Mode::Fullscreen {
+            builder = builder.with_fullscreen(Some(Fullscreen::Borderless(monitor)));
+        } else {
+            builder = builder.with_inner_size(PhysicalSize::new(w, h)).with_decorations(mode == Mode::Windowed);
+            if let Some(m) = &monitor {
+                let origin = m.position();
+                builder = builder.with_position(PhysicalPosition::new(origin.x + cfg.x, origin.y + cfg.y));
+            }
+        }
+        let window = Arc::new(builder.build(elwt).map_err(|e| e.to_string())?);
+
+        let surface = instance.create_surface(window.clone()).map_err(|e| e.to_string())?;
+        let caps = surface.get_capabilities(adapter);
+        if !caps.formats.contains(&gpu.format) {
+            return Err(format!("surface does not support {:?}", gpu.format));
+        }
+        let size = window.inner_size();
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format: gpu.format,
+            width: size.width.max(1),
+            height: size.height.max(1),
+            present_mode: wgpu::PresentMode::AutoVsync,
+            alpha_mode: caps.alpha_modes[0],
+            view_formats: vec![],
+            desired_maximum_frame_latency: 2,
+        };
+        surface.configure(gpu.device, &config);
+
+        Ok(Self {
+            id: cfg.id.clone(),
+            window,
+            surface,
+            config,
+            screens: cfg.screens.iter().map(|s| (s.node_id.clone(), s.x, s.y)).collect(),
+            pass: CanvasPass::new(gpu, cfg.screens.len()),
+        })
+    }
+
+    pub fn window_id(&self) -> WindowId {
+        self.window.id()
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, size: PhysicalSize<u32>) {
+        self.config.width = size.width.max(1);
+        self.config.height = size.height.max(1);
+        self.surface.configure(device, &self.config);
+    }
+
+    pub fn render(&mut self, gpu: &CanvasGpu, sizes: &HashMap<String, (u32, u32)>, frames: &HashMap<String, ScreenFrame>) {
+        let frame = match self.surface.get_current_texture() {
+            Ok(frame) => frame,
+            Err(err) => {
+                warn!("Output '{}' surface error: {err:?}, reconfiguring...", self.id);
+                self.surface.configure(gpu.device, &self.config);
+                return;
+            }
+        };
+        let view = frame.texture.create_view(&wgpu::TextureViewDescriptor::default());
+        self.pass.draw(gpu, &view, (self.config.width, self.config.height), &self.screens, sizes, frames);
+        frame.present();
+    }
+}
@@ -1,4 +1,7 @@
 use anyhow::Result;
+use log::{info, warn};
+use serde::Deserialize;
+use std::sync::mpsc;
 use std::time::{Duration, Instant};
 use wgpu::SurfaceConfiguration;
 use wgpu::util::DeviceExt;
@@ -6,65 +9,493 @@ use glam::{Mat4, Vec3};
 use winit::{
     dpi::LogicalSize,
     event::{Event, WindowEvent},
-    event_loop::EventLoop,
-    window::WindowBuilder,
+    event_loop::{EventLoop, EventLoopWindowTarget},
+    window::{Fullscreen, Window, WindowBuilder},
 };
 
-use crate::state::AppState;
-use std::collections::HashMap;
+use crate::media::{self, FrameSource, Loaded, Loader};
+use crate::mesh::{MeshCache, MeshKey, MeshVertex};
+use crate::metrics::{FrameStats, Samples};
+use crate::capture::{encode_png, read_rgba, CaptureJob};
+use crate::output::{find_monitor, CanvasGpu, CanvasPass, Output, ScreenFrame};
+use crate::proto::{CaptureFrameRequest, CaptureFrameResponse, MediaLoadState};
+use crate::scene::{InstanceData, InstanceGpu, Instances, MeshInstance, MeshInstanceGpu};
+use crate::state::{ActiveClip, AppState, TextureCacheStats};
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
 
 pub struct Renderer;
 
-impl Renderer {
-    pub fn run(app: AppState) -> Result<()> {
-    let event_loop = EventLoop::new().expect("create event loop");
-    let window = WindowBuilder::new()
-            .with_title("Constellation Display")
-            .with_inner_size(LogicalSize::new(1280.0, 720.0))
-            .build(&event_loop)?;
-    use std::sync::Arc;
-    let window = Arc::new(window);
+/// Render-loop settings. The window, vsync and clear color can change at runtime
+/// (config reload); the rest is fixed at startup.
+#[derive(Clone, Debug)]
+pub struct RenderConfig {
+    /// Approximate GPU memory media textures may hold before least recently used
+    /// clips are evicted.
+    pub texture_budget_bytes: u64,
+    /// Render offscreen without opening any window
+    pub headless: bool,
+    pub title: String,
+    /// Preview window size (logical pixels), or the offscreen target size when headless
+    pub width: u32,
+    pub height: u32,
+    /// Borderless fullscreen on `monitor` instead of a `width` x `height` window
+    pub fullscreen: bool,
+    /// Monitor name as reported by the OS; empty = primary
+    pub monitor: String,
+    pub vsync: Vsync,
+    /// Preview background, linear RGBA
+    pub clear_color: [f64; 4],
+}
 
-        // WGPU setup
-        let instance = wgpu::Instance::default();
-    let surface = instance.create_surface(window.as_ref())?;
-        let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
-            power_preference: wgpu::PowerPreference::HighPerformance,
-            compatible_surface: Some(&surface),
-            force_fallback_adapter: false,
-        }))
-        .expect("no adapter");
+impl Default for RenderConfig {
+    fn default() -> Self {
+        Self {
+            texture_budget_bytes: 1024 * 1024 * 1024,
+            headless: false,
+            title: "Constellation Display".into(),
+            width: 1280,
+            height: 720,
+            fullscreen: false,
+            monitor: String::new(),
+            vsync: Vsync::Auto,
+            clear_color: [0.02, 0.02, 0.03, 1.0],
+        }
+    }
+}
 
-        let (device, queue) = pollster::block_on(adapter.request_device(
-            &wgpu::DeviceDescriptor {
-                label: Some("device"),
-                required_features: wgpu::Features::empty(),
-                required_limits: wgpu::Limits::default(),
+/// Presentation mode of the preview window.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Vsync {
+    /// Synced to the display, with the lowest latency mode the surface offers
+    Auto,
+    /// Unsynced, may tear
+    Off,
+    Fifo,
+    Mailbox,
+    Immediate,
+}
+
+impl Vsync {
+    fn present_mode(self, supported: &[wgpu::PresentMode]) -> wgpu::PresentMode {
+        let mode = match self {
+            Vsync::Auto => wgpu::PresentMode::AutoVsync,
+            Vsync::Off => wgpu::PresentMode::AutoNoVsync,
+            Vsync::Fifo => wgpu::PresentMode::Fifo,
+            Vsync::Mailbox => wgpu::PresentMode::Mailbox,
+            Vsync::Immediate => wgpu::PresentMode::Immediate,
+        };
+        if matches!(self, Vsync::Auto | Vsync::Off) || supported.contains(&mode) {
+            mode
+        } else {
+            warn!("vsync {self:?} is not supported by the surface, using auto");
+            wgpu::PresentMode::AutoVsync
+        }
+    }
+}
+
+/// Refresh interval of the monitor showing `window`; 60 Hz if unknown.
+fn refresh_period(window: &Window) -> Duration {
+    let mhz = window.current_monitor().and_then(|m| m.refresh_rate_millihertz()).unwrap_or(60_000);
+    Duration::from_secs_f64(1000.0 / mhz.max(1) as f64)
+}
+
+fn to_color([r, g, b, a]: [f64; 4]) -> wgpu::Color {
+    wgpu::Color { r, g, b, a }
+}
+
+/// Frame rate of the headless render loop.
+const HEADLESS_FPS: f64 = 60.0;
+
+/// Color format of offscreen render targets.
+const OFFSCREEN_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+/// Depth buffer format of the 3D preview.
+const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+/// The render loop's device, shared with offline rendering on other threads.
+#[derive(Clone)]
+pub struct Gpu {
+    pub device: Arc<wgpu::Device>,
+    pub queue: Arc<wgpu::Queue>,
+}
+
+/// GPU texture for one clip, re-uploaded in place whenever its source yields a new frame.
+struct TexEntry {
+    source: Box<dyn FrameSource>,
+    texture: Option<(wgpu::Texture, Rc<wgpu::BindGroup>)>,
+    /// Size of `texture`
+    bytes: u64,
+    /// Frame counter when a screen last showed this clip
+    last_used: u64,
+}
+
+enum Slot {
+    /// `attempts`: earlier failed opens of the same source
+    Loading { attempts: u32 },
+    Ready(TexEntry),
+    Failed { attempts: u32, retry_at: Instant },
+}
+
+/// A clip's slot and the source it was opened from. A clip whose URI or fps
+/// changes (a project reload fixing a path, say) is opened again.
+struct Cached {
+    uri: String,
+    fps: f64,
+    slot: Slot,
+}
+
+/// Wait before reopening a clip that failed `attempts` times: 1s, doubling up to a minute.
+fn retry_backoff(attempts: u32) -> Duration {
+    Duration::from_secs(1 << attempts.saturating_sub(1).min(6)).min(Duration::from_secs(60))
+}
+
+fn opened(source: Option<Box<dyn FrameSource>>, attempts: u32, frame: u64) -> Slot {
+    match source {
+        Some(source) => Slot::Ready(TexEntry { source, texture: None, bytes: 0, last_used: frame }),
+        None => Slot::Failed { attempts: attempts + 1, retry_at: Instant::now() + retry_backoff(attempts + 1) },
+    }
+}
+
+/// What a screen should show for its clip this frame.
+enum TexLookup {
+    Ready(Rc<wgpu::BindGroup>),
+    /// Still opening, or opened but no frame decoded yet.
+    Loading,
+    Failed,
+}
+
+/// Seconds ahead of the playhead whose clips are opened in advance.
+const PREFETCH_SECONDS: f64 = 5.0;
+
+/// Texture cache: clip_id -> streaming texture. Clips are opened on the loader's
+/// threads; the render thread only uploads frames that are already decoded.
+/// Resident textures are kept under a byte budget by evicting the least recently
+/// shown clips that aren't coming up on the timeline.
+struct TexCache {
+    map: HashMap<String, Cached>,
+    loader: Loader,
+    /// Open clips and decode frames on the calling thread, waiting for each (offline rendering)
+    blocking: bool,
+    budget: u64,
+    frame: u64,
+    hits: u64,
+    misses: u64,
+    upload_times: Samples,
+    decode_times: Samples,
+}
+
+impl TexCache {
+    fn new(budget: u64) -> Self {
+        let workers = std::thread::available_parallelism().map(|n| n.get().min(4)).unwrap_or(2);
+        Self {
+            map: HashMap::new(),
+            loader: Loader::new(workers),
+            blocking: false,
+            budget,
+            frame: 0,
+            hits: 0,
+            misses: 0,
+            upload_times: Samples::default(),
+            decode_times: Samples::default(),
+        }
+    }
+
+    fn begin_frame(&mut self) {
+        self.frame += 1;
+    }
+
+    /// Start opening a clip unless it is already known from the same URI and fps.
+    /// Failed clips are retried with a growing backoff.
+    fn request(&mut self, clip: &ActiveClip) {
+        let attempts = match self.map.get(&clip.clip_id) {
+            Some(c) if c.uri == clip.uri && c.fps == clip.fps => match c.slot {
+                Slot::Failed { attempts, retry_at } if Instant::now() >= retry_at => attempts,
+                _ => return,
             },
-            None,
-        ))?;
+            _ => 0,
+        };
+        self.misses += 1;
+        let slot = if self.blocking {
+            opened(media::open(&clip.uri, clip.fps), attempts, self.frame)
+        } else {
+            self.loader.request(&clip.clip_id, &clip.uri, clip.fps);
+            Slot::Loading { attempts }
+        };
+        self.map.insert(clip.clip_id.clone(), Cached { uri: clip.uri.clone(), fps: clip.fps, slot });
+    }
 
-        let size = window.inner_size();
-        let surface_caps = surface.get_capabilities(&adapter);
-        let surface_format = surface_caps
-            .formats
-            .iter()
-            .copied()
-            .find(|f| f.is_srgb())
-            .unwrap_or(surface_caps.formats[0]);
+    /// Move finished loads into the cache, dropping those for a source the clip no
+    /// longer has. Returns true if any slot changed.
+    fn poll(&mut self) -> bool {
+        let mut changed = false;
+        for Loaded { clip_id, uri, fps, source } in self.loader.poll() {
+            let Some(c) = self.map.get_mut(&clip_id) else { continue };
+            if let Slot::Loading { attempts } = c.slot {
+                if c.uri == uri && c.fps == fps {
+                    c.slot = opened(source, attempts, self.frame);
+                    changed = true;
+                }
+            }
+        }
+        changed
+    }
 
-        let mut config = SurfaceConfiguration {
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-            format: surface_format,
-            width: size.width.max(1),
-            height: size.height.max(1),
-            present_mode: wgpu::PresentMode::AutoVsync,
-            alpha_mode: surface_caps.alpha_modes[0],
-            view_formats: vec![],
-            desired_maximum_frame_latency: 2,
+    fn resident_bytes(&self) -> u64 {
+        self.map.values().map(|c| match &c.slot {
+            Slot::Ready(e) => e.bytes,
+            _ => 0,
+        }).sum()
+    }
+
+    fn stats(&self) -> TextureCacheStats {
+        TextureCacheStats { hits: self.hits, misses: self.misses, resident_bytes: self.resident_bytes(), budget_bytes: self.budget }
+    }
+
+    /// Drop least recently used textures until the cache fits the budget. Clips in
+    /// `keep` (on screen or coming up soon) are never evicted, so the budget can be
+    /// exceeded if they alone don't fit. Returns the evicted clips, least recently
+    /// used first; their textures are only freed once nothing else holds them.
+    fn evict(&mut self, keep: &HashSet<String>) -> Vec<String> {
+        let mut resident = self.resident_bytes();
+        if resident <= self.budget {
+            return Vec::new();
+        }
+        let mut candidates: Vec<(u64, u64, String)> = self.map.iter().filter_map(|(id, c)| match &c.slot {
+            Slot::Ready(e) if !keep.contains(id) && e.bytes > 0 => Some((e.last_used, e.bytes, id.clone())),
+            _ => None,
+        }).collect();
+        candidates.sort();
+        let mut evicted = Vec::new();
+        for (_, bytes, id) in candidates {
+            if resident <= self.budget {
+                break;
+            }
+            self.map.remove(&id);
+            resident -= bytes;
+            evicted.push(id);
+        }
+        evicted
+    }
+
+    fn load_state(&self) -> MediaLoadState {
+        let mut st = MediaLoadState::default();
+        for (id, c) in &self.map {
+            match c.slot {
+                Slot::Loading { .. } => {
+                    st.loading += 1;
+                    st.loading_clip_ids.push(id.clone());
+                }
+                Slot::Ready(_) => st.ready += 1,
+                Slot::Failed { .. } => {
+                    st.failed += 1;
+                    st.failed_clip_ids.push(id.clone());
+                }
+            }
+        }
+        st.loading_clip_ids.sort();
+        st.failed_clip_ids.sort();
+        st
+    }
+
+    fn get_or_load(
+        &mut self,
+        clip: &ActiveClip,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        tex_bgl: &wgpu::BindGroupLayout,
+        sampler: &wgpu::Sampler,
+    ) -> TexLookup {
+        self.request(clip);
+        let entry = match self.map.get_mut(&clip.clip_id).map(|c| &mut c.slot) {
+            Some(Slot::Ready(entry)) => entry,
+            Some(Slot::Failed { .. }) => return TexLookup::Failed,
+            _ => return TexLookup::Loading,
         };
-        surface.configure(&device, &config);
+        entry.last_used = self.frame;
+        let frame = if self.blocking {
+            entry.source.frame_at_blocking(clip.media_time)
+        } else {
+            entry.source.frame_at(clip.media_time)
+        };
+        if let Some(frame) = frame {
+            self.decode_times.push(frame.decode_time);
+            let started = Instant::now();
+            let size = wgpu::Extent3d { width: frame.width, height: frame.height, depth_or_array_layers: 1 };
+            let reuse = entry.texture.as_ref().map(|(t, _)| t.size() == size).unwrap_or(false);
+            if !reuse {
+                let tex = device.create_texture(&wgpu::TextureDescriptor{
+                    label: Some("media-tex"),
+                    size,
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format: wgpu::TextureFormat::Rgba8UnormSrgb,
+                    usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                    view_formats: &[],
+                });
+                let view = tex.create_view(&wgpu::TextureViewDescriptor::default());
+                let bg = Rc::new(device.create_bind_group(&wgpu::BindGroupDescriptor{
+                    label: Some("media-bg"),
+                    layout: tex_bgl,
+                    entries: &[
+                        wgpu::BindGroupEntry{ binding:0, resource: wgpu::BindingResource::TextureView(&view) },
+                        wgpu::BindGroupEntry{ binding:1, resource: wgpu::BindingResource::Sampler(sampler) },
+                    ],
+                }));
+                entry.texture = Some((tex, bg));
+                entry.bytes = size.width as u64 * size.height as u64 * 4;
+            }
+            if let Some((tex, _)) = entry.texture.as_ref() {
+                queue.write_texture(
+                    wgpu::ImageCopyTexture { texture: tex, mip_level: 0, origin: wgpu::Origin3d::ZERO, aspect: wgpu::TextureAspect::All },
+                    &frame.pixels,
+                    wgpu::ImageDataLayout { offset: 0, bytes_per_row: Some(4 * frame.width), rows_per_image: Some(frame.height) },
+                    size,
+                );
+            }
+            self.upload_times.push(started.elapsed());
+        }
+        match entry.texture.as_ref() {
+            Some((_, bg)) => {
+                self.hits += 1;
+                TexLookup::Ready(bg.clone())
+            }
+            None => TexLookup::Loading,
+        }
+    }
+}
+
+/// Live frame counters and timing samples behind the frame metrics.
+struct FrameTiming {
+    frames: u64,
+    dropped: u64,
+    last_present: Option<Instant>,
+    published: Instant,
+    since_publish: u32,
+    cpu: Samples,
+    gpu: Samples,
+    interval: Samples,
+}
+
+impl FrameTiming {
+    fn new() -> Self {
+        Self {
+            frames: 0,
+            dropped: 0,
+            last_present: None,
+            published: Instant::now(),
+            since_publish: 0,
+            cpu: Samples::default(),
+            gpu: Samples::default(),
+            interval: Samples::default(),
+        }
+    }
+}
 
+/// Measures the preview pass on the GPU with timestamp queries. One reading is in
+/// flight at a time; frames drawn while it is pending aren't measured.
+struct GpuTimer {
+    queries: wgpu::QuerySet,
+    resolved: wgpu::Buffer,
+    readback: wgpu::Buffer,
+    /// Nanoseconds per timestamp tick
+    period: f64,
+    /// Written by the map callback: whether `readback` could be mapped
+    mapped: Arc<Mutex<Option<bool>>>,
+    busy: bool,
+}
+
+impl GpuTimer {
+    /// `None` if the device wasn't created with timestamp queries.
+    fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Option<Self> {
+        if !device.features().contains(wgpu::Features::TIMESTAMP_QUERY) {
+            return None;
+        }
+        let buffer = |label, usage| device.create_buffer(&wgpu::BufferDescriptor { label: Some(label), size: 16, usage, mapped_at_creation: false });
+        Some(Self {
+            queries: device.create_query_set(&wgpu::QuerySetDescriptor { label: Some("gpu-timer"), ty: wgpu::QueryType::Timestamp, count: 2 }),
+            resolved: buffer("gpu-timer-resolve", wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC),
+            readback: buffer("gpu-timer-readback", wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST),
+            period: queue.get_timestamp_period() as f64,
+            mapped: Arc::new(Mutex::new(None)),
+            busy: false,
+        })
+    }
+
+    fn timestamp_writes(&self) -> wgpu::RenderPassTimestampWrites<'_> {
+        wgpu::RenderPassTimestampWrites { query_set: &self.queries, beginning_of_pass_write_index: Some(0), end_of_pass_write_index: Some(1) }
+    }
+
+    /// Copy the pass's timestamps into the readback buffer; record after the pass.
+    fn resolve(&self, encoder: &mut wgpu::CommandEncoder) {
+        encoder.resolve_query_set(&self.queries, 0..2, &self.resolved, 0);
+        encoder.copy_buffer_to_buffer(&self.resolved, 0, &self.readback, 0, 16);
+    }
+
+    /// Start reading back; call once the commands from `resolve` are submitted.
+    fn map(&mut self) {
+        self.busy = true;
+        let mapped = self.mapped.clone();
+        self.readback.slice(..).map_async(wgpu::MapMode::Read, move |r| *mapped.lock().unwrap() = Some(r.is_ok()));
+    }
+
+    /// The measured time, once the readback has landed.
+    fn read(&mut self) -> Option<Duration> {
+        let ok = self.mapped.lock().unwrap().take()?;
+        self.busy = false;
+        if !ok {
+            return None;
+        }
+        let data = self.readback.slice(..).get_mapped_range();
+        let ticks: &[u64] = bytemuck::cast_slice(&data);
+        let elapsed = ticks[1].checked_sub(ticks[0]).map(|t| Duration::from_nanos((t as f64 * self.period) as u64));
+        drop(data);
+        self.readback.unmap();
+        elapsed
+    }
+}
+
+/// Pipeline, buffers and media textures for drawing the 3D preview, independent of
+/// whether frames go to a window or an offscreen texture.
+pub struct FrameRenderer {
+    device: Arc<wgpu::Device>,
+    queue: Arc<wgpu::Queue>,
+    format: wgpu::TextureFormat,
+    camera_bgl: wgpu::BindGroupLayout,
+    camera_buffer: wgpu::Buffer,
+    camera_bg: wgpu::BindGroup,
+    tex_bgl: wgpu::BindGroupLayout,
+    /// Screen quads on canvases, without depth
+    pipeline: wgpu::RenderPipeline,
+    /// Screen quads in the preview, depth tested against meshes
+    preview_pipeline: wgpu::RenderPipeline,
+    mesh_pipeline: wgpu::RenderPipeline,
+    quad_vb: wgpu::Buffer,
+    inst_vb: wgpu::Buffer,
+    mesh_inst_vb: wgpu::Buffer,
+    /// Preview depth buffer, recreated when the target size changes
+    depth: Option<wgpu::Texture>,
+    linear_sampler: wgpu::Sampler,
+    white_bg: Rc<wgpu::BindGroup>,
+    loading_bg: Rc<wgpu::BindGroup>,
+    clear_color: wgpu::Color,
+    tex_cache: TexCache,
+    mesh_cache: MeshCache,
+    /// Last clip and texture each screen showed, held while its next clip is still loading
+    last_shown: HashMap<String, (String, Rc<wgpu::BindGroup>)>,
+    timing: FrameTiming,
+    gpu_timer: Option<GpuTimer>,
+    /// Captures waiting for their clips to load
+    pending_captures: Vec<CaptureJob>,
+    /// Queue of the thread rendering captures at a requested time, once started
+    offline_captures: Option<mpsc::SyncSender<(CaptureJob, (u32, u32))>>,
+}
+
+impl FrameRenderer {
+    fn new(device: Arc<wgpu::Device>, queue: Arc<wgpu::Queue>, format: wgpu::TextureFormat, cfg: &RenderConfig) -> Self {
         // Shaders and pipeline
         let shader_src = include_str!("shaders/quad.wgsl");
         let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
@@ -128,30 +559,82 @@ impl Renderer {
                     wgpu::VertexAttribute{ shader_location: 1, offset: (3*4) as u64, format: wgpu::VertexFormat::Float32x2 },
                 ],
             },
-            // instance: model mat4 as 4 vec4 attrs
+            // instance: model mat4 as 4 vec4 attrs, then opacity (padded to 16 bytes)
             wgpu::VertexBufferLayout{
-                array_stride: (16*4) as u64,
+                array_stride: std::mem::size_of::<InstanceGpu>() as u64,
                 step_mode: wgpu::VertexStepMode::Instance,
                 attributes: &[
                     wgpu::VertexAttribute{ shader_location: 2, offset: 0, format: wgpu::VertexFormat::Float32x4 },
                     wgpu::VertexAttribute{ shader_location: 3, offset: (4*4) as u64, format: wgpu::VertexFormat::Float32x4 },
                     wgpu::VertexAttribute{ shader_location: 4, offset: (8*4) as u64, format: wgpu::VertexFormat::Float32x4 },
                     wgpu::VertexAttribute{ shader_location: 5, offset: (12*4) as u64, format: wgpu::VertexFormat::Float32x4 },
+                    wgpu::VertexAttribute{ shader_location: 6, offset: (16*4) as u64, format: wgpu::VertexFormat::Float32 },
                 ],
             },
         ];
 
-        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor{
-            label: Some("quad-pipeline"),
+        let quad_pipeline = |label, depth_stencil| device.create_render_pipeline(&wgpu::RenderPipelineDescriptor{
+            label: Some(label),
             layout: Some(&pipeline_layout),
             vertex: wgpu::VertexState{ module: &shader, entry_point: "vs_main", buffers: &vertex_layouts },
             fragment: Some(wgpu::FragmentState{
                 module: &shader,
                 entry_point: "fs_main",
-                targets: &[Some(wgpu::ColorTargetState{ format: surface_format, blend: Some(wgpu::BlendState::ALPHA_BLENDING), write_mask: wgpu::ColorWrites::ALL })],
+                targets: &[Some(wgpu::ColorTargetState{ format, blend: Some(wgpu::BlendState::ALPHA_BLENDING), write_mask: wgpu::ColorWrites::ALL })],
             }),
             primitive: wgpu::PrimitiveState{ cull_mode: None, ..Default::default() },
-            depth_stencil: None,
+            depth_stencil,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+        let depth_state = wgpu::DepthStencilState {
+            format: DEPTH_FORMAT,
+            depth_write_enabled: true,
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        };
+        let pipeline = quad_pipeline("quad-pipeline", None);
+        let preview_pipeline = quad_pipeline("quad-preview-pipeline", Some(depth_state.clone()));
+
+        // Meshes: baked glTF vertices, instanced per mesh component
+        let mesh_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("mesh-shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("shaders/mesh.wgsl").into()),
+        });
+        let mesh_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("mesh-pipeline-layout"),
+            bind_group_layouts: &[&camera_bgl],
+            push_constant_ranges: &[],
+        });
+        let mesh_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor{
+            label: Some("mesh-pipeline"),
+            layout: Some(&mesh_layout),
+            vertex: wgpu::VertexState{
+                module: &mesh_shader,
+                entry_point: "vs_main",
+                buffers: &[
+                    // vertex: pos, normal, color
+                    wgpu::VertexBufferLayout{
+                        array_stride: std::mem::size_of::<MeshVertex>() as u64,
+                        step_mode: wgpu::VertexStepMode::Vertex,
+                        attributes: &wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x3, 2 => Float32x4],
+                    },
+                    // instance: model mat4 as 4 vec4 attrs, then material color
+                    wgpu::VertexBufferLayout{
+                        array_stride: std::mem::size_of::<MeshInstanceGpu>() as u64,
+                        step_mode: wgpu::VertexStepMode::Instance,
+                        attributes: &wgpu::vertex_attr_array![3 => Float32x4, 4 => Float32x4, 5 => Float32x4, 6 => Float32x4, 7 => Float32x4],
+                    },
+                ],
+            },
+            fragment: Some(wgpu::FragmentState{
+                module: &mesh_shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState{ format, blend: Some(wgpu::BlendState::ALPHA_BLENDING), write_mask: wgpu::ColorWrites::ALL })],
+            }),
+            primitive: wgpu::PrimitiveState{ cull_mode: None, ..Default::default() },
+            depth_stencil: Some(depth_state),
             multisample: wgpu::MultisampleState::default(),
             multiview: None,
         });
@@ -170,30 +653,19 @@ impl Renderer {
             contents: bytemuck::cast_slice(&quad),
             usage: wgpu::BufferUsages::VERTEX,
         });
-        let mut inst_vb = device.create_buffer(&wgpu::BufferDescriptor{
+        let inst_vb = device.create_buffer(&wgpu::BufferDescriptor{
             label: Some("inst-vb"),
             size: 64 * 1024,
             usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
             mapped_at_creation: false,
         });
+        let mesh_inst_vb = device.create_buffer(&wgpu::BufferDescriptor{
+            label: Some("mesh-inst-vb"),
+            size: 64 * 1024,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
 
-        // White 1x1 texture as fallback
-        let white_tex = device.create_texture_with_data(
-            &queue,
-            &wgpu::TextureDescriptor{
-                label: Some("white-tex"),
-                size: wgpu::Extent3d { width: 1, height: 1, depth_or_array_layers: 1 },
-                mip_level_count: 1,
-                sample_count: 1,
-                dimension: wgpu::TextureDimension::D2,
-                format: wgpu::TextureFormat::Rgba8UnormSrgb,
-                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
-                view_formats: &[],
-            },
-            wgpu::util::TextureDataOrder::LayerMajor,
-            &[255,255,255,255],
-        );
-        let white_view = white_tex.create_view(&wgpu::TextureViewDescriptor::default());
         let linear_sampler = device.create_sampler(&wgpu::SamplerDescriptor{
             label: Some("linear-sampler"),
             mag_filter: wgpu::FilterMode::Linear,
@@ -204,175 +676,700 @@ impl Renderer {
             address_mode_w: wgpu::AddressMode::ClampToEdge,
             ..Default::default()
         });
-        let white_bg = device.create_bind_group(&wgpu::BindGroupDescriptor{
-            label: Some("white-bg"),
-            layout: &tex_bgl,
-            entries: &[
-                wgpu::BindGroupEntry{ binding:0, resource: wgpu::BindingResource::TextureView(&white_view) },
-                wgpu::BindGroupEntry{ binding:1, resource: wgpu::BindingResource::Sampler(&linear_sampler) },
-            ],
-        });
+        // White 1x1 texture as fallback
+        let white_bg = solid_bind_group(&device, &queue, &tex_bgl, &linear_sampler, "white", [255,255,255,255]);
+        // Black 1x1 shown on a screen whose clip is still loading and that has nothing previous to hold
+        let loading_bg = solid_bind_group(&device, &queue, &tex_bgl, &linear_sampler, "loading", [0,0,0,255]);
 
-        // Texture cache: clip_id -> bind group
-        struct TexCache {
-            map: HashMap<String, wgpu::BindGroup>,
-        }
-        impl TexCache {
-            fn new() -> Self { Self { map: HashMap::new() } }
-            fn get_or_load(
-                &mut self,
-                clip_id: &str,
-                uri: &str,
-                device: &wgpu::Device,
-                queue: &wgpu::Queue,
-                tex_bgl: &wgpu::BindGroupLayout,
-                sampler: &wgpu::Sampler,
-            ) -> Option<wgpu::BindGroup> {
-                if let Some(bg) = self.map.get(clip_id) { return Some(bg.clone()); }
-                if let Some((pixels, w, h)) = load_image_rgba(uri) {
-                    let tex = device.create_texture_with_data(
-                        queue,
-                        &wgpu::TextureDescriptor{
-                            label: Some("media-tex"),
-                            size: wgpu::Extent3d { width: w, height: h, depth_or_array_layers: 1 },
-                            mip_level_count: 1,
-                            sample_count: 1,
-                            dimension: wgpu::TextureDimension::D2,
-                            format: wgpu::TextureFormat::Rgba8UnormSrgb,
-                            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
-                            view_formats: &[],
-                        },
-                        wgpu::util::TextureDataOrder::LayerMajor,
-                        &pixels,
-                    );
-                    let view = tex.create_view(&wgpu::TextureViewDescriptor::default());
-                    let bg = device.create_bind_group(&wgpu::BindGroupDescriptor{
-                        label: Some("media-bg"),
-                        layout: tex_bgl,
-                        entries: &[
-                            wgpu::BindGroupEntry{ binding:0, resource: wgpu::BindingResource::TextureView(&view) },
-                            wgpu::BindGroupEntry{ binding:1, resource: wgpu::BindingResource::Sampler(sampler) },
-                        ],
-                    });
-                    self.map.insert(clip_id.to_string(), bg.clone());
-                    return Some(bg);
+        let tex_cache = TexCache::new(cfg.texture_budget_bytes);
+        let gpu_timer = GpuTimer::new(&device, &queue);
+
+        Self {
+            device,
+            queue,
+            format,
+            camera_bgl,
+            camera_buffer,
+            camera_bg,
+            tex_bgl,
+            pipeline,
+            preview_pipeline,
+            mesh_pipeline,
+            quad_vb,
+            inst_vb,
+            mesh_inst_vb,
+            depth: None,
+            linear_sampler,
+            white_bg,
+            loading_bg,
+            clear_color: to_color(cfg.clear_color),
+            tex_cache,
+            mesh_cache: MeshCache::new(),
+            last_shown: HashMap::new(),
+            timing: FrameTiming::new(),
+            gpu_timer,
+            pending_captures: Vec::new(),
+            offline_captures: None,
+        }
+    }
+
+    /// An offscreen renderer on `gpu` that waits for every clip to open and decode
+    /// the exact frame asked for, so output doesn't depend on loading speed.
+    pub fn offline(gpu: Gpu, cfg: &RenderConfig) -> Self {
+        let mut renderer = Self::new(gpu.device, gpu.queue, OFFSCREEN_FORMAT, cfg);
+        renderer.tex_cache.blocking = true;
+        renderer.mesh_cache.blocking = true;
+        renderer.gpu_timer = None;
+        renderer
+    }
+
+    fn gpu(&self) -> Gpu {
+        Gpu { device: self.device.clone(), queue: self.queue.clone() }
+    }
+
+    /// Screen instances at time `t` with the texture each one shows, and mesh
+    /// instances. Starts loading clips and meshes that aren't in the cache yet.
+    fn resolve(&mut self, app: &AppState, t: f64) -> Resolved {
+        let Instances { screens: insts, meshes } = app.instances_at(t);
+        for m in &meshes {
+            self.mesh_cache.request(&mesh_key(m), &self.device);
+        }
+        let meshes_loading = meshes.iter().any(|m| self.mesh_cache.is_loading(&mesh_key(m)));
+        let mut loading = HashSet::new();
+        // While a clip loads the screen holds its previous texture (or black);
+        // no clip or a clip that failed to open shows white.
+        let bind_groups = insts.iter().map(|inst| {
+            let Some(clip) = app.active_clip_for_node(&inst.node_id, t) else {
+                self.last_shown.remove(&inst.node_id);
+                return self.white_bg.clone();
+            };
+            match self.tex_cache.get_or_load(&clip, &self.device, &self.queue, &self.tex_bgl, &self.linear_sampler) {
+                TexLookup::Ready(bg) => {
+                    self.last_shown.insert(inst.node_id.clone(), (clip.clip_id.clone(), bg.clone()));
+                    bg
+                }
+                TexLookup::Loading => {
+                    loading.insert(inst.node_id.clone());
+                    self.last_shown.get(&inst.node_id).map(|(_, bg)| bg.clone()).unwrap_or_else(|| self.loading_bg.clone())
                 }
-                None
+                TexLookup::Failed => {
+                    self.last_shown.remove(&inst.node_id);
+                    self.white_bg.clone()
+                }
+            }
+        }).collect();
+        Resolved { insts, bind_groups, loading, meshes, meshes_loading }
+    }
+
+    /// `resolve` for offline rendering, keeping texture memory under budget as it goes.
+    pub fn resolve_offline(&mut self, app: &AppState, t: f64) -> Resolved {
+        self.tex_cache.begin_frame();
+        let resolved = self.resolve(app, t);
+        let keep: HashSet<String> = app.upcoming_clips(t, PREFETCH_SECONDS).into_iter().map(|c| c.clip_id).collect();
+        self.evict(&keep);
+        resolved
+    }
+
+    /// Evict textures over budget, including screens' holds on them so they are
+    /// freed. Returns true if anything was evicted.
+    fn evict(&mut self, keep: &HashSet<String>) -> bool {
+        let evicted = self.tex_cache.evict(keep);
+        self.last_shown.retain(|_, (clip_id, _)| !evicted.contains(clip_id));
+        !evicted.is_empty()
+    }
+
+    /// Draw the 3D preview of `r` as seen at time `t` into `target` (`width` x `height`,
+    /// in `self.format`) and submit it.
+    /// `timed` measures the pass's GPU time for the frame metrics.
+    fn render_preview(&mut self, app: &AppState, t: f64, r: &Resolved, target: &wgpu::TextureView, (width, height): (u32, u32), timed: bool) {
+        let device = &self.device;
+        let queue = &self.queue;
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("frame") });
+
+        // Update camera: the scene's camera node, or a default overview without one
+        let aspect = width as f32 / height.max(1) as f32;
+        let vp = match app.camera_at(t) {
+            Some(cam) => cam.view_proj(aspect),
+            None => {
+                let view_m = Mat4::look_at_rh(Vec3::new(6.0, 4.0, 10.0), Vec3::new(0.0, 2.0, 0.0), Vec3::Y);
+                Mat4::perspective_rh_gl(45f32.to_radians(), aspect.max(0.01), 0.1, 1000.0) * view_m
+            }
+        };
+        queue.write_buffer(&self.camera_buffer, 0, bytemuck::bytes_of(&vp.to_cols_array_2d()));
+
+        let inst_raw: Vec<InstanceGpu> = r.insts.iter().map(|i| i.gpu()).collect();
+        let needed = (inst_raw.len() * std::mem::size_of::<InstanceGpu>()) as u64;
+        if needed > self.inst_vb.size() {
+            self.inst_vb = device.create_buffer(&wgpu::BufferDescriptor{
+                label: Some("inst-vb"),
+                size: needed.next_power_of_two(),
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+        }
+        if !inst_raw.is_empty() {
+            queue.write_buffer(&self.inst_vb, 0, bytemuck::cast_slice(&inst_raw));
+        }
+        // Mesh instances that are loaded, grouped by mesh so each is drawn in one call
+        let mut by_mesh: Vec<(MeshKey, Vec<MeshInstanceGpu>)> = Vec::new();
+        for m in &r.meshes {
+            let key = mesh_key(m);
+            if self.mesh_cache.get(&key).is_none() {
+                continue;
+            }
+            match by_mesh.iter_mut().find(|(k, _)| *k == key) {
+                Some((_, list)) => list.push(m.gpu()),
+                None => by_mesh.push((key, vec![m.gpu()])),
             }
         }
+        let mesh_raw: Vec<MeshInstanceGpu> = by_mesh.iter().flat_map(|(_, list)| list.iter().copied()).collect();
+        let needed = (mesh_raw.len() * std::mem::size_of::<MeshInstanceGpu>()) as u64;
+        if needed > self.mesh_inst_vb.size() {
+            self.mesh_inst_vb = device.create_buffer(&wgpu::BufferDescriptor{
+                label: Some("mesh-inst-vb"),
+                size: needed.next_power_of_two(),
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+        }
+        if !mesh_raw.is_empty() {
+            queue.write_buffer(&self.mesh_inst_vb, 0, bytemuck::cast_slice(&mesh_raw));
+        }
+        let depth_size = wgpu::Extent3d { width, height, depth_or_array_layers: 1 };
+        if self.depth.as_ref().map(|d| d.size() != depth_size).unwrap_or(true) {
+            self.depth = Some(device.create_texture(&wgpu::TextureDescriptor{
+                label: Some("preview-depth"),
+                size: depth_size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: DEPTH_FORMAT,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                view_formats: &[],
+            }));
+        }
+        let depth_view = self.depth.as_ref().map(|d| d.create_view(&wgpu::TextureViewDescriptor::default()));
 
-        fn load_image_rgba(uri: &str) -> Option<(Vec<u8>, u32, u32)> {
-            // Expect file:// URIs
-            let path = if let Ok(u) = url::Url::parse(uri) {
-                if u.scheme() == "file" {
-                    u.to_file_path().ok()?
-                } else { return None }
-            } else {
-                std::path::PathBuf::from(uri)
-            };
-            let img = image::open(path).ok()?.to_rgba8();
-            let (w, h) = img.dimensions();
-            Some((img.into_raw(), w, h))
+        let timer = self.gpu_timer.as_ref().filter(|t| timed && !t.busy);
+        let measured = timer.is_some();
+        {
+            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("main-pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: target,
+                    resolve_target: None,
+                    ops: wgpu::Operations { load: wgpu::LoadOp::Clear(self.clear_color), store: wgpu::StoreOp::Store },
+                })],
+                depth_stencil_attachment: depth_view.as_ref().map(|view| wgpu::RenderPassDepthStencilAttachment {
+                    view,
+                    depth_ops: Some(wgpu::Operations { load: wgpu::LoadOp::Clear(1.0), store: wgpu::StoreOp::Discard }),
+                    stencil_ops: None,
+                }),
+                timestamp_writes: timer.map(GpuTimer::timestamp_writes),
+                occlusion_query_set: None,
+            });
+            rpass.set_bind_group(0, &self.camera_bg, &[]);
+            // Meshes first so screens blend over whatever is behind them
+            rpass.set_pipeline(&self.mesh_pipeline);
+            rpass.set_vertex_buffer(1, self.mesh_inst_vb.slice(..));
+            let mut first = 0u32;
+            for (key, list) in &by_mesh {
+                let Some(mesh) = self.mesh_cache.get(key) else { continue };
+                let n = list.len() as u32;
+                rpass.set_vertex_buffer(0, mesh.vertices.slice(..));
+                rpass.set_index_buffer(mesh.indices.slice(..), wgpu::IndexFormat::Uint32);
+                rpass.draw_indexed(0..mesh.index_count, 0, first..first + n);
+                first += n;
+            }
+            rpass.set_pipeline(&self.preview_pipeline);
+            rpass.set_vertex_buffer(0, self.quad_vb.slice(..));
+            rpass.set_vertex_buffer(1, self.inst_vb.slice(..));
+            for (i, bg) in r.bind_groups.iter().enumerate() {
+                rpass.set_bind_group(1, bg, &[]);
+                let ii = i as u32;
+                rpass.draw(0..6, ii..ii+1);
+            }
+        }
+        let timer = self.gpu_timer.as_mut().filter(|_| measured);
+        if let Some(timer) = &timer {
+            timer.resolve(&mut encoder);
+        }
+        queue.submit(Some(encoder.finish()));
+        if let Some(timer) = timer {
+            timer.map();
+        }
+    }
+
+    /// Draw the live frame at the current transport time into `target` and submit it.
+    /// Returns what each screen showed, for output windows.
+    fn draw(&mut self, app: &AppState, target: &wgpu::TextureView, width: u32, height: u32) -> HashMap<String, ScreenFrame> {
+        let t_now = app.current_time();
+
+        // Pick up finished loads and start opening clips coming up on the timeline
+        self.tex_cache.begin_frame();
+        let mut media_changed = self.tex_cache.poll();
+        self.mesh_cache.poll(&self.device);
+        let upcoming = app.upcoming_clips(t_now, PREFETCH_SECONDS);
+        for clip in &upcoming {
+            self.tex_cache.request(clip);
+        }
+
+        // Evaluate instances (including animated transforms) at the current time
+        let resolved = self.resolve(app, t_now);
+        self.render_preview(app, t_now, &resolved, target, (width, height), true);
+
+        // Keep texture memory under budget, sparing what's on screen or coming up
+        let keep: HashSet<String> = upcoming.into_iter().map(|c| c.clip_id).collect();
+        media_changed |= self.evict(&keep);
+        if media_changed {
+            app.set_media_load_state(self.tex_cache.load_state());
+        }
+
+        resolved.screen_frames()
+    }
+
+    /// Record a presented frame that took `cpu` on the render thread, against a
+    /// display refresh of `period`. Publishes the frame metrics about once a second.
+    fn end_frame(&mut self, app: &AppState, cpu: Duration, period: Duration) {
+        if let Some(timer) = self.gpu_timer.as_mut() {
+            self.device.poll(wgpu::Maintain::Poll);
+            if let Some(gpu) = timer.read() {
+                self.timing.gpu.push(gpu);
+            }
+        }
+        let t = &mut self.timing;
+        t.cpu.push(cpu);
+        let now = Instant::now();
+        if let Some(prev) = t.last_present.replace(now) {
+            let interval = now - prev;
+            t.interval.push(interval);
+            // A frame n refresh periods after the previous one missed n - 1 refreshes
+            let periods = (interval.as_secs_f64() / period.as_secs_f64().max(1e-6)).round() as u64;
+            t.dropped += periods.saturating_sub(1);
+        }
+        t.frames += 1;
+        t.since_publish += 1;
+        let elapsed = t.published.elapsed();
+        if elapsed >= Duration::from_secs(1) {
+            let fps = t.since_publish as f64 / elapsed.as_secs_f64();
+            app.set_texture_cache_stats(self.tex_cache.stats());
+            app.set_frame_stats(FrameStats {
+                fps,
+                frames: t.frames,
+                dropped: t.dropped,
+                cpu: t.cpu.percentiles(),
+                gpu: t.gpu.percentiles(),
+                interval: t.interval.percentiles(),
+                upload: self.tex_cache.upload_times.percentiles(),
+                decode: self.tex_cache.decode_times.percentiles(),
+            });
+            t.since_publish = 0;
+            t.published = Instant::now();
+        }
+    }
+
+    fn canvas_gpu(&self) -> CanvasGpu<'_> {
+        CanvasGpu {
+            device: &self.device,
+            queue: &self.queue,
+            format: self.format,
+            camera_bgl: &self.camera_bgl,
+            pipeline: &self.pipeline,
+            quad_vb: &self.quad_vb,
         }
+    }
+
+    /// Render the 3D preview of `r` at time `t` offscreen and read it back as RGBA8.
+    pub fn read_preview(&mut self, app: &AppState, t: f64, r: &Resolved, (width, height): (u32, u32)) -> Result<Vec<u8>, String> {
+        let tex = self.capture_target(width, height);
+        let view = tex.create_view(&wgpu::TextureViewDescriptor::default());
+        self.render_preview(app, t, r, &view, (width, height), false);
+        read_rgba(&self.device, &self.queue, &tex)
+    }
+
+    /// Render one screen's canvas (`size` pixels) offscreen and read it back as RGBA8.
+    pub fn read_canvas(
+        &self,
+        screen: &str,
+        size: (u32, u32),
+        sizes: &HashMap<String, (u32, u32)>,
+        frames: &HashMap<String, ScreenFrame>,
+    ) -> Result<Vec<u8>, String> {
+        let tex = self.capture_target(size.0, size.1);
+        let view = tex.create_view(&wgpu::TextureViewDescriptor::default());
+        let gpu = self.canvas_gpu();
+        CanvasPass::new(&gpu, 1).draw(&gpu, &view, size, &[(screen.to_string(), 0, 0)], sizes, frames);
+        read_rgba(&self.device, &self.queue, &tex)
+    }
+
+    fn capture_target(&self, width: u32, height: u32) -> wgpu::Texture {
+        self.device.create_texture(&wgpu::TextureDescriptor{
+            label: Some("capture-target"),
+            size: wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        })
+    }
+
+    /// Run queued frame captures. `preview` is the live preview size, used when a
+    /// capture doesn't ask for one.
+    fn service_captures(&mut self, app: &AppState, preview: (u32, u32)) {
+        let mut jobs = std::mem::take(&mut self.pending_captures);
+        jobs.extend(app.take_captures());
+        for job in jobs {
+            if let Some(job) = self.capture(app, job, preview) {
+                self.pending_captures.push(job);
+            }
+        }
+    }
+
+    /// Render and answer one capture, or hand it back if its clips are still loading
+    /// and it may wait longer. Captures at a requested time go to `queue_offline_capture`.
+    fn capture(&mut self, app: &AppState, job: CaptureJob, preview: (u32, u32)) -> Option<CaptureJob> {
+        if job.req.time_seconds.is_some() {
+            self.queue_offline_capture(app, job, preview);
+            return None;
+        }
+        let t = app.current_time();
+        let resolved = self.resolve(app, t);
+        let screen = &job.req.screen_node_id;
+        let loading = if screen.is_empty() { !resolved.loading.is_empty() || resolved.meshes_loading } else { resolved.loading.contains(screen) };
+        if loading && Instant::now() < job.deadline {
+            return Some(job);
+        }
+        let captured = self.read_capture(app, t, &resolved, &job.req, preview);
+        reply_capture(job, captured, t, if loading { "captured with clips still loading" } else { "captured" });
+        None
+    }
+
+    /// Hand a capture at a requested time to the offline capture thread, starting it
+    /// on first use. Refused while `OFFLINE_CAPTURE_QUEUE` captures are already waiting.
+    fn queue_offline_capture(&mut self, app: &AppState, job: CaptureJob, preview: (u32, u32)) {
+        let t = job.req.time_seconds.unwrap_or_default();
+        if self.offline_captures.is_none() {
+            let (tx, rx) = mpsc::sync_channel(OFFLINE_CAPTURE_QUEUE);
+            let (app, gpu) = (app.clone(), self.gpu());
+            if let Err(e) = std::thread::Builder::new().name("capture".into()).spawn(move || offline_capture_loop(app, gpu, rx)) {
+                reply_capture(job, Err(format!("capture thread not started: {e}")), t, "");
+                return;
+            }
+            self.offline_captures = Some(tx);
+        }
+        let Some(tx) = &self.offline_captures else { return };
+        match tx.try_send((job, preview)) {
+            Ok(()) => {}
+            Err(mpsc::TrySendError::Full((job, _))) => {
+                reply_capture(job, Err("too many captures at a requested time are queued; try again later".into()), t, "");
+            }
+            Err(mpsc::TrySendError::Disconnected((job, _))) => {
+                self.offline_captures = None;
+                reply_capture(job, Err("capture thread stopped".into()), t, "");
+            }
+        }
+    }
+
+    /// Render what `req` asks for, at time `t`: the 3D preview (at the requested size,
+    /// else `preview`) or one screen's canvas. Returns RGBA8 pixels and their size.
+    fn read_capture(&mut self, app: &AppState, t: f64, r: &Resolved, req: &CaptureFrameRequest, preview: (u32, u32)) -> Result<(Vec<u8>, u32, u32), String> {
+        let screen = &req.screen_node_id;
+        if screen.is_empty() {
+            let (w, h) = if req.width > 0 && req.height > 0 { (req.width, req.height) } else { preview };
+            return self.read_preview(app, t, r, (w, h)).map(|px| (px, w, h));
+        }
+        let sizes = app.screen_sizes();
+        match sizes.get(screen) {
+            None => Err(format!("no screen '{screen}' with a canvas in the scene")),
+            Some(&(w, h)) => self.read_canvas(screen, (w, h), &sizes, &r.screen_frames()).map(|px| (px, w, h)),
+        }
+    }
+}
+
+/// Captures at a requested time waiting for the offline capture thread, at most.
+const OFFLINE_CAPTURE_QUEUE: usize = 4;
+
+/// Render captures at their requested time like export frames, one after another,
+/// on one offline renderer that decodes exactly that frame of every clip. It is
+/// reused across captures and leaves live playback and its streaming textures alone.
+fn offline_capture_loop(app: AppState, gpu: Gpu, jobs: mpsc::Receiver<(CaptureJob, (u32, u32))>) {
+    let mut renderer: Option<FrameRenderer> = None;
+    for (job, preview) in jobs {
+        // The caller gave up while this waited in the queue
+        if job.reply.is_closed() {
+            continue;
+        }
+        let cfg = app.render_config();
+        let renderer = renderer.get_or_insert_with(|| FrameRenderer::offline(gpu.clone(), &cfg));
+        renderer.clear_color = to_color(cfg.clear_color);
+        let t = job.req.time_seconds.unwrap_or_else(|| app.current_time());
+        let resolved = renderer.resolve_offline(&app, t);
+        let captured = renderer.read_capture(&app, t, &resolved, &job.req, preview);
+        reply_capture(job, captured, t, "captured");
+    }
+}
+
+fn reply_capture(job: CaptureJob, captured: Result<(Vec<u8>, u32, u32), String>, t: f64, message: &str) {
+    let resp = match captured.and_then(|(px, w, h)| encode_png(px, w, h).map(|png| (png, w, h))) {
+        Ok((png, width, height)) => CaptureFrameResponse { ok: true, message: message.into(), png, width, height, time_seconds: t },
+        Err(message) => CaptureFrameResponse { ok: false, message, time_seconds: t, ..Default::default() },
+    };
+    let _ = job.reply.send(resp);
+}
+
+/// Screen instances for one frame and the texture each shows, and mesh instances.
+pub struct Resolved {
+    insts: Vec<InstanceData>,
+    bind_groups: Vec<Rc<wgpu::BindGroup>>,
+    /// Screens showing a placeholder because their clip is still loading
+    loading: HashSet<String>,
+    meshes: Vec<MeshInstance>,
+    /// Some mesh isn't drawn yet because its asset is still loading
+    meshes_loading: bool,
+}
+
+fn mesh_key(m: &MeshInstance) -> MeshKey {
+    (m.uri.clone(), m.node.clone())
+}
+
+impl Resolved {
+    pub fn screen_frames(&self) -> HashMap<String, ScreenFrame> {
+        self.insts.iter().zip(&self.bind_groups)
+            .map(|(inst, bg)| (inst.node_id.clone(), ScreenFrame { bind_group: bg.clone(), opacity: inst.opacity }))
+            .collect()
+    }
+}
+
+/// Put `window` in borderless fullscreen on the configured monitor, or back in a window.
+fn apply_window(window: &Window, elwt: &EventLoopWindowTarget<()>, cfg: &RenderConfig) {
+    if !cfg.fullscreen {
+        window.set_fullscreen(None);
+        return;
+    }
+    match find_monitor(elwt, &cfg.monitor) {
+        Ok(monitor) => window.set_fullscreen(Some(Fullscreen::Borderless(monitor))),
+        Err(e) => warn!("preview window not made fullscreen: {e}"),
+    }
+}
+
+/// A device for rendering without a surface, on a software adapter when no GPU is available.
+fn offscreen_device() -> Result<(wgpu::Device, wgpu::Queue, wgpu::AdapterInfo)> {
+    let instance = wgpu::Instance::default();
+    let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+        power_preference: wgpu::PowerPreference::HighPerformance,
+        compatible_surface: None,
+        force_fallback_adapter: false,
+    }))
+    .or_else(|| {
+        warn!("no GPU adapter, falling back to a software adapter");
+        pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::LowPower,
+            compatible_surface: None,
+            force_fallback_adapter: true,
+        }))
+    })
+    .ok_or_else(|| anyhow::anyhow!("no adapter (not even a software one)"))?;
+    let (device, queue) = pollster::block_on(adapter.request_device(
+        &wgpu::DeviceDescriptor {
+            label: Some("device"),
+            required_features: adapter.features() & wgpu::Features::TIMESTAMP_QUERY,
+            required_limits: wgpu::Limits::downlevel_defaults().using_resolution(adapter.limits()),
+        },
+        None,
+    ))?;
+    Ok((device, queue, adapter.get_info()))
+}
+
+/// A 1x1 texture of one color, bound for sampling.
+fn solid_bind_group(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    tex_bgl: &wgpu::BindGroupLayout,
+    sampler: &wgpu::Sampler,
+    name: &str,
+    rgba: [u8; 4],
+) -> Rc<wgpu::BindGroup> {
+    let tex = device.create_texture_with_data(
+        queue,
+        &wgpu::TextureDescriptor{
+            label: Some(&format!("{name}-tex")),
+            size: wgpu::Extent3d { width: 1, height: 1, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        },
+        wgpu::util::TextureDataOrder::LayerMajor,
+        &rgba,
+    );
+    let view = tex.create_view(&wgpu::TextureViewDescriptor::default());
+    Rc::new(device.create_bind_group(&wgpu::BindGroupDescriptor{
+        label: Some(&format!("{name}-bg")),
+        layout: tex_bgl,
+        entries: &[
+            wgpu::BindGroupEntry{ binding:0, resource: wgpu::BindingResource::TextureView(&view) },
+            wgpu::BindGroupEntry{ binding:1, resource: wgpu::BindingResource::Sampler(sampler) },
+        ],
+    }))
+}
+
+impl Renderer {
+    /// Run the render loop on this thread until the window closes (or forever when
+    /// headless). Settings sent on `reload` are applied between frames.
+    pub fn run(app: AppState, cfg: RenderConfig, reload: mpsc::Receiver<RenderConfig>) -> Result<()> {
+        if cfg.headless {
+            return Self::run_headless(app, cfg, reload);
+        }
+    let event_loop = EventLoop::new().expect("create event loop");
+    let window = WindowBuilder::new()
+            .with_title(cfg.title.as_str())
+            .with_inner_size(LogicalSize::new(cfg.width as f64, cfg.height as f64))
+            .build(&event_loop)?;
+    apply_window(&window, &event_loop, &cfg);
+    let window = Arc::new(window);
+
+        // WGPU setup
+        let instance = wgpu::Instance::default();
+    let surface = instance.create_surface(window.as_ref())?;
+        let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            compatible_surface: Some(&surface),
+            force_fallback_adapter: false,
+        }))
+        .expect("no adapter");
+
+        let (device, queue) = pollster::block_on(adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                label: Some("device"),
+                required_features: adapter.features() & wgpu::Features::TIMESTAMP_QUERY,
+                required_limits: wgpu::Limits::default(),
+            },
+            None,
+        ))?;
+
+        let size = window.inner_size();
+        let surface_caps = surface.get_capabilities(&adapter);
+        let surface_format = surface_caps
+            .formats
+            .iter()
+            .copied()
+            .find(|f| f.is_srgb())
+            .unwrap_or(surface_caps.formats[0]);
+
+        let mut config = SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format: surface_format,
+            width: size.width.max(1),
+            height: size.height.max(1),
+            present_mode: cfg.vsync.present_mode(&surface_caps.present_modes),
+            alpha_mode: surface_caps.alpha_modes[0],
+            view_formats: vec![],
+            desired_maximum_frame_latency: 2,
+        };
+        surface.configure(&device, &config);
 
-        let mut tex_cache = TexCache::new();
+        let mut renderer = FrameRenderer::new(Arc::new(device), Arc::new(queue), surface_format, &cfg);
+        app.set_gpu(renderer.gpu());
+        app.set_render_config(cfg.clone());
+        app.set_ready(true);
 
-        let mut last_fps = Instant::now();
-        let mut frames: u32 = 0;
+        // Per-screen output windows, rebuilt whenever the output configuration changes
+        let mut outputs: Vec<Output> = Vec::new();
+        let mut outputs_rev = 0;
 
+        let mut current = cfg;
+        let mut refresh = refresh_period(&window);
         let win2 = window.clone();
+        let main_id = window.id();
         let res = event_loop.run(move |event, elwt| match event {
+            Event::WindowEvent { window_id, event } if window_id != main_id => match event {
+                WindowEvent::CloseRequested => outputs.retain(|o| o.window_id() != window_id),
+                WindowEvent::Resized(new_size) => {
+                    if let Some(o) = outputs.iter_mut().find(|o| o.window_id() == window_id) {
+                        o.resize(&renderer.device, new_size);
+                    }
+                }
+                _ => {}
+            },
             Event::WindowEvent { event, .. } => match event {
                 WindowEvent::CloseRequested => elwt.exit(),
                 WindowEvent::Resized(new_size) => {
                     config.width = new_size.width.max(1);
                     config.height = new_size.height.max(1);
-                    surface.configure(&device, &config);
+                    surface.configure(&renderer.device, &config);
                 }
                 WindowEvent::RedrawRequested => {
+                    // Fire timeline events crossed since the last frame before sampling time
+                    app.tick();
                     // Render frame
                     match surface.get_current_texture() {
                         Ok(frame) => {
+                            // Frame CPU time leaves out waiting for the swapchain
+                            let started = Instant::now();
                             let view_tex = frame.texture.create_view(&wgpu::TextureViewDescriptor::default());
-                            let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("frame") });
-
-                            // Update camera
-                            let eye = Vec3::new(6.0, 4.0, 10.0);
-                            let target = Vec3::new(0.0, 2.0, 0.0);
-                            let up = Vec3::Y;
-                            let view_m = Mat4::look_at_rh(eye, target, up);
-                            let aspect = config.width as f32 / config.height as f32;
-                            let proj = Mat4::perspective_rh_gl(45f32.to_radians(), aspect.max(0.01), 0.1, 1000.0);
-                            let vp = proj * view_m;
-                            queue.write_buffer(&camera_buffer, 0, bytemuck::bytes_of(&vp.to_cols_array_2d()));
-
-                            // Update instances from app state
-                            let insts = app.instances();
-                            let inst_raw: Vec<[[f32;4];4]> = insts.iter().map(|i| i.model).collect();
-                            let needed = (inst_raw.len() * std::mem::size_of::<[[f32;4];4]>()) as u64;
-                            if needed > inst_vb.size() {
-                                inst_vb = device.create_buffer(&wgpu::BufferDescriptor{
-                                    label: Some("inst-vb"),
-                                    size: needed.next_power_of_two(),
-                                    usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
-                                    mapped_at_creation: false,
-                                });
-                            }
-                            if !inst_raw.is_empty() {
-                                queue.write_buffer(&inst_vb, 0, bytemuck::cast_slice(&inst_raw));
-                            }
-
-                            // Render
-                            {
-                                let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                                    label: Some("main-pass"),
-                                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                                        view: &view_tex,
-                                        resolve_target: None,
-                                        ops: wgpu::Operations { load: wgpu::LoadOp::Clear(wgpu::Color { r: 0.02, g: 0.02, b: 0.03, a: 1.0 }), store: wgpu::StoreOp::Store },
-                                    })],
-                                    depth_stencil_attachment: None,
-                                    timestamp_writes: None,
-                                    occlusion_query_set: None,
-                                });
-                                rpass.set_pipeline(&pipeline);
-                                rpass.set_bind_group(0, &camera_bg, &[]);
-                                rpass.set_vertex_buffer(0, quad_vb.slice(..));
-                                rpass.set_vertex_buffer(1, inst_vb.slice(..));
-                                // Determine current time and draw each screen with its texture
-                                let t_now = app.current_time();
-                                for (i, inst) in insts.iter().enumerate() {
-                                    let bg = if let Some((clip_id, uri)) = app.active_clip_for_node(&inst.node_id, t_now) {
-                                        tex_cache.get_or_load(&clip_id, &uri, &device, &queue, &tex_bgl, &linear_sampler).unwrap_or_else(|| white_bg.clone())
-                                    } else {
-                                        white_bg.clone()
-                                    };
-                                    rpass.set_bind_group(1, &bg, &[]);
-                                    let ii = i as u32;
-                                    rpass.draw(0..6, ii..ii+1);
-                                }
-                            }
-                            queue.submit(Some(encoder.finish()));
+                            let screen_frames = renderer.draw(&app, &view_tex, config.width, config.height);
+                            let drawn = started.elapsed();
                             frame.present();
+                            app.set_ready(true);
+                            let presented = Instant::now();
 
-                            frames += 1;
-                            if last_fps.elapsed() >= Duration::from_secs(1) {
-                                let elapsed = last_fps.elapsed().as_secs_f64();
-                                let fps = frames as f64 / elapsed.max(1e-6);
-                                app.set_metrics_fps(fps);
-                                frames = 0;
-                                last_fps = Instant::now();
+                            // Output windows show the same screen textures flat at native resolution
+                            if !outputs.is_empty() {
+                                let sizes = app.screen_sizes();
+                                let gpu = renderer.canvas_gpu();
+                                for o in &mut outputs {
+                                    o.render(&gpu, &sizes, &screen_frames);
+                                }
                             }
+                            renderer.service_captures(&app, (config.width, config.height));
+                            renderer.end_frame(&app, drawn + presented.elapsed(), refresh);
                         }
+                        // A timeout is a skipped frame; anything else leaves nothing on screen
+                        Err(wgpu::SurfaceError::Timeout) => warn!("Surface timeout, skipping frame"),
                         Err(err) => {
-                            eprintln!("Surface error: {err:?}, reconfiguring...");
-                            surface.configure(&device, &config);
+                            warn!("Surface error: {err:?}, reconfiguring...");
+                            app.set_ready(false);
+                            surface.configure(&renderer.device, &config);
                         }
                     }
                 }
                 _ => {}
             },
             Event::AboutToWait => {
+                while let Ok(new) = reload.try_recv() {
+                    app.set_render_config(new.clone());
+                    renderer.clear_color = to_color(new.clear_color);
+                    if new.vsync != current.vsync {
+                        config.present_mode = new.vsync.present_mode(&surface_caps.present_modes);
+                        surface.configure(&renderer.device, &config);
+                    }
+                    if (&new.title, new.width, new.height, new.fullscreen, &new.monitor)
+                        != (&current.title, current.width, current.height, current.fullscreen, &current.monitor)
+                    {
+                        win2.set_title(&new.title);
+                        if !new.fullscreen {
+                            let _ = win2.request_inner_size(LogicalSize::new(new.width as f64, new.height as f64));
+                        }
+                        apply_window(&win2, elwt, &new);
+                        refresh = refresh_period(&win2);
+                    }
+                    current = new;
+                }
+                if let Some(cfg) = app.outputs_since(&mut outputs_rev) {
+                    outputs.clear();
+                    let sizes = app.screen_sizes();
+                    let gpu = renderer.canvas_gpu();
+                    for w in &cfg.windows {
+                        match Output::open(elwt, w, &sizes, &instance, &adapter, &gpu) {
+                            Ok(o) => outputs.push(o),
+                            Err(e) => warn!("output '{}' not opened: {e}", w.id),
+                        }
+                    }
+                }
                 // Request a redraw on each loop (simple animation tick)
                 win2.request_redraw();
             }
@@ -384,4 +1381,178 @@ impl Renderer {
             Err(e) => Err(anyhow::anyhow!("event loop error: {e}")),
         }
     }
+
+    /// Render without a window into an offscreen texture, at `HEADLESS_FPS`, until the
+    /// process is stopped. Uses a software adapter when no GPU is available.
+    fn run_headless(app: AppState, cfg: RenderConfig, reload: mpsc::Receiver<RenderConfig>) -> Result<()> {
+        let (device, queue, info) = offscreen_device()?;
+        info!("Headless rendering on {} ({:?})", info.name, info.device_type);
+
+        let mut renderer = FrameRenderer::new(Arc::new(device), Arc::new(queue), OFFSCREEN_FORMAT, &cfg);
+        app.set_gpu(renderer.gpu());
+        app.set_render_config(cfg.clone());
+        // No surface to lose: ready as soon as the device exists
+        app.set_ready(true);
+        let (mut width, mut height) = (cfg.width.max(1), cfg.height.max(1));
+        let mut target = renderer.capture_target(width, height);
+        let mut target_view = target.create_view(&wgpu::TextureViewDescriptor::default());
+        let mut outputs_rev = 0;
+        let frame_time = Duration::from_secs_f64(1.0 / HEADLESS_FPS);
+        loop {
+            let started = Instant::now();
+            while let Ok(new) = reload.try_recv() {
+                app.set_render_config(new.clone());
+                renderer.clear_color = to_color(new.clear_color);
+                if (new.width.max(1), new.height.max(1)) != (width, height) {
+                    (width, height) = (new.width.max(1), new.height.max(1));
+                    target = renderer.capture_target(width, height);
+                    target_view = target.create_view(&wgpu::TextureViewDescriptor::default());
+                }
+            }
+            if let Some(outputs) = app.outputs_since(&mut outputs_rev) {
+                if !outputs.windows.is_empty() {
+                    warn!("output windows are not opened in headless mode");
+                }
+            }
+            app.tick();
+            renderer.draw(&app, &target_view, width, height);
+            renderer.service_captures(&app, (width, height));
+            renderer.device.poll(wgpu::Maintain::Poll);
+            renderer.end_frame(&app, started.elapsed(), frame_time);
+            if let Some(rest) = frame_time.checked_sub(started.elapsed()) {
+                std::thread::sleep(rest);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::{Path, PathBuf};
+
+    /// A 1x1 PNG under the temp dir, unique to `name` and this process.
+    fn still(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("constellation-tex-{name}-{}.png", std::process::id()));
+        image::RgbaImage::from_pixel(1, 1, image::Rgba([255, 0, 0, 255])).save(&path).unwrap();
+        path
+    }
+
+    fn missing(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("constellation-tex-{name}-{}.png", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    fn clip(uri: &Path) -> ActiveClip {
+        ActiveClip { clip_id: "clip".into(), uri: uri.to_string_lossy().into_owned(), media_time: 0.0, fps: 0.0 }
+    }
+
+    fn slot(cache: &TexCache) -> &Slot {
+        &cache.map["clip"].slot
+    }
+
+    fn blocking() -> TexCache {
+        let mut cache = TexCache::new(u64::MAX);
+        cache.blocking = true;
+        cache
+    }
+
+    #[test]
+    fn reload_with_a_new_uri_reopens_the_clip() {
+        let (bad, good) = (missing("reload-bad"), still("reload-good"));
+        let mut cache = blocking();
+        cache.request(&clip(&bad));
+        assert!(matches!(slot(&cache), Slot::Failed { attempts: 1, .. }));
+        // Same source: not reopened before the backoff
+        cache.request(&clip(&bad));
+        assert_eq!(cache.misses, 1);
+        // The project now points the clip at a file that exists
+        cache.request(&clip(&good));
+        assert!(matches!(slot(&cache), Slot::Ready(_)));
+        // And a changed fps counts as a new source too
+        cache.request(&ActiveClip { fps: 24.0, ..clip(&good) });
+        assert_eq!(cache.misses, 3);
+        let _ = std::fs::remove_file(good);
+    }
+
+    #[test]
+    fn failed_clips_are_retried_after_a_backoff() {
+        let path = missing("retry");
+        let mut cache = blocking();
+        cache.request(&clip(&path));
+        image::RgbaImage::from_pixel(1, 1, image::Rgba([0, 0, 0, 255])).save(&path).unwrap();
+        cache.request(&clip(&path));
+        let Slot::Failed { attempts: 1, retry_at } = slot(&cache) else { panic!("not failed") };
+        assert!(*retry_at > Instant::now());
+        cache.map.get_mut("clip").unwrap().slot = Slot::Failed { attempts: 1, retry_at: Instant::now() };
+        cache.request(&clip(&path));
+        assert!(matches!(slot(&cache), Slot::Ready(_)));
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_a_minute() {
+        let secs: Vec<u64> = (1..=9).map(|n| retry_backoff(n).as_secs()).collect();
+        assert_eq!(secs, [1, 2, 4, 8, 16, 32, 60, 60, 60]);
+    }
+
+    #[test]
+    fn loads_for_a_replaced_source_are_dropped() {
+        let (old, new) = (still("stale-old"), missing("stale-new"));
+        let mut cache = TexCache::new(u64::MAX);
+        cache.request(&clip(&old));
+        cache.request(&clip(&new));
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while matches!(slot(&cache), Slot::Loading { .. }) && Instant::now() < deadline {
+            cache.poll();
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        // The old URI opens fine, but the clip now points at the missing one
+        assert!(matches!(slot(&cache), Slot::Failed { .. }));
+        let _ = std::fs::remove_file(old);
+    }
+
+    /// Open `id` from `path` as if its texture took `bytes` and was last shown at `last_used`.
+    fn resident(cache: &mut TexCache, id: &str, path: &Path, bytes: u64, last_used: u64) {
+        cache.request(&ActiveClip { clip_id: id.into(), ..clip(path) });
+        let Slot::Ready(e) = &mut cache.map.get_mut(id).unwrap().slot else { panic!("{id} not opened") };
+        e.bytes = bytes;
+        e.last_used = last_used;
+    }
+
+    #[test]
+    fn eviction_drops_least_recently_shown_first() {
+        let path = still("lru");
+        let mut cache = blocking();
+        cache.budget = 250;
+        for (id, last_used) in [("a", 3), ("b", 1), ("c", 2)] {
+            resident(&mut cache, id, &path, 100, last_used);
+        }
+        assert_eq!(cache.evict(&HashSet::new()), ["b"]);
+        assert!(cache.evict(&HashSet::new()).is_empty());
+        cache.budget = 100;
+        assert_eq!(cache.evict(&HashSet::new()), ["c"]);
+        assert_eq!(cache.resident_bytes(), 100);
+        assert!(matches!(cache.map["a"].slot, Slot::Ready(_)));
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn eviction_spares_kept_clips_even_over_budget() {
+        let path = still("keep");
+        let mut cache = blocking();
+        cache.budget = 0;
+        for (id, last_used) in [("a", 1), ("b", 2), ("c", 3)] {
+            resident(&mut cache, id, &path, 100, last_used);
+        }
+        cache.request(&ActiveClip { clip_id: "gone".into(), ..clip(&missing("keep-gone")) });
+        let keep = HashSet::from(["a".to_string()]);
+        assert_eq!(cache.evict(&keep), ["b", "c"]);
+        assert_eq!(cache.resident_bytes(), 100);
+        assert!(matches!(cache.map["a"].slot, Slot::Ready(_)));
+        // Clips without a texture hold no memory and stay
+        assert!(matches!(cache.map["gone"].slot, Slot::Failed { .. }));
+        let _ = std::fs::remove_file(path);
+    }
 }
@@ -1,45 +1,416 @@
 use crate::proto::*;
 use glam::{Mat4, Quat, Vec3};
+use std::collections::{HashMap, HashSet};
 
 #[repr(C)]
 #[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct InstanceGpu {
     pub model: [[f32; 4]; 4],
+    pub opacity: f32,
+    pub _pad: [f32; 3],
 }
 
 #[derive(Clone)]
 pub struct InstanceData {
     pub model: [[f32; 4]; 4],
     pub node_id: String,
+    pub opacity: f32,
 }
 
-fn mat4_from_transform(t: &Transform) -> Mat4 {
+impl InstanceData {
+    pub fn gpu(&self) -> InstanceGpu {
+        InstanceGpu { model: self.model, opacity: self.opacity, _pad: [0.0; 3] }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct MeshInstanceGpu {
+    pub model: [[f32; 4]; 4],
+    pub color: [f32; 4],
+}
+
+/// A mesh component at its node's world transform.
+#[derive(Clone)]
+pub struct MeshInstance {
+    pub model: [[f32; 4]; 4],
+    pub uri: String,
+    /// Node path inside the asset; empty for the whole asset
+    pub node: String,
+    /// Base color of the component's material; white without one
+    pub color: [f32; 4],
+}
+
+impl MeshInstance {
+    pub fn gpu(&self) -> MeshInstanceGpu {
+        MeshInstanceGpu { model: self.model, color: self.color }
+    }
+}
+
+/// Everything drawn in the 3D preview at one time.
+#[derive(Default)]
+pub struct Instances {
+    pub screens: Vec<InstanceData>,
+    pub meshes: Vec<MeshInstance>,
+}
+
+fn decompose(t: &Transform) -> (Vec3, Quat, Vec3) {
     let p = t.position.as_ref().map(|v| Vec3::new(v.x, v.y, v.z)).unwrap_or(Vec3::ZERO);
     let r = t.rotation.as_ref().map(|q| Quat::from_xyzw(q.x, q.y, q.z, q.w)).unwrap_or(Quat::IDENTITY);
     let s = t.scale.as_ref().map(|v| Vec3::new(v.x, v.y, v.z)).unwrap_or(Vec3::ONE);
+    (p, r, s)
+}
+
+/// Evaluate a transform track at time `t`: lerp position/scale, slerp rotation,
+/// holding the first/last key outside the keyed range. Components a key leaves
+/// unset fall back to `base` (the node's static transform).
+pub fn sample_track(track: &TrackTransform, t: f64, base: (Vec3, Quat, Vec3)) -> (Vec3, Quat, Vec3) {
+    let key_trs = |k: &TrackTransformKey| {
+        let Some(kt) = &k.transform else { return base };
+        let (mut p, mut r, mut s) = base;
+        if let Some(v) = &kt.position { p = Vec3::new(v.x, v.y, v.z); }
+        if let Some(q) = &kt.rotation { r = Quat::from_xyzw(q.x, q.y, q.z, q.w).normalize(); }
+        if let Some(v) = &kt.scale { s = Vec3::new(v.x, v.y, v.z); }
+        (p, r, s)
+    };
+    // Keys are not required to be sorted; find the bracketing pair directly.
+    let mut before: Option<&TrackTransformKey> = None;
+    let mut after: Option<&TrackTransformKey> = None;
+    for k in &track.keys {
+        if k.t <= t {
+            if before.map(|b| k.t >= b.t).unwrap_or(true) { before = Some(k); }
+        } else if after.map(|a| k.t < a.t).unwrap_or(true) {
+            after = Some(k);
+        }
+    }
+    match (before, after) {
+        (None, None) => base,
+        (Some(k), None) | (None, Some(k)) => key_trs(k),
+        (Some(a), Some(b)) => {
+            let f = ((t - a.t) / (b.t - a.t)) as f32;
+            let (pa, ra, sa) = key_trs(a);
+            let (pb, rb, sb) = key_trs(b);
+            (pa.lerp(pb, f), ra.slerp(rb, f), sa.lerp(sb, f))
+        }
+    }
+}
+
+/// Transform tracks of a timeline keyed by target node id. When several tracks
+/// target the same node the last one wins.
+fn transform_tracks(timeline: Option<&Timeline>) -> HashMap<&str, &TrackTransform> {
+    let mut out = HashMap::new();
+    for tr in timeline.map(|tl| tl.tracks.as_slice()).unwrap_or_default() {
+        if let Some(timeline_track::Kind::Transform(tt)) = &tr.kind {
+            out.insert(tt.target_node_id.as_str(), tt);
+        }
+    }
+    out
+}
+
+fn local_matrix(node: &Node, tracks: &HashMap<&str, &TrackTransform>, t: f64) -> Mat4 {
+    let base = node.transform.as_ref().map(decompose).unwrap_or((Vec3::ZERO, Quat::IDENTITY, Vec3::ONE));
+    let (p, r, s) = match tracks.get(node.id.as_str()) {
+        Some(track) => sample_track(track, t, base),
+        None => base,
+    };
     Mat4::from_scale_rotation_translation(s, r, p)
 }
 
-pub fn instances_from_project(p: &Project) -> Vec<InstanceData> {
-    let mut out = Vec::new();
+/// Enabled screen and mesh instances with world transforms evaluated at timeline
+/// time `t`, applying the timeline's transform tracks through the node hierarchy.
+pub fn instances_at(p: &Project, timeline: Option<&Timeline>, t: f64) -> Instances {
+    let mut out = Instances::default();
     if let Some(scene) = &p.scene {
+        let walk = Walk {
+            tracks: transform_tracks(timeline),
+            t,
+            materials: scene.materials.iter().map(|m| (m.id.as_str(), m)).collect(),
+            meshes: scene.meshes.iter().map(|m| (m.id.as_str(), m)).collect(),
+        };
         for n in &scene.roots {
-            collect_instances(n, Mat4::IDENTITY, &mut out);
+            collect_instances(n, Mat4::IDENTITY, &walk, &mut out);
         }
     }
     out
 }
 
-fn collect_instances(node: &Node, parent: Mat4, out: &mut Vec<InstanceData>) {
-    let local = node.transform.as_ref().map(mat4_from_transform).unwrap_or(Mat4::IDENTITY);
-    let world = parent * local;
-    match &node.comp0 {
-        Some(node::Comp0::Screen(_s)) => {
-            out.push(InstanceData { model: world.to_cols_array_2d(), node_id: node.id.clone() });
+/// What `collect_instances` looks up while walking the scene.
+struct Walk<'a> {
+    tracks: HashMap<&'a str, &'a TrackTransform>,
+    t: f64,
+    materials: HashMap<&'a str, &'a MaterialPbr>,
+    meshes: HashMap<&'a str, &'a MeshRef>,
+}
+
+fn collect_instances(node: &Node, parent: Mat4, walk: &Walk, out: &mut Instances) {
+    let world = parent * local_matrix(node, &walk.tracks, walk.t);
+    if node.screen().is_some_and(|s| !s.disabled) {
+        out.screens.push(InstanceData { model: world.to_cols_array_2d(), node_id: node.id.clone(), opacity: 1.0 });
+    }
+    for m in node.meshes() {
+        let Some(mesh) = &m.mesh else { continue };
+        // A reference by id to the scene's mesh list, or an inline one
+        let mesh = match walk.meshes.get(mesh.id.as_str()) {
+            Some(r) if mesh.uri.is_empty() => r,
+            _ => mesh,
+        };
+        if mesh.uri.is_empty() {
+            continue;
         }
-        _ => {}
+        let color = walk.materials.get(m.material_id.as_str()).and_then(|m| m.base_color.as_ref()).map_or([1.0; 4], |c| [c.r, c.g, c.b, c.a]);
+        out.meshes.push(MeshInstance { model: world.to_cols_array_2d(), uri: mesh.uri.clone(), node: mesh.node.clone(), color });
     }
     for c in &node.children {
-        collect_instances(c, world, out);
+        collect_instances(c, world, walk, out);
+    }
+}
+
+/// A camera node evaluated at some timeline time.
+pub struct CameraData {
+    /// Camera world transform; the camera looks down its local -Z with +Y up.
+    pub world: Mat4,
+    pub fov_deg: f32,
+    pub near: f32,
+    pub far: f32,
+}
+
+impl CameraData {
+    /// View-projection matrix for a viewport of the given aspect ratio. Unset or
+    /// invalid lens values fall back to 45°, 0.1 and 1000.
+    pub fn view_proj(&self, aspect: f32) -> Mat4 {
+        let fov = if self.fov_deg > 0.0 && self.fov_deg < 180.0 { self.fov_deg } else { 45.0 };
+        let near = if self.near > 0.0 { self.near } else { 0.1 };
+        let far = if self.far > near { self.far } else { 1000.0_f32.max(near * 2.0) };
+        // Scale in the node's world transform must not distort the view
+        let (_, r, p) = self.world.to_scale_rotation_translation();
+        let view = Mat4::from_rotation_translation(r, p).inverse();
+        Mat4::perspective_rh_gl(fov.to_radians(), aspect.max(0.01), near, far) * view
+    }
+}
+
+/// The camera on node `id` (or the first camera in scene order when `id` is `None`)
+/// with its world transform at timeline time `t`.
+pub fn camera_at(p: &Project, timeline: Option<&Timeline>, t: f64, id: Option<&str>) -> Option<CameraData> {
+    fn find(node: &Node, parent: Mat4, tracks: &HashMap<&str, &TrackTransform>, t: f64, id: Option<&str>) -> Option<CameraData> {
+        let world = parent * local_matrix(node, tracks, t);
+        if let Some(c) = node.camera() {
+            if id.map(|id| id == node.id).unwrap_or(true) {
+                return Some(CameraData { world, fov_deg: c.fov_deg, near: c.near, far: c.far });
+            }
+        }
+        node.children.iter().find_map(|c| find(c, world, tracks, t, id))
+    }
+    let tracks = transform_tracks(timeline);
+    p.scene.as_ref()?.roots.iter().find_map(|n| find(n, Mat4::IDENTITY, &tracks, t, id))
+}
+
+/// Canvas size of every enabled screen node with positive `pixels_x`/`pixels_y`, keyed by node id.
+pub fn screen_sizes(p: &Project) -> HashMap<String, (u32, u32)> {
+    fn walk(node: &Node, out: &mut HashMap<String, (u32, u32)>) {
+        if let Some(s) = node.screen() {
+            if !s.disabled && s.pixels_x > 0 && s.pixels_y > 0 {
+                out.insert(node.id.clone(), (s.pixels_x as u32, s.pixels_y as u32));
+            }
+        }
+        for c in &node.children {
+            walk(c, out);
+        }
+    }
+    let mut out = HashMap::new();
+    for n in p.scene.iter().flat_map(|s| &s.roots) {
+        walk(n, &mut out);
+    }
+    out
+}
+
+/// All node ids present in the scene graph, at any depth.
+pub fn node_ids(scene: &Scene) -> HashSet<String> {
+    fn walk(node: &Node, out: &mut HashSet<String>) {
+        out.insert(node.id.clone());
+        for c in &node.children {
+            walk(c, out);
+        }
+    }
+    let mut out = HashSet::new();
+    for n in &scene.roots {
+        walk(n, &mut out);
+    }
+    out
+}
+
+/// Target node ids referenced by tracks of any timeline that do not exist in the scene.
+/// Each missing id is reported once, in track order.
+pub fn missing_track_targets(p: &Project) -> Vec<String> {
+    let ids = p.scene.as_ref().map(node_ids).unwrap_or_default();
+    let mut missing: Vec<String> = Vec::new();
+    for tr in p.timelines.iter().flat_map(|tl| &tl.tracks) {
+        let target = match &tr.kind {
+            Some(timeline_track::Kind::Media(m)) => &m.target_node_id,
+            Some(timeline_track::Kind::Transform(t)) => &t.target_node_id,
+            None => continue,
+        };
+        if !ids.contains(target) && !missing.contains(target) {
+            missing.push(target.clone());
+        }
+    }
+    missing
+}
+
+/// Media track targets that exist in the scene but have no screen component, so
+/// nothing shows their clips. Each id is reported once, in track order.
+pub fn non_screen_media_targets(p: &Project) -> Vec<String> {
+    fn walk<'a>(node: &'a Node, out: &mut HashMap<&'a str, bool>) {
+        out.entry(node.id.as_str()).or_insert(node.screen().is_some());
+        for c in &node.children {
+            walk(c, out);
+        }
+    }
+    let mut has_screen = HashMap::new();
+    for n in p.scene.iter().flat_map(|s| &s.roots) {
+        walk(n, &mut has_screen);
+    }
+    let mut out: Vec<String> = Vec::new();
+    for tr in p.timelines.iter().flat_map(|tl| &tl.tracks) {
+        if let Some(timeline_track::Kind::Media(m)) = &tr.kind {
+            if has_screen.get(m.target_node_id.as_str()) == Some(&false) && !out.contains(&m.target_node_id) {
+                out.push(m.target_node_id.clone());
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto;
+
+    const BASE: (Vec3, Quat, Vec3) = (Vec3::ZERO, Quat::IDENTITY, Vec3::ONE);
+
+    fn v3(v: Vec3) -> proto::Vec3 {
+        proto::Vec3 { x: v.x, y: v.y, z: v.z }
+    }
+
+    fn key(t: f64, position: Option<Vec3>, rotation: Option<Quat>, scale: Option<Vec3>) -> TrackTransformKey {
+        let rotation = rotation.map(|q| proto::Quat { x: q.x, y: q.y, z: q.z, w: q.w });
+        TrackTransformKey { t, transform: Some(Transform { position: position.map(v3), rotation, scale: scale.map(v3) }) }
+    }
+
+    fn at(t: f64, x: f32) -> TrackTransformKey {
+        key(t, Some(Vec3::new(x, 0.0, 0.0)), None, None)
+    }
+
+    fn track(target: &str, keys: Vec<TrackTransformKey>) -> TrackTransform {
+        TrackTransform { target_node_id: target.into(), keys }
+    }
+
+    fn assert_near(a: Vec3, b: Vec3) {
+        assert!(a.abs_diff_eq(b, 1e-5), "{a} != {b}");
+    }
+
+    #[test]
+    fn lerps_position_and_scale_between_keys() {
+        let tr = track("n", vec![key(0.0, Some(Vec3::ZERO), None, Some(Vec3::ONE)), key(2.0, Some(Vec3::new(4.0, 2.0, 0.0)), None, Some(Vec3::splat(3.0)))]);
+        let (p, r, s) = sample_track(&tr, 0.5, BASE);
+        assert_near(p, Vec3::new(1.0, 0.5, 0.0));
+        assert_near(s, Vec3::splat(1.5));
+        assert_eq!(r, Quat::IDENTITY);
+    }
+
+    #[test]
+    fn slerps_rotation_between_keys() {
+        let quarter = Quat::from_rotation_y(std::f32::consts::FRAC_PI_2);
+        let tr = track("n", vec![key(0.0, None, Some(Quat::IDENTITY), None), key(1.0, None, Some(quarter), None)]);
+        let (_, r, _) = sample_track(&tr, 0.5, BASE);
+        assert!(r.abs_diff_eq(Quat::from_rotation_y(std::f32::consts::FRAC_PI_4), 1e-5), "{r}");
+        // Key rotations are normalized before interpolating
+        let scaled = proto::Quat { x: 0.0, y: 0.0, z: 0.0, w: 2.0 };
+        let tr = track("n", vec![TrackTransformKey { t: 0.0, transform: Some(Transform { rotation: Some(scaled), ..Default::default() }) }]);
+        assert!(sample_track(&tr, 0.0, BASE).1.abs_diff_eq(Quat::IDENTITY, 1e-6));
+    }
+
+    #[test]
+    fn clamps_outside_the_keys() {
+        let tr = track("n", vec![at(1.0, 10.0), at(3.0, 30.0)]);
+        assert_near(sample_track(&tr, -5.0, BASE).0, Vec3::new(10.0, 0.0, 0.0));
+        assert_near(sample_track(&tr, 1.0, BASE).0, Vec3::new(10.0, 0.0, 0.0));
+        assert_near(sample_track(&tr, 3.0, BASE).0, Vec3::new(30.0, 0.0, 0.0));
+        assert_near(sample_track(&tr, 99.0, BASE).0, Vec3::new(30.0, 0.0, 0.0));
+        assert_eq!(sample_track(&track("n", vec![]), 1.0, BASE), BASE);
+    }
+
+    #[test]
+    fn unsorted_keys_bracket_by_time() {
+        let tr = track("n", vec![at(4.0, 40.0), at(0.0, 0.0), at(2.0, 20.0), at(1.0, 10.0)]);
+        for (t, x) in [(0.5, 5.0), (1.5, 15.0), (3.0, 30.0), (5.0, 40.0)] {
+            assert_near(sample_track(&tr, t, BASE).0, Vec3::new(x, 0.0, 0.0));
+        }
+    }
+
+    #[test]
+    fn partial_keys_keep_the_node_transform() {
+        let base = (Vec3::new(0.0, 5.0, 0.0), Quat::from_rotation_z(1.0), Vec3::splat(2.0));
+        let (p, r, s) = sample_track(&track("n", vec![at(0.0, 1.0), at(1.0, 3.0)]), 0.5, base);
+        assert_near(p, Vec3::new(2.0, 0.0, 0.0));
+        assert!(r.abs_diff_eq(base.1, 1e-6));
+        assert_near(s, base.2);
+    }
+
+    fn node(id: &str, position: Vec3, components: Vec<component::Kind>, children: Vec<Node>) -> Node {
+        Node {
+            id: id.into(),
+            transform: Some(Transform { position: Some(v3(position)), ..Default::default() }),
+            components: components.into_iter().map(|k| Component { kind: Some(k) }).collect(),
+            children,
+            ..Default::default()
+        }
+    }
+
+    fn screen(disabled: bool) -> component::Kind {
+        component::Kind::Screen(ScreenComponent { pixels_x: 64, pixels_y: 32, disabled })
+    }
+
+    fn mesh(id: &str, uri: &str, material: &str) -> component::Kind {
+        component::Kind::Mesh(MeshComponent { mesh: Some(MeshRef { id: id.into(), uri: uri.into(), node: String::new() }), material_id: material.into() })
+    }
+
+    fn translation(model: &[[f32; 4]; 4]) -> Vec3 {
+        Mat4::from_cols_array_2d(model).w_axis.truncate()
+    }
+
+    fn stage() -> Project {
+        let wall = node("wall", Vec3::new(0.0, 2.0, 0.0), vec![screen(false)], vec![]);
+        let off = node("off", Vec3::ZERO, vec![screen(true)], vec![]);
+        let truss = node("truss", Vec3::new(1.0, 0.0, 0.0), vec![mesh("truss", "", "steel"), mesh("", "", "")], vec![wall, off]);
+        let scene = Scene {
+            roots: vec![truss],
+            meshes: vec![MeshRef { id: "truss".into(), uri: "file:///assets/truss.glb".into(), node: "Truss".into() }],
+            materials: vec![MaterialPbr { id: "steel".into(), base_color: Some(ColorRgba { r: 0.5, g: 0.5, b: 0.6, a: 1.0 }), ..Default::default() }],
+            ..Default::default()
+        };
+        Project { scene: Some(scene), ..Default::default() }
+    }
+
+    #[test]
+    fn instances_follow_the_hierarchy_and_tracks() {
+        let p = stage();
+        let still = instances_at(&p, None, 1.0);
+        assert_eq!(still.screens.iter().map(|s| s.node_id.as_str()).collect::<Vec<_>>(), ["wall"]);
+        assert_near(translation(&still.screens[0].model), Vec3::new(1.0, 2.0, 0.0));
+
+        let tl = Timeline { tracks: vec![TimelineTrack { kind: Some(timeline_track::Kind::Transform(track("truss", vec![at(0.0, 0.0), at(2.0, 4.0)]))) }], ..Default::default() };
+        let moving = instances_at(&p, Some(&tl), 1.0);
+        assert_near(translation(&moving.screens[0].model), Vec3::new(2.0, 2.0, 0.0));
+        assert_near(translation(&moving.meshes[0].model), Vec3::new(2.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn mesh_instances_resolve_refs_and_materials() {
+        let inst = instances_at(&stage(), None, 0.0);
+        // The second mesh component has neither a ref nor a URI
+        assert_eq!(inst.meshes.len(), 1);
+        let m = &inst.meshes[0];
+        assert_eq!((m.uri.as_str(), m.node.as_str()), ("file:///assets/truss.glb", "Truss"));
+        assert_eq!(m.color, [0.5, 0.5, 0.6, 1.0]);
     }
 }
@@ -0,0 +1,45 @@
+struct Camera {
+  view_proj: mat4x4<f32>,
+};
+@group(0) @binding(0) var<uniform> camera: Camera;
+
+struct VsIn {
+  @location(0) pos: vec3<f32>,
+  @location(1) normal: vec3<f32>,
+  @location(2) color: vec4<f32>,
+  @location(3) m0: vec4<f32>,
+  @location(4) m1: vec4<f32>,
+  @location(5) m2: vec4<f32>,
+  @location(6) m3: vec4<f32>,
+  @location(7) tint: vec4<f32>,
+};
+
+struct VsOut {
+  @builtin(position) pos: vec4<f32>,
+  @location(0) normal: vec3<f32>,
+  @location(1) color: vec4<f32>,
+};
+
+@vertex
+fn vs_main(input: VsIn) -> VsOut {
+  var out: VsOut;
+  let model = mat4x4<f32>(input.m0, input.m1, input.m2, input.m3);
+  out.pos = camera.view_proj * model * vec4<f32>(input.pos, 1.0);
+  // Good enough without non-uniform scale; lighting is only for telling surfaces apart
+  out.normal = (model * vec4<f32>(input.normal, 0.0)).xyz;
+  out.color = input.color * input.tint;
+  return out;
+}
+
+// Fixed key light from above and in front, plus ambient so nothing goes fully black
+const LIGHT_DIR: vec3<f32> = vec3<f32>(0.3, 0.8, 0.5);
+const AMBIENT: f32 = 0.35;
+
+@fragment
+fn fs_main(inf: VsOut) -> @location(0) vec4<f32> {
+  let len = length(inf.normal);
+  // Two-sided, as meshes are drawn without culling; unshaded where there is no normal
+  let diffuse = select(1.0, abs(dot(inf.normal / max(len, 1e-6), normalize(LIGHT_DIR))), len > 1e-6);
+  let shade = AMBIENT + (1.0 - AMBIENT) * diffuse;
+  return vec4<f32>(inf.color.rgb * shade, inf.color.a);
+}
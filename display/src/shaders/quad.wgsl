@@ -4,17 +4,19 @@ struct Camera {
 @group(0) @binding(0) var<uniform> camera: Camera;
 
 struct VsIn {
-  @location(0) pos: vec3<f32>;
-  @location(1) uv: vec2<f32>;
-  @location(2) m0: vec4<f32>;
-  @location(3) m1: vec4<f32>;
-  @location(4) m2: vec4<f32>;
-  @location(5) m3: vec4<f32>;
+  @location(0) pos: vec3<f32>,
+  @location(1) uv: vec2<f32>,
+  @location(2) m0: vec4<f32>,
+  @location(3) m1: vec4<f32>,
+  @location(4) m2: vec4<f32>,
+  @location(5) m3: vec4<f32>,
+  @location(6) opacity: f32,
 };
 
 struct VsOut {
-  @builtin(position) pos: vec4<f32>;
-  @location(0) v_uv: vec2<f32>;
+  @builtin(position) pos: vec4<f32>,
+  @location(0) v_uv: vec2<f32>,
+  @location(1) opacity: f32,
 };
 
 @vertex
@@ -24,6 +26,7 @@ fn vs_main(input: VsIn) -> VsOut {
   let world = model * vec4<f32>(input.pos, 1.0);
   out.pos = camera.view_proj * world;
   out.v_uv = input.uv;
+  out.opacity = input.opacity;
   return out;
 }
 
@@ -31,7 +34,7 @@ fn vs_main(input: VsIn) -> VsOut {
 fn fs_main(inf: VsOut) -> @location(0) vec4<f32> {
   let uv = vec2<f32>(inf.v_uv.x, 1.0 - inf.v_uv.y);
   let color = textureSample(tex, samp, uv);
-  return color;
+  return vec4<f32>(color.rgb, color.a * inf.opacity);
 }
 @group(1) @binding(0) var tex: texture_2d<f32>;
 @group(1) @binding(1) var samp: sampler;
@@ -1,9 +1,28 @@
-use std::sync::{Arc, Mutex};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, RwLock};
 use std::time::Instant;
-use tokio::sync::watch;
+use log::warn;
+use tokio::sync::{oneshot, watch};
 
-use crate::proto::{Metrics, StateUpdate, TransportState, Project, TimelineTrack};
-use crate::scene::{instances_from_project, InstanceData};
+use crate::capture::{CaptureJob, CAPTURE_WAIT};
+use crate::events::{self, EventCursor, EventHandler};
+use crate::media;
+use crate::metrics::FrameStats;
+use crate::proto::{diagnostic, timeline_track, CaptureFrameRequest, CaptureFrameResponse, Diagnostic, EndBehavior, MediaLoadState, Metrics, OutputConfig, StateUpdate, TransportState, Project, Scene, Timeline, TimelineEvent};
+use crate::render::{Gpu, RenderConfig};
+use crate::scene::{camera_at, instances_at, missing_track_targets, non_screen_media_targets, screen_sizes, CameraData, Instances};
+use crate::validate;
+
+/// A media clip resolved for a node at some timeline time.
+pub struct ActiveClip {
+    pub clip_id: String,
+    pub uri: String,
+    /// Seconds into the clip's media
+    pub media_time: f64,
+    /// Frame rate for image sequences
+    pub fps: f64,
+}
 
 #[derive(Clone)]
 pub struct AppState {
@@ -15,62 +34,260 @@ struct TransportInner {
     rate: f64,
     base_time: f64,
     started_at: Option<Instant>,
+    epoch: u64,  // bumped on every discontinuity (seek, stop, play-at, ...)
+    anchor: f64, // transport time at the start of the current epoch
+    duration: f64, // of the active timeline; <= 0 means unbounded
+    end_behavior: EndBehavior,
+    loop_in: f64,
+    loop_out: f64, // <= loop_in when no region is set
+}
+
+/// Where an unbounded transport time falls relative to the end behavior.
+#[derive(Debug, PartialEq)]
+enum Boundary {
+    Within,
+    Hold(f64),
+    Stop(f64),
+    /// Passed `end` and wrapped to `time`, re-entering the region at `start` (its out
+    /// point when playing backwards).
+    Wrap { end: f64, start: f64, time: f64 },
+    /// Bounced off a ping-pong bound; playback continues at `time` in the opposite direction.
+    Reflect { time: f64 },
+}
+
+/// `x` wrapped into `[0, len)`. `rem_euclid` may round up to `len` itself.
+fn wrap(x: f64, len: f64) -> f64 {
+    let r = x.rem_euclid(len);
+    if r >= len { 0.0 } else { r }
+}
+
+impl TransportInner {
+    fn raw_time_at(&self, now: Instant) -> f64 {
+        let mut time = self.base_time;
+        if self.status == 1 {
+            if let Some(start) = self.started_at {
+                time += self.rate * now.saturating_duration_since(start).as_secs_f64();
+            }
+        }
+        time
+    }
+
+    fn time(&self) -> f64 {
+        let raw = self.raw_time_at(Instant::now());
+        match self.boundary(raw) {
+            Boundary::Within => raw,
+            Boundary::Hold(t) | Boundary::Stop(t) => t,
+            Boundary::Wrap { time, .. } | Boundary::Reflect { time } => time,
+        }
+    }
+
+    /// The region looping modes cycle through: the loop region if set, else the whole timeline.
+    fn region(&self) -> Option<(f64, f64)> {
+        if self.end_behavior != EndBehavior::EndLoop && self.loop_out > self.loop_in {
+            Some((self.loop_in, self.loop_out))
+        } else if self.duration > 0.0 {
+            Some((0.0, self.duration))
+        } else {
+            None
+        }
+    }
+
+    fn boundary(&self, raw: f64) -> Boundary {
+        let boundary = match self.end_behavior {
+            EndBehavior::EndHold if self.duration > 0.0 && raw >= self.duration => Boundary::Hold(self.duration),
+            EndBehavior::EndStop if self.duration > 0.0 && raw >= self.duration => Boundary::Stop(self.duration),
+            // Playing backwards, the start of the timeline is the end
+            EndBehavior::EndStop if self.rate < 0.0 && raw < 0.0 => Boundary::Stop(0.0),
+            EndBehavior::EndLoop | EndBehavior::EndLoopRegion => match self.region() {
+                Some((a, b)) if raw >= b => Boundary::Wrap { end: b, start: a, time: a + wrap(raw - a, b - a) },
+                Some((a, b)) if self.rate < 0.0 && raw < a && self.base_time >= a => Boundary::Wrap { end: a, start: b, time: a + wrap(raw - a, b - a) },
+                _ => Boundary::Within,
+            },
+            EndBehavior::EndPingPong => match self.region() {
+                Some((a, b)) if self.rate > 0.0 && raw >= b => Boundary::Reflect { time: (2.0 * b - raw).max(a) },
+                Some((a, b)) if self.rate < 0.0 && raw <= a && self.base_time >= a => Boundary::Reflect { time: (2.0 * a - raw).min(b) },
+                _ => Boundary::Within,
+            },
+            _ => Boundary::Within,
+        };
+        // Nothing plays before the start of the timeline. Reversing past it holds there
+        // unless the end behavior wrapped or reflected first, e.g. when playback started
+        // before the loop region or the timeline has no duration to loop.
+        match boundary {
+            Boundary::Within if self.rate < 0.0 && raw < 0.0 => Boundary::Hold(0.0),
+            b => b,
+        }
+    }
+
+    /// Fold the time played since `started_at` into `base_time` and stop the clock.
+    fn fold_elapsed(&mut self) {
+        if let Some(start) = self.started_at.take() {
+            self.base_time += self.rate * start.elapsed().as_secs_f64();
+        }
+    }
+
+    fn mark_discontinuity(&mut self) {
+        self.epoch += 1;
+        self.anchor = self.time();
+    }
 }
 
 struct MetricsInner {
-    fps: f64,
-    dropped: f64,
+    frame: FrameStats,
+    texture_cache: TextureCacheStats,
+}
+
+/// Texture cache counters reported in `Metrics`.
+#[derive(Clone, Copy, Default)]
+pub struct TextureCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub resident_bytes: u64,
+    pub budget_bytes: u64,
 }
 
 struct Shared {
     inner: Mutex<Inner>,
     tx: watch::Sender<StateUpdate>,
+    handlers: RwLock<HashMap<String, EventHandler>>,
+    captures: Mutex<Vec<CaptureJob>>,
+    /// Set by the render loop once its device exists
+    gpu: Mutex<Option<Gpu>>,
+    /// The render loop's current settings, for offline rendering to match it
+    render_config: Mutex<RenderConfig>,
+    /// Whether the render loop can currently present frames
+    ready: watch::Sender<bool>,
 }
 
 struct Inner {
     transport: TransportInner,
     metrics: MetricsInner,
-    instances: Vec<InstanceData>,
     project: Option<Project>,
+    active_timeline: Option<String>,
+    event_cursor: EventCursor,
+    opacity: HashMap<String, f32>,
+    media: MediaLoadState,
+    /// Camera node selected over the RPC; `None` renders from the first camera in the scene
+    camera: Option<String>,
+    outputs: OutputConfig,
+    /// Bumped on every output configuration change so the render loop can rebuild its windows
+    outputs_rev: u64,
+    /// Directories relative clip URIs are resolved against
+    media_roots: Vec<PathBuf>,
+}
+
+impl Inner {
+    fn active_timeline(&self) -> Option<&Timeline> {
+        let p = self.project.as_ref()?;
+        let id = self.active_timeline.as_deref()?;
+        p.timelines.iter().find(|t| t.id == id)
+    }
+
+    /// Rebuild output windows if a new scene changed the canvas sizes they are fitted to.
+    fn refit_outputs(&mut self, before: HashMap<String, (u32, u32)>) {
+        let after = self.project.as_ref().map(screen_sizes).unwrap_or_default();
+        if !self.outputs.windows.is_empty() && after != before {
+            self.outputs_rev += 1;
+        }
+    }
+
+    fn sync_duration(&mut self) {
+        self.transport.duration = self.active_timeline().map(|t| t.duration_seconds).unwrap_or(0.0);
+    }
 }
 
 impl AppState {
     pub fn new() -> (Self, watch::Receiver<StateUpdate>) {
         let (tx, rx) = watch::channel(StateUpdate {
-            transport: Some(TransportState { status: 0, time_seconds: 0.0, rate: 1.0 }),
-            metrics: Some(Metrics { fps: 0.0, dropped_frames: 0.0 }),
+            transport: Some(TransportState { rate: 1.0, ..Default::default() }),
+            metrics: Some(Metrics::default()),
+            media: Some(MediaLoadState::default()),
         });
         let inner = Inner {
-            transport: TransportInner { status: 0, rate: 1.0, base_time: 0.0, started_at: None },
-            metrics: MetricsInner { fps: 0.0, dropped: 0.0 },
-            instances: Vec::new(),
+            transport: TransportInner {
+                status: 0,
+                rate: 1.0,
+                base_time: 0.0,
+                started_at: None,
+                epoch: 0,
+                anchor: 0.0,
+                duration: 0.0,
+                end_behavior: EndBehavior::EndHold,
+                loop_in: 0.0,
+                loop_out: 0.0,
+            },
+            metrics: MetricsInner { frame: FrameStats::default(), texture_cache: TextureCacheStats::default() },
             project: None,
+            active_timeline: None,
+            event_cursor: EventCursor::new(),
+            opacity: HashMap::new(),
+            media: MediaLoadState::default(),
+            camera: None,
+            outputs: OutputConfig::default(),
+            outputs_rev: 0,
+            media_roots: Vec::new(),
         };
-        let shared = Arc::new(Shared { inner: Mutex::new(inner), tx });
-        (Self { shared }, rx)
+        let shared = Arc::new(Shared {
+            inner: Mutex::new(inner),
+            tx,
+            handlers: RwLock::new(HashMap::new()),
+            captures: Mutex::new(Vec::new()),
+            gpu: Mutex::new(None),
+            render_config: Mutex::new(RenderConfig::default()),
+            ready: watch::channel(false).0,
+        });
+        let app = Self { shared };
+        events::register_builtins(&app);
+        (app, rx)
+    }
+
+    pub fn set_frame_stats(&self, stats: FrameStats) {
+        let mut g = self.shared.inner.lock().unwrap();
+        g.metrics.frame = stats;
+        drop(g);
+        self.notify();
+    }
+
+    /// Record texture cache counters; they go out with the next metrics update.
+    pub fn set_texture_cache_stats(&self, stats: TextureCacheStats) {
+        self.shared.inner.lock().unwrap().metrics.texture_cache = stats;
     }
 
-    pub fn set_metrics_fps(&self, fps: f64) {
+    /// Publish background media loading progress; subscribers are only notified on change.
+    pub fn set_media_load_state(&self, media: MediaLoadState) {
         let mut g = self.shared.inner.lock().unwrap();
-        g.metrics.fps = fps;
+        if g.media == media {
+            return;
+        }
+        g.media = media;
         drop(g);
         self.notify();
     }
 
+    /// Start playing, from `at_seconds` if given. Without a time this is a no-op
+    /// while already playing.
     pub fn play(&self, at_seconds: Option<f64>) {
         let mut g = self.shared.inner.lock().unwrap();
-    if let Some(at) = at_seconds { g.transport.base_time = at; }
-    g.transport.started_at = Some(Instant::now());
-    g.transport.status = 1;
+        let t = &mut g.transport;
+        if at_seconds.is_none() && t.status == 1 {
+            return;
+        }
+        t.fold_elapsed();
+        if let Some(at) = at_seconds {
+            t.base_time = at;
+        }
+        t.started_at = Some(Instant::now());
+        t.status = 1;
+        if at_seconds.is_some() {
+            t.mark_discontinuity();
+        }
         drop(g);
         self.notify();
     }
 
     pub fn pause(&self) {
         let mut g = self.shared.inner.lock().unwrap();
-        if let Some(start) = g.transport.started_at.take() {
-            g.transport.base_time += g.transport.rate * start.elapsed().as_secs_f64();
-        }
+        g.transport.fold_elapsed();
         g.transport.status = 2;
         drop(g);
         self.notify();
@@ -78,35 +295,33 @@ impl AppState {
 
     pub fn stop(&self) {
         let mut g = self.shared.inner.lock().unwrap();
-    g.transport.started_at = None;
-    g.transport.base_time = 0.0;
-    g.transport.status = 0;
+        g.transport.started_at = None;
+        g.transport.base_time = 0.0;
+        g.transport.status = 0;
+        g.transport.mark_discontinuity();
         drop(g);
         self.notify();
     }
 
     pub fn seek(&self, to_seconds: f64) {
         let mut g = self.shared.inner.lock().unwrap();
-    g.transport.base_time = to_seconds;
-    if let Some(start) = g.transport.started_at.as_mut() {
+        g.transport.base_time = to_seconds;
+        if let Some(start) = g.transport.started_at.as_mut() {
             *start = Instant::now();
         }
+        g.transport.mark_discontinuity();
         drop(g);
         self.notify();
     }
 
     pub fn set_rate(&self, rate: f64) {
         let mut g = self.shared.inner.lock().unwrap();
-        {
-            let t = &mut g.transport; // single mutable borrow
-            if let Some(start) = t.started_at.as_mut() {
-                let elapsed = start.elapsed().as_secs_f64();
-                let old_rate = t.rate;
-                t.base_time += old_rate * elapsed;
-                *start = Instant::now();
-            }
-            t.rate = rate;
+        let t = &mut g.transport;
+        if t.started_at.is_some() {
+            t.fold_elapsed();
+            t.started_at = Some(Instant::now());
         }
+        t.rate = rate;
         drop(g);
         self.notify();
     }
@@ -116,55 +331,398 @@ impl AppState {
         let _ = self.shared.tx.send(upd);
     }
 
-    fn snapshot(&self) -> StateUpdate {
+    /// Current state, with the transport time sampled now.
+    pub fn snapshot(&self) -> StateUpdate {
         let g = self.shared.inner.lock().unwrap();
-        let mut time = g.transport.base_time;
-        if g.transport.status == 1 {
-            if let Some(start) = g.transport.started_at {
-                time += g.transport.rate * start.elapsed().as_secs_f64();
-            }
-        }
+        let time = g.transport.time();
         StateUpdate {
-            transport: Some(TransportState { status: g.transport.status, time_seconds: time, rate: g.transport.rate }),
-            metrics: Some(Metrics { fps: g.metrics.fps, dropped_frames: g.metrics.dropped }),
+            transport: Some(TransportState {
+                status: g.transport.status,
+                time_seconds: time,
+                rate: g.transport.rate,
+                active_timeline_id: g.active_timeline.clone().unwrap_or_default(),
+                end_behavior: g.transport.end_behavior as i32,
+                loop_in_seconds: g.transport.loop_in,
+                loop_out_seconds: g.transport.loop_out,
+            }),
+            metrics: Some(Metrics {
+                fps: g.metrics.frame.fps,
+                dropped_frames: g.metrics.frame.dropped as f64,
+                texture_cache_hits: g.metrics.texture_cache.hits,
+                texture_cache_misses: g.metrics.texture_cache.misses,
+                texture_resident_bytes: g.metrics.texture_cache.resident_bytes,
+                texture_budget_bytes: g.metrics.texture_cache.budget_bytes,
+                frames_total: g.metrics.frame.frames,
+                frame_cpu_ms: g.metrics.frame.cpu.clone(),
+                frame_gpu_ms: g.metrics.frame.gpu.clone(),
+                frame_interval_ms: g.metrics.frame.interval.clone(),
+                texture_upload_ms: g.metrics.frame.upload.clone(),
+                decode_ms: g.metrics.frame.decode.clone(),
+            }),
+            media: Some(g.media.clone()),
         }
     }
 
     pub fn watch(&self) -> watch::Receiver<StateUpdate> { self.shared.tx.subscribe() }
 
-    pub fn set_project(&self, project: &Project) {
-        let instances = instances_from_project(project);
+    /// Validate and load a project. Nothing is applied if any diagnostic is an error.
+    pub fn set_project(&self, project: &Project) -> Vec<Diagnostic> {
+        let diags = validate::project(project);
+        if validate::has_errors(&diags) {
+            return diags;
+        }
+        let active = project
+            .timelines
+            .iter()
+            .find(|t| t.id == project.active_timeline_id)
+            .or(project.timelines.first())
+            .map(|t| t.id.clone());
         let mut g = self.shared.inner.lock().unwrap();
-        g.instances = instances;
+        let sizes = g.project.as_ref().map(screen_sizes).unwrap_or_default();
         g.project = Some(project.clone());
+        g.refit_outputs(sizes);
+        g.active_timeline = active;
+        g.sync_duration();
+        g.opacity.clear();
+        g.transport.mark_discontinuity();
+        drop(g);
+        self.notify();
+        diags
+    }
+
+    /// Switch the active timeline. The transport rewinds to 0 unless `keep_position`
+    /// is set; the play/pause status is preserved either way.
+    /// Returns false if no loaded timeline has the given id.
+    pub fn activate_timeline(&self, timeline_id: &str, keep_position: bool) -> bool {
+        let mut g = self.shared.inner.lock().unwrap();
+        let exists = g.project.as_ref().map(|p| p.timelines.iter().any(|t| t.id == timeline_id)).unwrap_or(false);
+        if !exists {
+            return false;
+        }
+        g.active_timeline = Some(timeline_id.to_string());
+        g.sync_duration();
+        if !keep_position {
+            g.transport.base_time = 0.0;
+            if let Some(start) = g.transport.started_at.as_mut() {
+                *start = Instant::now();
+            }
+        }
+        g.transport.mark_discontinuity();
+        drop(g);
+        self.notify();
+        true
+    }
+
+    /// Replace only the scene of the loaded project, keeping media, timeline and transport.
+    /// Tracks left pointing at missing nodes, and media tracks at nodes that are no
+    /// longer screens, are reported as warnings rather than rejected.
+    /// Returns `None` if no project is loaded.
+    pub fn set_scene(&self, scene: &Scene) -> Option<Vec<Diagnostic>> {
+        let mut g = self.shared.inner.lock().unwrap();
+        let sizes = screen_sizes(g.project.as_ref()?);
+        let mut diags = validate::scene(scene);
+        if validate::has_errors(&diags) {
+            return Some(diags);
+        }
+        let project = g.project.as_mut()?;
+        project.scene = Some(scene.clone());
+        for id in missing_track_targets(project) {
+            diags.push(Diagnostic {
+                severity: diagnostic::Severity::Warning as i32,
+                code: diagnostic::Code::UnknownTargetNode as i32,
+                message: format!("tracks target missing node '{id}'"),
+                node_id: id,
+                ..Default::default()
+            });
+        }
+        for id in non_screen_media_targets(project) {
+            diags.push(Diagnostic {
+                severity: diagnostic::Severity::Warning as i32,
+                code: diagnostic::Code::TargetNotScreen as i32,
+                message: format!("media tracks target node '{id}' which has no screen"),
+                node_id: id,
+                ..Default::default()
+            });
+        }
+        g.refit_outputs(sizes);
+        drop(g);
+        self.notify();
+        Some(diags)
     }
 
-    pub fn instances(&self) -> Vec<InstanceData> {
+    /// Screen and mesh instances at timeline time `t`, with the active timeline's
+    /// transform tracks applied. Relative mesh URIs are resolved like clip URIs.
+    pub fn instances_at(&self, t: f64) -> Instances {
         let g = self.shared.inner.lock().unwrap();
-        g.instances.clone()
+        let Some(p) = &g.project else { return Instances::default() };
+        let mut out = instances_at(p, g.active_timeline(), t);
+        for inst in &mut out.screens {
+            if let Some(o) = g.opacity.get(&inst.node_id) {
+                inst.opacity = *o;
+            }
+        }
+        for mesh in &mut out.meshes {
+            mesh.uri = media::resolve_uri(&mesh.uri, &g.media_roots);
+        }
+        out
     }
 
-    pub fn current_time(&self) -> f64 {
+    /// A detached copy of the loaded project with `timeline_id` (empty = the active
+    /// one) active and the same camera selection, plus that timeline's duration.
+    /// Rendering from it is unaffected by the live transport and later edits.
+    pub fn detached(&self, timeline_id: &str) -> Result<(AppState, f64), String> {
         let g = self.shared.inner.lock().unwrap();
-        let mut time = g.transport.base_time;
-        if g.transport.status == 1 {
-            if let Some(start) = g.transport.started_at {
-                time += g.transport.rate * start.elapsed().as_secs_f64();
+        let project = g.project.clone().ok_or("no project loaded")?;
+        let active = if timeline_id.is_empty() {
+            g.active_timeline.clone()
+        } else if project.timelines.iter().any(|t| t.id == timeline_id) {
+            Some(timeline_id.to_string())
+        } else {
+            return Err(format!("unknown timeline: {timeline_id}"));
+        };
+        let camera = g.camera.clone();
+        let media_roots = g.media_roots.clone();
+        drop(g);
+
+        let (copy, _rx) = AppState::new();
+        let mut c = copy.shared.inner.lock().unwrap();
+        c.project = Some(project);
+        c.active_timeline = active;
+        c.camera = camera;
+        c.media_roots = media_roots;
+        c.sync_duration();
+        let duration = c.transport.duration;
+        drop(c);
+        Ok((copy, duration))
+    }
+
+    /// Select the camera node to render from; an empty id goes back to the first
+    /// camera in the scene. Fails if the loaded scene has no camera with that id.
+    pub fn select_camera(&self, node_id: &str) -> Result<(), String> {
+        let mut g = self.shared.inner.lock().unwrap();
+        if node_id.is_empty() {
+            g.camera = None;
+            return Ok(());
+        }
+        let p = g.project.as_ref().ok_or("no project loaded")?;
+        if camera_at(p, None, 0.0, Some(node_id)).is_none() {
+            return Err(format!("no camera node '{node_id}' in scene"));
+        }
+        g.camera = Some(node_id.to_string());
+        Ok(())
+    }
+
+    /// The camera to render from at timeline time `t`, with the active timeline's
+    /// transform tracks applied. Falls back to the first camera in the scene if the
+    /// selected one is gone; `None` if the scene has no camera at all.
+    pub fn camera_at(&self, t: f64) -> Option<CameraData> {
+        let g = self.shared.inner.lock().unwrap();
+        let p = g.project.as_ref()?;
+        let timeline = g.active_timeline();
+        g.camera.as_deref()
+            .and_then(|id| camera_at(p, timeline, t, Some(id)))
+            .or_else(|| camera_at(p, timeline, t, None))
+    }
+
+    /// Validate and apply an output configuration. Nothing is applied if any diagnostic is an error.
+    pub fn set_outputs(&self, cfg: &OutputConfig) -> Vec<Diagnostic> {
+        let mut g = self.shared.inner.lock().unwrap();
+        let diags = validate::outputs(cfg, g.project.as_ref());
+        if !validate::has_errors(&diags) {
+            g.outputs = cfg.clone();
+            g.outputs_rev += 1;
+        }
+        diags
+    }
+
+    /// The output configuration if it changed since revision `seen`, which is updated.
+    pub fn outputs_since(&self, seen: &mut u64) -> Option<OutputConfig> {
+        let g = self.shared.inner.lock().unwrap();
+        if g.outputs_rev == *seen {
+            return None;
+        }
+        *seen = g.outputs_rev;
+        Some(g.outputs.clone())
+    }
+
+    /// Canvas size in pixels of every screen node in the scene.
+    pub fn screen_sizes(&self) -> HashMap<String, (u32, u32)> {
+        let g = self.shared.inner.lock().unwrap();
+        g.project.as_ref().map(screen_sizes).unwrap_or_default()
+    }
+
+    /// Queue a frame capture for the render loop; the reply arrives once it has rendered.
+    pub fn request_capture(&self, req: CaptureFrameRequest) -> oneshot::Receiver<CaptureFrameResponse> {
+        let (reply, rx) = oneshot::channel();
+        let job = CaptureJob { req, reply, deadline: Instant::now() + CAPTURE_WAIT };
+        self.shared.captures.lock().unwrap().push(job);
+        rx
+    }
+
+    /// Set the directories relative clip URIs are resolved against. Clips already
+    /// opened keep their files.
+    pub fn set_media_roots(&self, roots: Vec<PathBuf>) {
+        self.shared.inner.lock().unwrap().media_roots = roots;
+    }
+
+    pub fn set_gpu(&self, gpu: Gpu) {
+        *self.shared.gpu.lock().unwrap() = Some(gpu);
+    }
+
+    /// The render loop's device, once it has started.
+    pub fn gpu(&self) -> Option<Gpu> {
+        self.shared.gpu.lock().unwrap().clone()
+    }
+
+    /// Set by the render loop at startup and on every config reload.
+    pub fn set_render_config(&self, cfg: RenderConfig) {
+        *self.shared.render_config.lock().unwrap() = cfg;
+    }
+
+    pub fn render_config(&self) -> RenderConfig {
+        self.shared.render_config.lock().unwrap().clone()
+    }
+
+    /// Set by the render loop: true once it has a device and surface, false while the
+    /// surface is lost. Backs the gRPC health status.
+    pub fn set_ready(&self, ready: bool) {
+        self.shared.ready.send_if_modified(|r| std::mem::replace(r, ready) != ready);
+    }
+
+    pub fn watch_ready(&self) -> watch::Receiver<bool> {
+        self.shared.ready.subscribe()
+    }
+
+    pub fn take_captures(&self) -> Vec<CaptureJob> {
+        std::mem::take(&mut *self.shared.captures.lock().unwrap())
+    }
+
+    pub fn set_node_opacity(&self, node_id: &str, opacity: f32) {
+        let mut g = self.shared.inner.lock().unwrap();
+        g.opacity.insert(node_id.to_string(), opacity);
+    }
+
+    /// Register (or replace) the handler for a timeline event action.
+    pub fn register_event_handler<F>(&self, action: &str, handler: F)
+    where
+        F: Fn(&AppState, &TimelineEvent) -> Result<(), String> + Send + Sync + 'static,
+    {
+        self.shared.handlers.write().unwrap().insert(action.to_string(), Arc::new(handler));
+    }
+
+    pub fn set_end_behavior(&self, behavior: EndBehavior) {
+        let mut g = self.shared.inner.lock().unwrap();
+        g.transport.end_behavior = behavior;
+        drop(g);
+        self.notify();
+    }
+
+    /// Set the loop region; `out_seconds <= in_seconds` clears it.
+    pub fn set_loop_region(&self, in_seconds: f64, out_seconds: f64) {
+        let mut g = self.shared.inner.lock().unwrap();
+        if out_seconds > in_seconds {
+            g.transport.loop_in = in_seconds;
+            g.transport.loop_out = out_seconds;
+        } else {
+            g.transport.loop_in = 0.0;
+            g.transport.loop_out = 0.0;
+        }
+        drop(g);
+        self.notify();
+    }
+
+    /// Apply the end behavior and fire the active timeline's events crossed since
+    /// the previous tick. Called once per rendered frame.
+    pub fn tick(&self) {
+        let mut changed = false;
+        let fired: Vec<TimelineEvent> = {
+            let mut g = self.shared.inner.lock().unwrap();
+            let now = Instant::now();
+            let raw = g.transport.raw_time_at(now);
+            let boundary = g.transport.boundary(raw);
+            let (epoch, anchor, playing) = (g.transport.epoch, g.transport.anchor, g.transport.status == 1);
+            let events = g.active_timeline().map(|tl| tl.events.clone()).unwrap_or_default();
+            let cursor = &mut g.event_cursor;
+            cursor.sync(epoch, anchor);
+            let idx = match boundary {
+                Boundary::Within => cursor.advance(&events, raw, playing),
+                Boundary::Hold(t) | Boundary::Stop(t) | Boundary::Reflect { time: t } => cursor.advance(&events, t, playing),
+                Boundary::Wrap { end, start, time } => {
+                    let mut idx = cursor.advance(&events, end, playing);
+                    cursor.rewind_to(start);
+                    idx.extend(cursor.advance(&events, time, playing));
+                    idx
+                }
+            };
+            // Re-anchor the clock at the mapped time so it never drifts or grows unbounded.
+            // A transport that isn't playing doesn't move, so it is left as it is.
+            let t = &mut g.transport;
+            match boundary {
+                _ if !playing => {}
+                Boundary::Within => {}
+                Boundary::Hold(end) => {
+                    t.base_time = end;
+                    t.started_at = None;
+                    t.status = 2;
+                    changed = true;
+                }
+                Boundary::Stop(_) => {
+                    t.base_time = 0.0;
+                    t.started_at = None;
+                    t.status = 0;
+                    t.mark_discontinuity();
+                    changed = true;
+                }
+                Boundary::Wrap { time, .. } => {
+                    t.base_time = time;
+                    t.started_at = Some(now);
+                }
+                Boundary::Reflect { time } => {
+                    t.base_time = time;
+                    t.started_at = Some(now);
+                    t.rate = -t.rate;
+                    changed = true;
+                }
             }
+            idx.into_iter().map(|i| events[i].clone()).collect()
+        };
+        if changed {
+            self.notify();
+        }
+        for e in &fired {
+            self.dispatch(e);
         }
-        time
     }
 
-    pub fn active_clip_for_node(&self, node_id: &str, t: f64) -> Option<(String, String)> {
+    fn dispatch(&self, event: &TimelineEvent) {
+        let handler = self.shared.handlers.read().unwrap().get(&event.action).cloned();
+        match handler {
+            Some(h) => {
+                if let Err(err) = h(self, event) {
+                    warn!("event '{}' at {}s failed: {err}", event.action, event.t);
+                }
+            }
+            None => warn!("no handler for event action '{}' at {}s", event.action, event.t),
+        }
+    }
+
+    pub fn current_time(&self) -> f64 {
+        let g = self.shared.inner.lock().unwrap();
+        g.transport.time()
+    }
+
+    /// The clip shown on `node_id` at timeline time `t`. A media track occupies
+    /// `[start_at, start_at + (out - in))` on the timeline and plays the clip from `in`.
+    pub fn active_clip_for_node(&self, node_id: &str, t: f64) -> Option<ActiveClip> {
         let g = self.shared.inner.lock().unwrap();
         let p = g.project.as_ref()?;
-        let timeline = p.timeline.as_ref()?;
+        let timeline = g.active_timeline()?;
         let mut best: Option<(&crate::proto::TrackMedia, f64)> = None;
         for tr in &timeline.tracks {
             if let Some(timeline_track::Kind::Media(m)) = &tr.kind {
                 if m.target_node_id == node_id {
-                    let start = m.start_at_seconds + m.in_seconds;
-                    let end = m.start_at_seconds + m.out_seconds;
+                    let start = m.start_at_seconds;
+                    let end = m.start_at_seconds + (m.out_seconds - m.in_seconds);
                     if t >= start && t < end {
                         // prefer the latest starting clip if overlaps
                         if best.map(|(_, bs)| start >= bs).unwrap_or(true) {
@@ -176,6 +734,345 @@ impl AppState {
         }
         let (m, _) = best?;
         let clip = p.media.iter().find(|c| c.id == m.clip_id)?;
-        Some((clip.id.clone(), clip.uri.clone()))
+        Some(ActiveClip {
+            clip_id: clip.id.clone(),
+            uri: media::resolve_uri(&clip.uri, &g.media_roots),
+            media_time: t - m.start_at_seconds + m.in_seconds,
+            fps: clip.fps,
+        })
+    }
+
+    /// Clips on the active timeline that are showing at `t` or start within the next
+    /// `horizon` seconds, resolved at the point they will first be shown.
+    pub fn upcoming_clips(&self, t: f64, horizon: f64) -> Vec<ActiveClip> {
+        let g = self.shared.inner.lock().unwrap();
+        let (Some(p), Some(timeline)) = (g.project.as_ref(), g.active_timeline()) else {
+            return Vec::new();
+        };
+        let mut out = Vec::new();
+        for tr in &timeline.tracks {
+            if let Some(timeline_track::Kind::Media(m)) = &tr.kind {
+                let start = m.start_at_seconds;
+                let end = m.start_at_seconds + (m.out_seconds - m.in_seconds);
+                if end <= t || start > t + horizon {
+                    continue;
+                }
+                if let Some(clip) = p.media.iter().find(|c| c.id == m.clip_id) {
+                    out.push(ActiveClip {
+                        clip_id: clip.id.clone(),
+                        uri: media::resolve_uri(&clip.uri, &g.media_roots),
+                        media_time: t.max(start) - start + m.in_seconds,
+                        fps: clip.fps,
+                    });
+                }
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::{component, Component, MediaClip, Node, ScreenComponent, TimelineTrack, TrackMedia};
+    use std::thread::sleep;
+    use std::time::Duration;
+
+    const STEP: Duration = Duration::from_millis(50);
+
+    /// (epoch, anchor, time) of the transport.
+    fn transport(app: &AppState) -> (u64, f64, f64) {
+        let g = app.shared.inner.lock().unwrap();
+        (g.transport.epoch, g.transport.anchor, g.transport.time())
+    }
+
+    #[test]
+    fn play_at_while_playing_anchors_at_target() {
+        let (app, _rx) = AppState::new();
+        app.play(None);
+        sleep(STEP);
+        let (epoch, ..) = transport(&app);
+        app.play(Some(10.0));
+        let (after, anchor, time) = transport(&app);
+        assert_eq!(after, epoch + 1);
+        assert!((anchor - 10.0).abs() < 0.01, "anchor {anchor}");
+        assert!((10.0..10.01).contains(&time), "time {time}");
+    }
+
+    #[test]
+    fn play_while_playing_keeps_time() {
+        let (app, _rx) = AppState::new();
+        app.play(None);
+        sleep(STEP);
+        let (epoch, _, before) = transport(&app);
+        app.play(None);
+        let (after, _, time) = transport(&app);
+        assert_eq!(after, epoch, "not a discontinuity");
+        assert!(time >= before, "{time} < {before}");
+    }
+
+    #[test]
+    fn play_resumes_from_pause() {
+        let (app, _rx) = AppState::new();
+        app.play(None);
+        sleep(STEP);
+        app.pause();
+        let (_, _, paused) = transport(&app);
+        sleep(STEP);
+        app.play(None);
+        let (_, _, time) = transport(&app);
+        assert!(time >= paused && time < paused + 0.01, "{time} vs {paused}");
+    }
+
+    #[test]
+    fn seek_anchors_at_target() {
+        let (app, _rx) = AppState::new();
+        app.play(None);
+        sleep(STEP);
+        let (epoch, ..) = transport(&app);
+        app.seek(3.0);
+        let (after, anchor, time) = transport(&app);
+        assert_eq!(after, epoch + 1);
+        assert!((anchor - 3.0).abs() < 0.01, "anchor {anchor}");
+        assert!((3.0..3.01).contains(&time), "time {time}");
+    }
+
+    #[test]
+    fn rate_change_keeps_time_continuous() {
+        let (app, _rx) = AppState::new();
+        app.play(None);
+        sleep(STEP);
+        let (epoch, _, before) = transport(&app);
+        app.set_rate(-2.0);
+        let (after, _, time) = transport(&app);
+        assert_eq!(after, epoch, "not a discontinuity");
+        assert!((time - before).abs() < 0.01, "{time} vs {before}");
+        sleep(STEP);
+        let (_, _, later) = transport(&app);
+        assert!(later < time, "runs backwards at a negative rate");
+    }
+
+    /// A playing transport over a 10s timeline, at `base_time` when the clock last started.
+    fn playing(end_behavior: EndBehavior, rate: f64, base_time: f64, region: (f64, f64)) -> TransportInner {
+        TransportInner {
+            status: 1,
+            rate,
+            base_time,
+            started_at: None,
+            epoch: 0,
+            anchor: 0.0,
+            duration: 10.0,
+            end_behavior,
+            loop_in: region.0,
+            loop_out: region.1,
+        }
+    }
+
+    #[test]
+    fn boundary_for_every_end_behavior_and_direction() {
+        use Boundary::*;
+        use EndBehavior::*;
+        const NONE: (f64, f64) = (0.0, 0.0);
+        const REGION: (f64, f64) = (4.0, 8.0);
+        let cases = [
+            // behavior, region, rate, base_time, raw time, expected
+            (EndHold, NONE, 1.0, 5.0, 9.5, Within),
+            (EndHold, NONE, 1.0, 5.0, 12.0, Hold(10.0)),
+            (EndHold, NONE, -1.0, 5.0, 0.0, Within),
+            (EndHold, NONE, -1.0, 5.0, -1.0, Hold(0.0)),
+            (EndStop, NONE, 1.0, 5.0, 10.5, Stop(10.0)),
+            (EndStop, NONE, -1.0, 5.0, 0.5, Within),
+            (EndStop, NONE, -1.0, 5.0, -0.5, Stop(0.0)),
+            (EndLoop, NONE, 1.0, 5.0, 11.0, Wrap { end: 10.0, start: 0.0, time: 1.0 }),
+            (EndLoop, NONE, -1.0, 5.0, 0.0, Within),
+            (EndLoop, NONE, -1.0, 5.0, -1.0, Wrap { end: 0.0, start: 10.0, time: 9.0 }),
+            // Whole-timeline loop ignores the region
+            (EndLoop, REGION, 1.0, 5.0, 9.0, Within),
+            (EndLoop, REGION, -1.0, 5.0, 3.0, Within),
+            (EndLoopRegion, REGION, 1.0, 5.0, 8.5, Wrap { end: 8.0, start: 4.0, time: 4.5 }),
+            (EndLoopRegion, REGION, -1.0, 5.0, 3.5, Wrap { end: 4.0, start: 8.0, time: 7.5 }),
+            (EndLoopRegion, NONE, 1.0, 5.0, 12.0, Wrap { end: 10.0, start: 0.0, time: 2.0 }),
+            (EndLoopRegion, NONE, -1.0, 5.0, -2.0, Wrap { end: 0.0, start: 10.0, time: 8.0 }),
+            // Playing towards the region from outside it doesn't wrap
+            (EndLoopRegion, REGION, 1.0, 1.0, 3.0, Within),
+            (EndLoopRegion, REGION, -1.0, 2.0, 1.0, Within),
+            (EndLoopRegion, REGION, -1.0, 2.0, -1.0, Hold(0.0)),
+            (EndPingPong, REGION, -1.0, 2.0, -1.0, Hold(0.0)),
+            (EndPingPong, REGION, 1.0, 5.0, 9.0, Reflect { time: 7.0 }),
+            (EndPingPong, REGION, -1.0, 5.0, 3.0, Reflect { time: 5.0 }),
+            (EndPingPong, NONE, 1.0, 5.0, 10.5, Reflect { time: 9.5 }),
+            (EndPingPong, NONE, -1.0, 5.0, -0.5, Reflect { time: 0.5 }),
+        ];
+        for (behavior, region, rate, base_time, raw, expected) in cases {
+            let t = playing(behavior, rate, base_time, region);
+            assert_eq!(t.boundary(raw), expected, "{behavior:?} region {region:?} rate {rate} from {base_time} at {raw}");
+        }
+    }
+
+    #[test]
+    fn reversing_an_unbounded_loop_holds_at_zero() {
+        for behavior in [EndBehavior::EndLoop, EndBehavior::EndLoopRegion, EndBehavior::EndPingPong] {
+            let mut t = playing(behavior, -1.0, 1.0, (0.0, 0.0));
+            t.duration = 0.0;
+            assert_eq!(t.boundary(0.5), Boundary::Within, "{behavior:?}");
+            assert_eq!(t.boundary(-3.0), Boundary::Hold(0.0), "{behavior:?}");
+        }
+    }
+
+    #[test]
+    fn tick_leaves_a_transport_that_is_not_playing_alone() {
+        let (app, mut rx) = AppState::new();
+        {
+            let mut g = app.shared.inner.lock().unwrap();
+            g.transport.duration = 10.0;
+            g.transport.base_time = 10.0;
+            g.transport.status = 2;
+        }
+        rx.borrow_and_update();
+        for _ in 0..3 {
+            app.tick();
+        }
+        assert!(!rx.has_changed().unwrap(), "paused at the end republished state");
+        assert_eq!(app.shared.inner.lock().unwrap().transport.status, 2);
+
+        // Stopped and seeked past the end stays stopped there
+        app.shared.inner.lock().unwrap().transport.status = 0;
+        app.seek(12.0);
+        rx.borrow_and_update();
+        app.tick();
+        assert!(!rx.has_changed().unwrap());
+        let (status, base_time) = {
+            let g = app.shared.inner.lock().unwrap();
+            (g.transport.status, g.transport.base_time)
+        };
+        assert_eq!((status, base_time), (0, 12.0));
+    }
+
+    #[test]
+    fn playing_into_hold_pauses_once() {
+        let (app, mut rx) = AppState::new();
+        app.shared.inner.lock().unwrap().transport.duration = 1.0;
+        app.seek(0.99);
+        app.play(None);
+        sleep(STEP);
+        rx.borrow_and_update();
+        app.tick();
+        assert!(rx.has_changed().unwrap());
+        rx.borrow_and_update();
+        app.tick();
+        assert!(!rx.has_changed().unwrap());
+        let (_, _, time) = transport(&app);
+        assert_eq!(time, 1.0);
+    }
+
+    #[test]
+    fn stopped_at_zero_stays_put_at_a_negative_rate() {
+        for behavior in [EndBehavior::EndHold, EndBehavior::EndStop, EndBehavior::EndLoop, EndBehavior::EndLoopRegion] {
+            let mut t = playing(behavior, -1.0, 0.0, (0.0, 0.0));
+            t.status = 0;
+            assert_eq!(t.boundary(t.raw_time_at(Instant::now())), Boundary::Within, "{behavior:?}");
+        }
+    }
+
+    #[test]
+    fn reverse_loop_wraps_the_clock_into_the_region() {
+        let (app, _rx) = AppState::new();
+        app.set_end_behavior(EndBehavior::EndLoopRegion);
+        app.set_loop_region(4.0, 8.0);
+        app.seek(4.02);
+        app.set_rate(-1.0);
+        app.play(None);
+        sleep(STEP);
+        app.tick();
+        let (_, _, time) = transport(&app);
+        assert!((7.9..8.0).contains(&time), "wrapped to {time}");
+    }
+
+    fn scene(wall_is_screen: bool) -> Scene {
+        let screen = ScreenComponent { pixels_x: 64, pixels_y: 32, ..Default::default() };
+        let components = if wall_is_screen { vec![Component { kind: Some(component::Kind::Screen(screen)) }] } else { Vec::new() };
+        Scene { roots: vec![Node { id: "wall".into(), components, ..Default::default() }], ..Default::default() }
+    }
+
+    #[test]
+    fn set_scene_notifies_and_warns_about_media_tracks_off_screens() {
+        let (app, mut rx) = AppState::new();
+        assert_eq!(app.set_scene(&scene(true)), None, "no project loaded");
+        let track = |target: &str| TimelineTrack {
+            kind: Some(timeline_track::Kind::Media(TrackMedia { target_node_id: target.into(), clip_id: "clip".into(), out_seconds: 5.0, ..Default::default() })),
+        };
+        let project = Project {
+            scene: Some(scene(true)),
+            media: vec![MediaClip { id: "clip".into(), uri: "file:///media/clip.png".into(), ..Default::default() }],
+            timelines: vec![Timeline { id: "main".into(), tracks: vec![track("wall"), track("wall")], ..Default::default() }],
+            ..Default::default()
+        };
+        assert!(app.set_project(&project).is_empty());
+        rx.borrow_and_update();
+
+        let diags = app.set_scene(&scene(false)).unwrap();
+        let found: Vec<_> = diags.iter().map(|d| (d.severity(), d.code(), d.node_id.as_str())).collect();
+        assert_eq!(found, [(diagnostic::Severity::Warning, diagnostic::Code::TargetNotScreen, "wall")]);
+        assert!(rx.has_changed().unwrap(), "scene change was not published");
+
+        rx.borrow_and_update();
+        assert!(app.set_scene(&Scene::default()).unwrap().iter().all(|d| d.code() == diagnostic::Code::UnknownTargetNode));
+        assert!(rx.has_changed().unwrap());
+    }
+
+    /// Active timeline id and (status, duration, epoch, time) of the transport.
+    fn active(app: &AppState) -> (String, (i32, f64, u64, f64)) {
+        let id = app.snapshot().transport.unwrap().active_timeline_id;
+        let g = app.shared.inner.lock().unwrap();
+        (id, (g.transport.status, g.transport.duration, g.transport.epoch, g.transport.time()))
+    }
+
+    #[test]
+    fn activate_timeline_switches_and_rewinds() {
+        let (app, mut rx) = AppState::new();
+        let fired = Arc::new(Mutex::new(Vec::new()));
+        let log = fired.clone();
+        app.register_event_handler("mark", move |_, e| {
+            log.lock().unwrap().push(e.t);
+            Ok(())
+        });
+        let timeline = |id: &str, duration_seconds: f64, t: f64| Timeline {
+            id: id.into(),
+            duration_seconds,
+            events: vec![TimelineEvent { t, action: "mark".into(), ..Default::default() }],
+            ..Default::default()
+        };
+        let project = Project { scene: Some(scene(true)), timelines: vec![timeline("a", 10.0, 1.0), timeline("b", 4.0, 2.0)], ..Default::default() };
+        assert!(!validate::has_errors(&app.set_project(&project)));
+        app.seek(3.0);
+        app.play(None);
+        app.tick();
+
+        // An unknown id changes nothing
+        rx.borrow_and_update();
+        let (_, (_, _, epoch, before)) = active(&app);
+        assert!(!app.activate_timeline("missing", false));
+        let (id, (status, duration, after, time)) = active(&app);
+        assert_eq!((id.as_str(), status, duration, after), ("a", 1, 10.0, epoch));
+        assert!(time >= before);
+        assert!(!rx.has_changed().unwrap());
+
+        // Switching rewinds to 0, still playing, at the new timeline's length
+        assert!(app.activate_timeline("b", false));
+        let (id, (status, duration, after, time)) = active(&app);
+        assert_eq!((id.as_str(), status, duration, after), ("b", 1, 4.0, epoch + 1));
+        assert!((0.0..0.01).contains(&time), "time {time}");
+        assert!(rx.has_changed().unwrap());
+
+        // The event cursor restarted at 0 too, so b's event at 2s fires when crossed
+        app.shared.inner.lock().unwrap().transport.base_time = 2.5;
+        app.tick();
+        assert_eq!(*fired.lock().unwrap(), [2.0]);
+
+        // keep_position switches without moving the playhead
+        assert!(app.activate_timeline("a", true));
+        let (id, (status, duration, _, time)) = active(&app);
+        assert_eq!((id.as_str(), status, duration), ("a", 1, 10.0));
+        assert!((2.5..2.6).contains(&time), "time {time}");
     }
 }
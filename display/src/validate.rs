@@ -0,0 +1,350 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::media;
+use crate::proto::diagnostic::{Code, Severity};
+use crate::proto::*;
+
+const ROTATION_EPS: f32 = 1e-3;
+const SCALE_EPS: f32 = 1e-6;
+
+fn diag(severity: Severity, code: Code, message: String) -> Diagnostic {
+    Diagnostic { severity: severity as i32, code: code as i32, message, ..Default::default() }
+}
+
+pub fn has_errors(diags: &[Diagnostic]) -> bool {
+    diags.iter().any(|d| d.severity == Severity::Error as i32)
+}
+
+/// One-line summary suitable for `Ack.message`.
+pub fn summary(what: &str, diags: &[Diagnostic]) -> String {
+    let errors = diags.iter().filter(|d| d.severity == Severity::Error as i32).count();
+    let warnings = diags.len() - errors;
+    match (errors, warnings) {
+        (0, 0) => format!("{what} loaded"),
+        (0, w) => format!("{what} loaded with {w} warning(s)"),
+        (e, w) => format!("{what} rejected: {e} error(s), {w} warning(s)"),
+    }
+}
+
+/// Checks that only depend on the scene graph: duplicate ids and degenerate transforms.
+pub fn scene(scene: &Scene) -> Vec<Diagnostic> {
+    let mut out = Vec::new();
+    let mut seen = HashSet::new();
+    for n in &scene.roots {
+        check_node(n, &mut seen, &mut out);
+    }
+    out
+}
+
+fn check_node(node: &Node, seen: &mut HashSet<String>, out: &mut Vec<Diagnostic>) {
+    if !seen.insert(node.id.clone()) {
+        let mut d = diag(Severity::Error, Code::DuplicateNodeId, format!("duplicate node id '{}'", node.id));
+        d.node_id = node.id.clone();
+        out.push(d);
+    }
+    if let Some(t) = &node.transform {
+        for mut d in check_transform(t, &format!("node '{}'", node.id)) {
+            d.node_id = node.id.clone();
+            out.push(d);
+        }
+    }
+    let screens = node.kinds().filter(|k| matches!(k, component::Kind::Screen(_))).count();
+    let cameras = node.kinds().filter(|k| matches!(k, component::Kind::Camera(_))).count();
+    for (what, n) in [("screen", screens), ("camera", cameras)] {
+        if n > 1 {
+            let mut d = diag(Severity::Warning, Code::DuplicateComponent, format!("node '{}' has {n} {what} components; only the first is used", node.id));
+            d.node_id = node.id.clone();
+            out.push(d);
+        }
+    }
+    for c in &node.children {
+        check_node(c, seen, out);
+    }
+}
+
+fn check_transform(t: &Transform, subject: &str) -> Vec<Diagnostic> {
+    let mut out = Vec::new();
+    if let Some(q) = &t.rotation {
+        let len = (q.x * q.x + q.y * q.y + q.z * q.z + q.w * q.w).sqrt();
+        if (len - 1.0).abs() > ROTATION_EPS {
+            out.push(diag(
+                Severity::Warning,
+                Code::NonNormalizedRotation,
+                format!("{subject} has a non-normalized rotation (length {len:.4})"),
+            ));
+        }
+    }
+    if let Some(s) = &t.scale {
+        if s.x.abs() < SCALE_EPS || s.y.abs() < SCALE_EPS || s.z.abs() < SCALE_EPS {
+            out.push(diag(
+                Severity::Warning,
+                Code::ZeroScale,
+                format!("{subject} has a zero scale component ({}, {}, {})", s.x, s.y, s.z),
+            ));
+        }
+    }
+    out
+}
+
+fn collect_nodes<'a>(node: &'a Node, out: &mut HashMap<&'a str, &'a Node>) {
+    out.entry(node.id.as_str()).or_insert(node);
+    for c in &node.children {
+        collect_nodes(c, out);
+    }
+}
+
+/// Full project validation: scene checks plus timeline references into scene and media.
+pub fn project(p: &Project) -> Vec<Diagnostic> {
+    let mut out = p.scene.as_ref().map(scene).unwrap_or_default();
+
+    let mut nodes = HashMap::new();
+    if let Some(s) = &p.scene {
+        for n in &s.roots {
+            collect_nodes(n, &mut nodes);
+        }
+    }
+    let clips: HashSet<&str> = p.media.iter().map(|c| c.id.as_str()).collect();
+    for c in &p.media {
+        if media::is_sequence(&c.uri) && c.fps <= 0.0 {
+            let mut d = diag(Severity::Error, Code::MissingClipFps, format!("image sequence clip '{}' needs a positive fps", c.id));
+            d.clip_id = c.id.clone();
+            out.push(d);
+        }
+    }
+
+    let mut timeline_ids = HashSet::new();
+    for tl in &p.timelines {
+        if !timeline_ids.insert(tl.id.as_str()) {
+            let mut d = diag(Severity::Error, Code::DuplicateTimelineId, format!("duplicate timeline id '{}'", tl.id));
+            d.timeline_id = tl.id.clone();
+            out.push(d);
+        }
+        for tr in &tl.tracks {
+            let mut found = Vec::new();
+            match &tr.kind {
+                Some(timeline_track::Kind::Media(m)) => {
+                    match nodes.get(m.target_node_id.as_str()) {
+                        None => found.push(diag(
+                            Severity::Error,
+                            Code::UnknownTargetNode,
+                            format!("media track targets unknown node '{}'", m.target_node_id),
+                        )),
+                        Some(n) if n.screen().is_none() => found.push(diag(
+                            Severity::Warning,
+                            Code::TargetNotScreen,
+                            format!("media track targets node '{}' which has no screen", m.target_node_id),
+                        )),
+                        Some(_) => {}
+                    }
+                    if !clips.contains(m.clip_id.as_str()) {
+                        let mut d = diag(Severity::Error, Code::UnknownClip, format!("media track references unknown clip '{}'", m.clip_id));
+                        d.clip_id = m.clip_id.clone();
+                        found.push(d);
+                    }
+                    if m.out_seconds <= m.in_seconds {
+                        let mut d = diag(
+                            Severity::Error,
+                            Code::EmptyClipRange,
+                            format!("media track for clip '{}' has out ({}) <= in ({})", m.clip_id, m.out_seconds, m.in_seconds),
+                        );
+                        d.clip_id = m.clip_id.clone();
+                        found.push(d);
+                    }
+                    for d in &mut found {
+                        d.node_id = m.target_node_id.clone();
+                    }
+                }
+                Some(timeline_track::Kind::Transform(t)) => {
+                    if !nodes.contains_key(t.target_node_id.as_str()) {
+                        found.push(diag(
+                            Severity::Error,
+                            Code::UnknownTargetNode,
+                            format!("transform track targets unknown node '{}'", t.target_node_id),
+                        ));
+                    }
+                    for k in &t.keys {
+                        if let Some(tf) = &k.transform {
+                            found.extend(check_transform(tf, &format!("transform key at {}s for '{}'", k.t, t.target_node_id)));
+                        }
+                    }
+                    for d in &mut found {
+                        d.node_id = t.target_node_id.clone();
+                    }
+                }
+                None => {}
+            }
+            for mut d in found {
+                d.timeline_id = tl.id.clone();
+                out.push(d);
+            }
+        }
+    }
+    out
+}
+
+/// Output configuration checks. Screens are looked up in the loaded scene, but since
+/// the scene can be swapped later, missing or unusable screens are only warnings.
+pub fn outputs(cfg: &OutputConfig, p: Option<&Project>) -> Vec<Diagnostic> {
+    let mut out = Vec::new();
+    let mut nodes = HashMap::new();
+    if let Some(s) = p.and_then(|p| p.scene.as_ref()) {
+        for n in &s.roots {
+            collect_nodes(n, &mut nodes);
+        }
+    }
+    let mut ids = HashSet::new();
+    for w in &cfg.windows {
+        if !ids.insert(w.id.as_str()) {
+            out.push(diag(Severity::Error, Code::DuplicateOutputId, format!("duplicate output id '{}'", w.id)));
+        }
+        for s in &w.screens {
+            let found = match nodes.get(s.node_id.as_str()).map(|n| n.screen()) {
+                None => Some(diag(
+                    Severity::Warning,
+                    Code::UnknownTargetNode,
+                    format!("output '{}' shows unknown node '{}'", w.id, s.node_id),
+                )),
+                Some(Some(sc)) if sc.pixels_x <= 0 || sc.pixels_y <= 0 => Some(diag(
+                    Severity::Warning,
+                    Code::EmptyScreenCanvas,
+                    format!("output '{}' shows screen '{}' with a {}x{} canvas", w.id, s.node_id, sc.pixels_x, sc.pixels_y),
+                )),
+                Some(Some(sc)) if sc.disabled => Some(diag(
+                    Severity::Warning,
+                    Code::ScreenDisabled,
+                    format!("output '{}' shows disabled screen '{}'", w.id, s.node_id),
+                )),
+                Some(Some(_)) => None,
+                Some(None) => Some(diag(
+                    Severity::Warning,
+                    Code::TargetNotScreen,
+                    format!("output '{}' shows node '{}' which has no screen", w.id, s.node_id),
+                )),
+            };
+            if let Some(mut d) = found {
+                d.node_id = s.node_id.clone();
+                out.push(d);
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(id: &str, children: Vec<Node>) -> Node {
+        Node { id: id.into(), children, ..Default::default() }
+    }
+
+    fn screen(id: &str) -> Node {
+        let screen = ScreenComponent { pixels_x: 64, pixels_y: 32, ..Default::default() };
+        Node { id: id.into(), components: vec![Component { kind: Some(component::Kind::Screen(screen)) }], ..Default::default() }
+    }
+
+    fn media_track(target: &str, clip: &str) -> TimelineTrack {
+        let m = TrackMedia { target_node_id: target.into(), clip_id: clip.into(), out_seconds: 5.0, ..Default::default() };
+        TimelineTrack { kind: Some(timeline_track::Kind::Media(m)) }
+    }
+
+    fn transform_track(target: &str) -> TimelineTrack {
+        TimelineTrack { kind: Some(timeline_track::Kind::Transform(TrackTransform { target_node_id: target.into(), ..Default::default() })) }
+    }
+
+    fn project(roots: Vec<Node>, tracks: Vec<TimelineTrack>) -> Project {
+        Project {
+            scene: Some(Scene { roots, ..Default::default() }),
+            media: vec![MediaClip { id: "clip".into(), uri: "file:///media/clip.png".into(), ..Default::default() }],
+            timelines: vec![Timeline { id: "main".into(), tracks, ..Default::default() }],
+            ..Default::default()
+        }
+    }
+
+    /// (severity, code, node_id) of each diagnostic.
+    fn found(diags: &[Diagnostic]) -> Vec<(Severity, Code, &str)> {
+        diags.iter().map(|d| (d.severity(), d.code(), d.node_id.as_str())).collect()
+    }
+
+    #[test]
+    fn unset_severity_is_not_an_error() {
+        let d = Diagnostic { code: Code::ZeroScale as i32, ..Default::default() };
+        assert_eq!(d.severity(), Severity::Unspecified);
+        assert!(!has_errors(&[d]));
+    }
+
+    #[test]
+    fn clean_scene_has_no_findings() {
+        let s = Scene { roots: vec![node("stage", vec![screen("wall"), node("truss", vec![screen("side")])])], ..Default::default() };
+        assert!(scene(&s).is_empty());
+    }
+
+    #[test]
+    fn duplicate_ids_across_roots_and_levels() {
+        let s = Scene { roots: vec![node("a", vec![node("b", vec![])]), node("b", vec![]), node("c", vec![])], ..Default::default() };
+        assert_eq!(found(&scene(&s)), [(Severity::Error, Code::DuplicateNodeId, "b")]);
+    }
+
+    #[test]
+    fn child_reusing_an_ancestor_id_is_rejected() {
+        // Nodes nest rather than name their parent, so a cycle can only be spelled as
+        // a descendant claiming an ancestor's id; it must not resolve as the same node.
+        let s = Scene { roots: vec![node("rig", vec![node("arm", vec![node("rig", vec![])])])], ..Default::default() };
+        let diags = scene(&s);
+        assert_eq!(found(&diags), [(Severity::Error, Code::DuplicateNodeId, "rig")]);
+        assert!(has_errors(&diags));
+    }
+
+    #[test]
+    fn degenerate_transforms_are_warnings() {
+        let mut n = node("n", vec![]);
+        n.transform = Some(Transform {
+            rotation: Some(Quat { x: 0.0, y: 0.0, z: 0.0, w: 2.0 }),
+            scale: Some(Vec3 { x: 1.0, y: 0.0, z: 1.0 }),
+            ..Default::default()
+        });
+        let diags = scene(&Scene { roots: vec![n], ..Default::default() });
+        assert_eq!(found(&diags), [(Severity::Warning, Code::NonNormalizedRotation, "n"), (Severity::Warning, Code::ZeroScale, "n")]);
+        assert!(!has_errors(&diags));
+    }
+
+    #[test]
+    fn tracks_resolve_nested_targets() {
+        let p = project(vec![node("stage", vec![screen("wall")])], vec![media_track("wall", "clip"), transform_track("wall")]);
+        assert!(super::project(&p).is_empty());
+    }
+
+    #[test]
+    fn missing_targets_and_clips() {
+        let p = project(
+            vec![node("stage", vec![screen("wall")])],
+            vec![media_track("gone", "clip"), media_track("stage", "clip"), media_track("wall", "nope"), transform_track("gone")],
+        );
+        let diags = super::project(&p);
+        assert_eq!(
+            found(&diags),
+            [
+                (Severity::Error, Code::UnknownTargetNode, "gone"),
+                (Severity::Warning, Code::TargetNotScreen, "stage"),
+                (Severity::Error, Code::UnknownClip, "wall"),
+                (Severity::Error, Code::UnknownTargetNode, "gone"),
+            ]
+        );
+        assert!(diags.iter().all(|d| d.timeline_id == "main"));
+        assert_eq!(diags[2].clip_id, "nope");
+    }
+
+    #[test]
+    fn project_includes_scene_findings_and_timeline_checks() {
+        let mut p = project(vec![node("a", vec![]), node("a", vec![]), screen("wall")], vec![]);
+        let mut empty = media_track("wall", "clip");
+        if let Some(timeline_track::Kind::Media(m)) = &mut empty.kind {
+            m.in_seconds = 5.0;
+        }
+        p.timelines[0].tracks.push(empty);
+        p.timelines.push(Timeline { id: "main".into(), ..Default::default() });
+        p.media.push(MediaClip { id: "seq".into(), uri: "file:///media/seq_%04d.png".into(), ..Default::default() });
+        let codes: Vec<Code> = super::project(&p).iter().map(|d| d.code()).collect();
+        assert_eq!(codes, [Code::DuplicateNodeId, Code::MissingClipFps, Code::EmptyClipRange, Code::DuplicateTimelineId]);
+    }
+}
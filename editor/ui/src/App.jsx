@@ -19,6 +19,8 @@ export default function App() {
   const { loadProject, playing, play, pause, time, selectedId, setSelected, gizmoMode, setGizmoMode, project, scene, addImageToShow, toggleConsole, addLog, viewMode, setViewMode, showOutputOverlay, toggleOutputOverlay, addScreenNode } = useEditorStore()
   const [fileName, setFileName] = useState('')
   const [addr, setAddr] = useState('http://127.0.0.1:50051')
+  // Token and certificate paths for a Display with TLS/authentication on
+  const [creds, setCreds] = useState({ token: '', caCert: '', cert: '', key: '' })
   const [status, setStatus] = useState('')
   // Resizable divider state between Inspector (top) and Media Bin (bottom)
   const rightPaneRef = useRef(null)
@@ -138,12 +140,14 @@ export default function App() {
           onDeselect={() => setSelected(null)}
           addr={addr}
           setAddr={setAddr}
+          creds={creds}
+          setCreds={setCreds}
           showOutputOverlay={showOutputOverlay}
           toggleOutputOverlay={toggleOutputOverlay}
           onApply={async ()=>{
             try {
               const wrapper = buildProjectWrapper(project, scene)
-              const message = await window.__TAURI__.invoke('apply_project', { addr, projectJson: JSON.stringify(wrapper) })
+              const message = await window.__TAURI__.invoke('apply_project', { addr, creds, projectJson: JSON.stringify(wrapper) })
               setStatus('Applied: ' + message)
               addLog({ level:'info', message:`Applied project to ${addr}: ${message}` })
             } catch (e) {
@@ -151,9 +155,9 @@ export default function App() {
               addLog({ level:'error', message:`Apply failed: ${e}` })
             }
           }}
-          onRemotePlay={async ()=>{ try { const msg = await window.__TAURI__.invoke('play', { addr }); setStatus('Play: '+msg) } catch(e){ setStatus('Play failed: '+e) } }}
-          onRemotePause={async ()=>{ try { const msg = await window.__TAURI__.invoke('pause', { addr }); setStatus('Pause: '+msg) } catch(e){ setStatus('Pause failed: '+e) } }}
-          onRemoteStop={async ()=>{ try { const msg = await window.__TAURI__.invoke('stop', { addr }); setStatus('Stop: '+msg) } catch(e){ setStatus('Stop failed: '+e) } }}
+          onRemotePlay={async ()=>{ try { const msg = await window.__TAURI__.invoke('play', { addr, creds }); setStatus('Play: '+msg) } catch(e){ setStatus('Play failed: '+e) } }}
+          onRemotePause={async ()=>{ try { const msg = await window.__TAURI__.invoke('pause', { addr, creds }); setStatus('Pause: '+msg) } catch(e){ setStatus('Pause failed: '+e) } }}
+          onRemoteStop={async ()=>{ try { const msg = await window.__TAURI__.invoke('stop', { addr, creds }); setStatus('Stop: '+msg) } catch(e){ setStatus('Stop failed: '+e) } }}
         />
         <input type="file" accept="application/json" onChange={onFile} ref={fileRef} style={{ display: 'none' }} />
       </header>
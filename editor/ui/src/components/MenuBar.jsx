@@ -15,6 +15,8 @@ function useClickAway(ref, onAway) {
   }, [ref, onAway])
 }
 
+const inputStyle = { width: 240, background: '#0f1115', color: '#c7cfdb', border: '1px solid #232636', borderRadius: 4, padding: '4px 6px' }
+
 export default function MenuBar({
   onOpenProject,
   onAddImage,
@@ -27,6 +29,8 @@ export default function MenuBar({
   onDeselect,
   addr,
   setAddr,
+  creds,
+  setCreds,
   onApply,
   onRemotePlay,
   onRemotePause,
@@ -87,7 +91,14 @@ export default function MenuBar({
       <Menu id="remote" title="Remote">
         <SectionTitle>Display Address</SectionTitle>
         <div style={{ padding: '0 6px 6px 6px' }}>
-          <input value={addr} onChange={(e) => setAddr(e.target.value)} style={{ width: 240, background: '#0f1115', color: '#c7cfdb', border: '1px solid #232636', borderRadius: 4, padding: '4px 6px' }} />
+          <input value={addr} onChange={(e) => setAddr(e.target.value)} style={inputStyle} />
+        </div>
+        <SectionTitle>Credentials</SectionTitle>
+        <div style={{ display: 'flex', flexDirection: 'column', gap: 4, padding: '0 6px 6px 6px' }}>
+          <input type="password" placeholder="Token" value={creds.token} onChange={(e) => setCreds({ ...creds, token: e.target.value })} style={inputStyle} />
+          <input placeholder="CA certificate (PEM path, https only)" value={creds.caCert} onChange={(e) => setCreds({ ...creds, caCert: e.target.value })} style={inputStyle} />
+          <input placeholder="Client certificate (PEM path)" value={creds.cert} onChange={(e) => setCreds({ ...creds, cert: e.target.value })} style={inputStyle} />
+          <input placeholder="Client key (PEM path)" value={creds.key} onChange={(e) => setCreds({ ...creds, key: e.target.value })} style={inputStyle} />
         </div>
         <div style={{ display: 'flex', gap: 6, padding: '0 6px 6px 6px', alignItems:'center' }}>
           <button type="button" onPointerDown={() => { setOpen(null); onApply() }} onClick={(e)=>e.preventDefault()}>Apply</button>
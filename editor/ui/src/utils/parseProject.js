@@ -28,6 +28,8 @@ function parseNode(n) {
     transform: t,
     children: (n.children ?? []).map(parseNode),
     kind: null,
+    // Components after `kind`; not edited here, only kept so they survive a save
+    components: n.components ?? [],
   }
   // New schema: node.kind with type discriminator
   if (n.kind?.type) {
@@ -55,11 +57,14 @@ function parseNode(n) {
         break
     }
   } else {
-    // Legacy schema compatibility
-    if (n.screen) node.kind = { type: 'screen', pixels: [n.screen.pixels_x, n.screen.pixels_y], enabled: n.screen.enabled ?? true }
-    if (n.light) node.kind = { type: 'light', light: n.light }
-    if (n.camera) node.kind = { type: 'camera', cam: n.camera }
-    if (n.mesh) node.kind = { type: 'mesh', mesh: n.mesh }
+    // Legacy schema compatibility: the first field becomes `kind`, the rest extra components
+    const legacy = []
+    if (n.mesh) legacy.push({ type: 'mesh', mesh: n.mesh })
+    if (n.camera) legacy.push({ type: 'camera', cam: n.camera })
+    if (n.light) legacy.push({ type: 'light', light: n.light })
+    if (n.screen) legacy.push({ type: 'screen', pixels: [n.screen.pixels_x, n.screen.pixels_y], enabled: n.screen.enabled ?? true })
+    node.kind = legacy.shift() ?? null
+    node.components.push(...legacy)
   }
   return node
 }
@@ -7,7 +7,10 @@ fn main() {
         "../proto/constellation/v1/scene.proto",
         "../proto/constellation/v1/control.proto",
     ];
+    let out_dir = std::path::PathBuf::from(std::env::var("OUT_DIR").expect("OUT_DIR"));
     tonic_build::configure()
+        // Encoded descriptors for the gRPC reflection service
+        .file_descriptor_set_path(out_dir.join("constellation_descriptor.bin"))
         // Derive serde on all generated messages to allow JSON round-trip
         .type_attribute(".", "#[derive(serde::Serialize, serde::Deserialize)]")
         .compile(protos, &[".."])
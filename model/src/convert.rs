@@ -0,0 +1,312 @@
+//! Conversion between the JSON schema and the proto messages, both ways.
+//! JSON -> proto -> JSON gives back the same document, apart from optional
+//! fields being filled in with their defaults and legacy node components being
+//! written as `kind`/`components`. Anything dropped or guessed on the way to proto is
+//! reported in `warnings`. Proto messages are built without `..Default::default()`
+//! so that a new proto field doesn't compile until it is mapped here.
+
+use crate::json::*;
+use crate::proto::*;
+
+fn non_empty(s: String) -> Option<String> {
+    (!s.is_empty()).then_some(s)
+}
+
+// ------------- JSON -> proto -------------
+
+impl From<Vec3J> for Vec3 {
+    fn from(v: Vec3J) -> Self { Vec3 { x: v.x, y: v.y, z: v.z } }
+}
+impl From<QuatJ> for Quat {
+    fn from(q: QuatJ) -> Self { Quat { x: q.x, y: q.y, z: q.z, w: q.w } }
+}
+impl From<TransformJ> for Transform {
+    fn from(t: TransformJ) -> Self {
+        Transform { position: t.position.map(Into::into), rotation: t.rotation.map(Into::into), scale: t.scale.map(Into::into) }
+    }
+}
+impl From<ColorJ> for ColorRgba {
+    fn from(c: ColorJ) -> Self { ColorRgba { r: c.r, g: c.g, b: c.b, a: c.a } }
+}
+
+impl From<TextureRefJ> for TextureRef {
+    fn from(t: TextureRefJ) -> Self { TextureRef { uri: t.uri } }
+}
+
+impl From<MaterialPbrJ> for MaterialPbr {
+    fn from(m: MaterialPbrJ) -> Self {
+        MaterialPbr {
+            id: m.id,
+            name: m.name.unwrap_or_default(),
+            base_color: m.base_color.map(Into::into),
+            base_color_tex: m.base_color_tex.map(Into::into),
+            metallic: m.metallic.unwrap_or(0.0),
+            roughness: m.roughness.unwrap_or(1.0),
+            mr_tex: m.mr_tex.map(Into::into),
+            emissive: m.emissive.map(Into::into),
+            emissive_tex: m.emissive_tex.map(Into::into),
+        }
+    }
+}
+impl From<MeshRefJ> for MeshRef {
+    fn from(m: MeshRefJ) -> Self { MeshRef { id: m.id, uri: m.uri, node: m.node.unwrap_or_default() } }
+}
+
+fn light(l: LightCompJ, node_id: &str, warnings: &mut Vec<String>) -> LightComponent {
+    let t = light_component::Type::from_str_name(&l.r#type).unwrap_or_else(|| {
+        warnings.push(format!("node '{node_id}': unknown light type '{}', using POINT", l.r#type));
+        light_component::Type::Point
+    });
+    LightComponent { r#type: t as i32, color: Some(l.color.into()), intensity: l.intensity, range: l.range, spot_angle: l.spot_angle.unwrap_or(0.0) }
+}
+
+fn mesh(m: MeshCompJ) -> MeshComponent {
+    MeshComponent { mesh: Some(m.mesh.into()), material_id: m.material_id.unwrap_or_default() }
+}
+
+impl KindJ {
+    fn into_proto(self, node_id: &str, warnings: &mut Vec<String>) -> component::Kind {
+        match self {
+            KindJ::Screen { pixels: [x, y], enabled } => component::Kind::Screen(ScreenComponent { pixels_x: x, pixels_y: y, disabled: !enabled }),
+            KindJ::Light(l) => component::Kind::Light(light(l, node_id, warnings)),
+            KindJ::Camera(c) => component::Kind::Camera(CameraComponent { fov_deg: c.fov_deg, near: c.near, far: c.far }),
+            KindJ::Mesh(m) => component::Kind::Mesh(mesh(m)),
+            KindJ::Other(v) => {
+                let ty = v.get("type").and_then(|t| t.as_str()).unwrap_or_default().to_string();
+                warnings.push(format!("node '{node_id}': unknown kind '{ty}' is kept but not rendered"));
+                component::Kind::Unknown(UnknownComponent { r#type: ty, json: v.to_string() })
+            }
+        }
+    }
+}
+
+impl NodeJ {
+    pub fn into_proto(self, warnings: &mut Vec<String>) -> Node {
+        let mut kinds: Vec<component::Kind> = self.kind.into_iter().chain(self.components).map(|k| k.into_proto(&self.id, warnings)).collect();
+        if let Some(m) = self.mesh {
+            kinds.push(component::Kind::Mesh(mesh(m)));
+        }
+        if let Some(c) = self.camera {
+            kinds.push(component::Kind::Camera(CameraComponent { fov_deg: c.fov_deg, near: c.near, far: c.far }));
+        }
+        if let Some(l) = self.light {
+            kinds.push(component::Kind::Light(light(l, &self.id, warnings)));
+        }
+        if let Some(s) = self.screen {
+            kinds.push(component::Kind::Screen(ScreenComponent { pixels_x: s.pixels_x, pixels_y: s.pixels_y, disabled: !s.enabled.unwrap_or(true) }));
+        }
+        Node {
+            children: self.children.into_iter().map(|c| c.into_proto(warnings)).collect(),
+            id: self.id,
+            name: self.name.unwrap_or_default(),
+            transform: Some(self.transform.into()),
+            components: kinds.into_iter().map(|k| Component { kind: Some(k) }).collect(),
+            comp0: None,
+        }
+    }
+}
+
+impl SceneJ {
+    pub fn into_proto(self, warnings: &mut Vec<String>) -> Scene {
+        Scene {
+            id: self.id,
+            name: self.name.unwrap_or_default(),
+            materials: self.materials.into_iter().map(Into::into).collect(),
+            meshes: self.meshes.into_iter().map(Into::into).collect(),
+            roots: self.roots.into_iter().map(|n| n.into_proto(warnings)).collect(),
+        }
+    }
+}
+
+impl TimelineJ {
+    pub fn into_proto(self, warnings: &mut Vec<String>) -> Timeline {
+        let id = self.id;
+        let tracks = self.tracks.into_iter().enumerate().filter_map(|(i, t)| {
+            if let Some(m) = t.media {
+                if t.transform.is_some() {
+                    warnings.push(format!("timeline '{id}': track {i} has both media and transform; only media is kept"));
+                }
+                return Some(TimelineTrack { kind: Some(timeline_track::Kind::Media(TrackMedia { target_node_id: m.target_node_id, clip_id: m.clip_id, in_seconds: m.in_seconds, out_seconds: m.out_seconds, start_at_seconds: m.start_at_seconds })) });
+            }
+            let Some(tt) = t.transform else {
+                warnings.push(format!("timeline '{id}': track {i} has neither media nor transform and is dropped"));
+                return None;
+            };
+            let keys = tt.keys.into_iter().map(|k| TrackTransformKey { t: k.t, transform: k.transform.map(Into::into) }).collect();
+            Some(TimelineTrack { kind: Some(timeline_track::Kind::Transform(TrackTransform { target_node_id: tt.target_node_id, keys })) })
+        }).collect();
+        let events = self.events.into_iter().map(|e| TimelineEvent { t: e.t, action: e.action, params: e.params }).collect();
+        Timeline { id, name: self.name.unwrap_or_default(), tracks, events, duration_seconds: self.duration_seconds }
+    }
+}
+
+impl ProjectJ {
+    pub fn into_proto(self, warnings: &mut Vec<String>) -> Project {
+        let media = self.media.into_iter().map(|m| MediaClip { id: m.id, name: m.name.unwrap_or_default(), uri: m.uri, duration_seconds: m.duration_seconds, fps: m.fps }).collect();
+        let timelines = self.timeline.into_iter().chain(self.timelines).map(|t| t.into_proto(warnings)).collect();
+        Project {
+            id: self.id,
+            name: self.name.unwrap_or_default(),
+            scene: Some(self.scene.into_proto(warnings)),
+            media,
+            timelines,
+            active_timeline_id: self.active_timeline_id.unwrap_or_default(),
+        }
+    }
+}
+
+impl OutputConfigJ {
+    pub fn into_proto(self, warnings: &mut Vec<String>) -> OutputConfig {
+        let windows = self.windows.into_iter().map(|w| {
+            let mode = match w.mode.as_deref() {
+                None => output_window::Mode::Windowed,
+                Some(m) => output_window::Mode::from_str_name(m).unwrap_or_else(|| {
+                    warnings.push(format!("output '{}': unknown mode '{m}', using WINDOWED", w.id));
+                    output_window::Mode::Windowed
+                }),
+            };
+            let screens = w.screens.into_iter().map(|s| OutputScreen { node_id: s.node_id, x: s.x, y: s.y }).collect();
+            OutputWindow { id: w.id, mode: mode as i32, monitor: w.monitor.unwrap_or_default(), x: w.x, y: w.y, screens }
+        }).collect();
+        OutputConfig { windows }
+    }
+}
+
+// ------------- proto -> JSON -------------
+
+impl From<&Vec3> for Vec3J {
+    fn from(v: &Vec3) -> Self { Vec3J { x: v.x, y: v.y, z: v.z } }
+}
+impl From<&Quat> for QuatJ {
+    fn from(q: &Quat) -> Self { QuatJ { x: q.x, y: q.y, z: q.z, w: q.w } }
+}
+impl From<&Transform> for TransformJ {
+    fn from(t: &Transform) -> Self {
+        TransformJ { position: t.position.as_ref().map(Into::into), rotation: t.rotation.as_ref().map(Into::into), scale: t.scale.as_ref().map(Into::into) }
+    }
+}
+impl From<&ColorRgba> for ColorJ {
+    fn from(c: &ColorRgba) -> Self { ColorJ { r: c.r, g: c.g, b: c.b, a: c.a } }
+}
+
+impl From<&TextureRef> for TextureRefJ {
+    fn from(t: &TextureRef) -> Self { TextureRefJ { uri: t.uri.clone() } }
+}
+
+impl From<&MaterialPbr> for MaterialPbrJ {
+    fn from(m: &MaterialPbr) -> Self {
+        MaterialPbrJ {
+            id: m.id.clone(),
+            name: non_empty(m.name.clone()),
+            base_color: m.base_color.as_ref().map(Into::into),
+            base_color_tex: m.base_color_tex.as_ref().map(Into::into),
+            metallic: Some(m.metallic),
+            roughness: Some(m.roughness),
+            mr_tex: m.mr_tex.as_ref().map(Into::into),
+            emissive: m.emissive.as_ref().map(Into::into),
+            emissive_tex: m.emissive_tex.as_ref().map(Into::into),
+        }
+    }
+}
+impl From<&MeshRef> for MeshRefJ {
+    fn from(m: &MeshRef) -> Self { MeshRefJ { id: m.id.clone(), uri: m.uri.clone(), node: non_empty(m.node.clone()) } }
+}
+
+impl From<&component::Kind> for KindJ {
+    fn from(k: &component::Kind) -> Self {
+        match k {
+            component::Kind::Screen(s) => KindJ::Screen { pixels: [s.pixels_x, s.pixels_y], enabled: !s.disabled },
+            component::Kind::Light(l) => KindJ::Light(LightCompJ {
+                r#type: l.r#type().as_str_name().to_string(),
+                color: l.color.as_ref().map_or(ColorJ { r: 1.0, g: 1.0, b: 1.0, a: 1.0 }, Into::into),
+                intensity: l.intensity,
+                range: l.range,
+                spot_angle: Some(l.spot_angle),
+            }),
+            component::Kind::Camera(c) => KindJ::Camera(CameraCompJ { fov_deg: c.fov_deg, near: c.near, far: c.far }),
+            component::Kind::Mesh(m) => KindJ::Mesh(MeshCompJ {
+                mesh: m.mesh.as_ref().map_or(MeshRefJ { id: String::new(), uri: String::new(), node: None }, Into::into),
+                material_id: non_empty(m.material_id.clone()),
+            }),
+            component::Kind::Unknown(u) => KindJ::Other(serde_json::from_str(&u.json).unwrap_or_else(|_| serde_json::json!({ "type": u.r#type }))),
+        }
+    }
+}
+
+/// Written in the editor's `kind` schema, `comp0` included.
+impl From<&Node> for NodeJ {
+    fn from(n: &Node) -> Self {
+        let legacy = n.comp0.clone().map(component::Kind::from);
+        let mut kinds = legacy.iter().chain(n.kinds()).map(KindJ::from);
+        NodeJ {
+            id: n.id.clone(),
+            name: non_empty(n.name.clone()),
+            transform: n.transform.as_ref().map(Into::into).unwrap_or_default(),
+            children: n.children.iter().map(Into::into).collect(),
+            kind: kinds.next(),
+            components: kinds.collect(),
+            mesh: None,
+            light: None,
+            screen: None,
+            camera: None,
+        }
+    }
+}
+
+impl From<&Scene> for SceneJ {
+    fn from(s: &Scene) -> Self {
+        SceneJ {
+            id: s.id.clone(),
+            name: non_empty(s.name.clone()),
+            materials: s.materials.iter().map(Into::into).collect(),
+            meshes: s.meshes.iter().map(Into::into).collect(),
+            roots: s.roots.iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl From<&Timeline> for TimelineJ {
+    fn from(t: &Timeline) -> Self {
+        let tracks = t.tracks.iter().filter_map(|t| match t.kind.as_ref()? {
+            timeline_track::Kind::Media(m) => Some(TimelineTrackJ {
+                media: Some(TrackMediaJ { target_node_id: m.target_node_id.clone(), clip_id: m.clip_id.clone(), in_seconds: m.in_seconds, out_seconds: m.out_seconds, start_at_seconds: m.start_at_seconds }),
+                transform: None,
+            }),
+            timeline_track::Kind::Transform(tt) => {
+                let keys = tt.keys.iter().map(|k| TrackTransformKeyJ { t: k.t, transform: k.transform.as_ref().map(Into::into) }).collect();
+                Some(TimelineTrackJ { media: None, transform: Some(TrackTransformJ { target_node_id: tt.target_node_id.clone(), keys }) })
+            }
+        }).collect();
+        let events = t.events.iter().map(|e| TimelineEventJ { t: e.t, action: e.action.clone(), params: e.params.clone() }).collect();
+        TimelineJ { id: t.id.clone(), name: non_empty(t.name.clone()), tracks, events, duration_seconds: t.duration_seconds }
+    }
+}
+
+impl From<&Project> for ProjectJ {
+    fn from(p: &Project) -> Self {
+        let media = p.media.iter().map(|m| MediaClipJ { id: m.id.clone(), name: non_empty(m.name.clone()), uri: m.uri.clone(), duration_seconds: m.duration_seconds, fps: m.fps }).collect();
+        ProjectJ {
+            id: p.id.clone(),
+            name: non_empty(p.name.clone()),
+            scene: p.scene.as_ref().map_or(SceneJ { id: String::new(), name: None, materials: vec![], meshes: vec![], roots: vec![] }, Into::into),
+            media,
+            timeline: None,
+            timelines: p.timelines.iter().map(Into::into).collect(),
+            active_timeline_id: non_empty(p.active_timeline_id.clone()),
+        }
+    }
+}
+
+impl From<&OutputConfig> for OutputConfigJ {
+    fn from(o: &OutputConfig) -> Self {
+        let windows = o.windows.iter().map(|w| OutputWindowJ {
+            id: w.id.clone(),
+            mode: Some(w.mode().as_str_name().to_string()),
+            monitor: non_empty(w.monitor.clone()),
+            x: w.x,
+            y: w.y,
+            screens: w.screens.iter().map(|s| OutputScreenJ { node_id: s.node_id.clone(), x: s.x, y: s.y }).collect(),
+        }).collect();
+        OutputConfigJ { windows }
+    }
+}
@@ -0,0 +1,404 @@
+//! The editor-facing JSON schema. Optional fields fall back to the proto defaults
+//! (`roughness` to 1); enums are their proto names, e.g. `"SPOT"`, `"FULLSCREEN"`.
+//! Doc comments here end up in the published JSON Schema.
+
+use std::collections::HashMap;
+
+use schemars::gen::SchemaGenerator;
+use schemars::schema::{Schema, SchemaObject, SubschemaValidation};
+use schemars::JsonSchema;
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[schemars(rename = "Vec3")]
+pub struct Vec3J { pub x: f32, pub y: f32, pub z: f32 }
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[schemars(rename = "Quat")]
+pub struct QuatJ { pub x: f32, pub y: f32, pub z: f32, pub w: f32 }
+/// Missing parts are the identity (no translation or rotation, unit scale), or
+/// for a transform key, the node's own transform.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize, JsonSchema)]
+#[schemars(rename = "Transform")]
+pub struct TransformJ {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub position: Option<Vec3J>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rotation: Option<QuatJ>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scale: Option<Vec3J>,
+}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[schemars(rename = "Color")]
+pub struct ColorJ { pub r: f32, pub g: f32, pub b: f32, pub a: f32 }
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[schemars(rename = "TextureRef")]
+pub struct TextureRefJ { pub uri: String }
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[schemars(rename = "MaterialPbr")]
+pub struct MaterialPbrJ {
+    pub id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_color: Option<ColorJ>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_color_tex: Option<TextureRefJ>,
+    /// Default 0
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metallic: Option<f32>,
+    /// Default 1
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub roughness: Option<f32>,
+    /// Packed metallic/roughness
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mr_tex: Option<TextureRefJ>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub emissive: Option<ColorJ>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub emissive_tex: Option<TextureRefJ>,
+}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[schemars(rename = "MeshRef")]
+pub struct MeshRefJ {
+    pub id: String,
+    /// e.g. `file:///assets/stage.glb`
+    pub uri: String,
+    /// Node path inside the asset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub node: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[schemars(rename = "MeshComponent")]
+pub struct MeshCompJ {
+    pub mesh: MeshRefJ,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub material_id: Option<String>,
+}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[schemars(rename = "LightComponent")]
+pub struct LightCompJ {
+    /// `POINT`, `DIRECTIONAL` or `SPOT`
+    #[schemars(schema_with = "light_types")]
+    pub r#type: String,
+    pub color: ColorJ,
+    pub intensity: f32,
+    /// Meters
+    pub range: f32,
+    /// Degrees, for `SPOT`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spot_angle: Option<f32>,
+}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[schemars(rename = "ScreenComponent")]
+pub struct ScreenCompJ {
+    pub pixels_x: i32,
+    pub pixels_y: i32,
+    /// Default true
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[schemars(rename = "CameraComponent")]
+pub struct CameraCompJ {
+    /// Vertical field of view
+    pub fov_deg: f32,
+    pub near: f32,
+    pub far: f32,
+}
+
+/// The editor's node component, discriminated by `type`:
+/// `{"type": "screen", "pixels": [w, h], "enabled": true}`, `{"type": "light", "light": {...}}`,
+/// `{"type": "camera", "cam": {...}}` or `{"type": "mesh", "mesh": {...}}`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum KindJ {
+    Screen { pixels: [i32; 2], enabled: bool },
+    Light(LightCompJ),
+    Camera(CameraCompJ),
+    Mesh(MeshCompJ),
+    /// Any other `type`, kept verbatim
+    Other(Value),
+}
+
+fn string_enum(values: &[&str]) -> Schema {
+    serde_json::from_value(serde_json::json!({ "type": "string", "enum": values })).expect("valid schema")
+}
+
+fn light_types(_: &mut SchemaGenerator) -> Schema {
+    string_enum(&["POINT", "DIRECTIONAL", "SPOT"])
+}
+
+fn window_modes(_: &mut SchemaGenerator) -> Schema {
+    string_enum(&["WINDOWED", "BORDERLESS", "FULLSCREEN"])
+}
+
+const KNOWN_KINDS: [&str; 4] = ["screen", "light", "camera", "mesh"];
+
+impl KindJ {
+    pub fn type_name(&self) -> &str {
+        match self {
+            KindJ::Screen { .. } => "screen",
+            KindJ::Light(_) => "light",
+            KindJ::Camera(_) => "camera",
+            KindJ::Mesh(_) => "mesh",
+            KindJ::Other(v) => v.get("type").and_then(Value::as_str).unwrap_or_default(),
+        }
+    }
+}
+
+// The known kinds as serde writes them, also describing them in the JSON Schema
+/// A screen, light, camera or mesh
+#[derive(Serialize, JsonSchema)]
+#[serde(tag = "type", rename_all = "lowercase")]
+#[schemars(rename = "KnownKind")]
+enum Tagged<'a> {
+    Screen {
+        pixels: &'a [i32; 2],
+        /// Default true
+        enabled: bool,
+    },
+    Light { light: &'a LightCompJ },
+    Camera {
+        /// Also read as `camera`
+        cam: &'a CameraCompJ,
+    },
+    Mesh { mesh: &'a MeshCompJ },
+}
+
+impl Serialize for KindJ {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        // Through a derived enum rather than `json!`, which would print f32s widened to f64
+        match self {
+            KindJ::Screen { pixels, enabled } => Tagged::Screen { pixels, enabled: *enabled }.serialize(s),
+            KindJ::Light(light) => Tagged::Light { light }.serialize(s),
+            KindJ::Camera(cam) => Tagged::Camera { cam }.serialize(s),
+            KindJ::Mesh(mesh) => Tagged::Mesh { mesh }.serialize(s),
+            KindJ::Other(v) => v.serialize(s),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for KindJ {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        struct ScreenK { pixels: [i32; 2], enabled: Option<bool> }
+        #[derive(Deserialize)]
+        struct LightK { light: LightCompJ }
+        #[derive(Deserialize)]
+        struct CameraK { #[serde(alias = "camera")] cam: CameraCompJ }
+        #[derive(Deserialize)]
+        struct MeshK { mesh: MeshCompJ }
+
+        let v = Value::deserialize(d)?;
+        let Some(ty) = v.get("type").and_then(Value::as_str) else {
+            return Err(D::Error::custom("node kind without a string `type`"));
+        };
+        let malformed = |e: serde_json::Error| D::Error::custom(format!("malformed {ty} kind: {e}"));
+        Ok(match ty {
+            "screen" => {
+                let k: ScreenK = serde_json::from_value(v.clone()).map_err(malformed)?;
+                KindJ::Screen { pixels: k.pixels, enabled: k.enabled.unwrap_or(true) }
+            }
+            "light" => KindJ::Light(serde_json::from_value::<LightK>(v.clone()).map_err(malformed)?.light),
+            "camera" => KindJ::Camera(serde_json::from_value::<CameraK>(v.clone()).map_err(malformed)?.cam),
+            "mesh" => KindJ::Mesh(serde_json::from_value::<MeshK>(v.clone()).map_err(malformed)?.mesh),
+            _ => KindJ::Other(v),
+        })
+    }
+}
+
+impl JsonSchema for KindJ {
+    fn schema_name() -> String {
+        "Kind".into()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        let other: Schema = serde_json::from_value(serde_json::json!({
+            "description": "Any other kind, kept but not rendered",
+            "type": "object",
+            "required": ["type"],
+            "properties": { "type": { "type": "string", "not": { "enum": KNOWN_KINDS } } }
+        }))
+        .expect("valid schema");
+        let mut schema = SchemaObject::default();
+        schema.metadata().description = Some("A node component, discriminated by `type`".into());
+        schema.subschemas = Some(Box::new(SubschemaValidation {
+            any_of: Some(vec![gen.subschema_for::<Tagged>(), other]),
+            ..Default::default()
+        }));
+        schema.into()
+    }
+}
+
+/// A scene node. Its components are `kind` followed by `components` (the editor's
+/// schema) and any of the legacy `mesh`/`light`/`screen`/`camera` fields; the
+/// first is written back as `kind`, the rest as `components`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[schemars(rename = "Node")]
+pub struct NodeJ {
+    pub id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub transform: TransformJ,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<NodeJ>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<KindJ>,
+    /// Components after `kind`, e.g. the mesh of an LED wall whose `kind` is its screen
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub components: Vec<KindJ>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mesh: Option<MeshCompJ>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub light: Option<LightCompJ>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub screen: Option<ScreenCompJ>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub camera: Option<CameraCompJ>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[schemars(rename = "Scene")]
+pub struct SceneJ {
+    pub id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub materials: Vec<MaterialPbrJ>,
+    #[serde(default)]
+    pub meshes: Vec<MeshRefJ>,
+    #[serde(default)]
+    pub roots: Vec<NodeJ>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[schemars(rename = "MediaClip")]
+pub struct MediaClipJ {
+    pub id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// File/NDI/etc; image sequences use a frame pattern, e.g. `file:///shots/intro/frame_%05d.png`
+    pub uri: String,
+    pub duration_seconds: f64,
+    /// Frame rate of image sequences
+    #[serde(default)]
+    pub fps: f64,
+}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[schemars(rename = "TrackMedia")]
+pub struct TrackMediaJ {
+    /// A node with a screen
+    pub target_node_id: String,
+    pub clip_id: String,
+    pub in_seconds: f64,
+    pub out_seconds: f64,
+    /// Position on the timeline
+    pub start_at_seconds: f64,
+}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[schemars(rename = "TrackTransformKey")]
+pub struct TrackTransformKeyJ {
+    pub t: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transform: Option<TransformJ>,
+}
+/// Keys are interpolated linearly (rotation spherically) and need not be sorted.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[schemars(rename = "TrackTransform")]
+pub struct TrackTransformJ {
+    pub target_node_id: String,
+    #[serde(default)]
+    pub keys: Vec<TrackTransformKeyJ>,
+}
+/// One of `media` or `transform`; a track with neither is dropped with a warning.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[schemars(rename = "TimelineTrack")]
+pub struct TimelineTrackJ {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub media: Option<TrackMediaJ>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transform: Option<TrackTransformJ>,
+}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[schemars(rename = "TimelineEvent")]
+pub struct TimelineEventJ {
+    /// Seconds
+    pub t: f64,
+    /// e.g. `play`, `pause`, `set_opacity`
+    pub action: String,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub params: HashMap<String, String>,
+}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[schemars(rename = "Timeline")]
+pub struct TimelineJ {
+    pub id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub tracks: Vec<TimelineTrackJ>,
+    #[serde(default)]
+    pub events: Vec<TimelineEventJ>,
+    pub duration_seconds: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[schemars(rename = "Project")]
+pub struct ProjectJ {
+    pub id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub scene: SceneJ,
+    #[serde(default)]
+    pub media: Vec<MediaClipJ>,
+    /// Legacy single timeline; placed before `timelines` when both are present.
+    /// Never written.
+    #[serde(default, skip_serializing)]
+    pub timeline: Option<TimelineJ>,
+    #[serde(default)]
+    pub timelines: Vec<TimelineJ>,
+    /// Default: the first timeline
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub active_timeline_id: Option<String>,
+}
+/// A Constellation project document, as saved by the editor.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[schemars(rename = "ProjectDocument")]
+pub struct ProjectWrapperJ { pub project: ProjectJ }
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[schemars(rename = "OutputScreen")]
+pub struct OutputScreenJ {
+    pub node_id: String,
+    /// Top-left of the canvas inside the window, in pixels
+    #[serde(default)]
+    pub x: i32,
+    #[serde(default)]
+    pub y: i32,
+}
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[schemars(rename = "OutputWindow")]
+pub struct OutputWindowJ {
+    pub id: String,
+    /// `WINDOWED` (default), `BORDERLESS` or `FULLSCREEN`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schemars(schema_with = "window_modes")]
+    pub mode: Option<String>,
+    /// Monitor name as reported by the OS; default the primary one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub monitor: Option<String>,
+    /// Window position relative to the monitor
+    #[serde(default)]
+    pub x: i32,
+    #[serde(default)]
+    pub y: i32,
+    pub screens: Vec<OutputScreenJ>,
+}
+/// The windows a display shows screens in.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[schemars(rename = "OutputConfig")]
+pub struct OutputConfigJ { pub windows: Vec<OutputWindowJ> }
@@ -0,0 +1,62 @@
+//! The Constellation project model shared by the display, the CLI and the editor:
+//! the generated protobuf/gRPC types, the editor-facing JSON schema and the
+//! conversion between the two. The JSON Schema of both documents is published in
+//! `model/schema/`.
+
+mod convert;
+pub mod json;
+mod node;
+
+pub mod proto {
+    tonic::include_proto!("constellation.v1");
+
+    pub const FILE_DESCRIPTOR_SET: &[u8] = tonic::include_file_descriptor_set!("constellation_descriptor");
+}
+
+use json::{OutputConfigJ, ProjectJ, ProjectWrapperJ};
+use proto::{OutputConfig, Project};
+
+/// A document converted to proto, with what was wrong with it that didn't stop
+/// the conversion (unknown enum names, ignored components, dropped tracks).
+#[derive(Debug)]
+pub struct Converted<T> {
+    pub value: T,
+    pub warnings: Vec<String>,
+}
+
+/// Parse a project document (`{"project": {...}}`). Malformed documents, including
+/// nodes with a malformed `kind`, are rejected.
+pub fn project_from_json(s: &str) -> Result<Converted<Project>, serde_json::Error> {
+    let wrapper: ProjectWrapperJ = serde_json::from_str(s)?;
+    let mut warnings = Vec::new();
+    let value = wrapper.project.into_proto(&mut warnings);
+    Ok(Converted { value, warnings })
+}
+
+/// Write `project` as a pretty-printed project document.
+pub fn project_to_json(project: &Project) -> Result<String, serde_json::Error> {
+    serde_json::to_string_pretty(&ProjectWrapperJ { project: ProjectJ::from(project) })
+}
+
+/// Parse an output configuration (`{"windows": [...]}`).
+pub fn outputs_from_json(s: &str) -> Result<Converted<OutputConfig>, serde_json::Error> {
+    let config: OutputConfigJ = serde_json::from_str(s)?;
+    let mut warnings = Vec::new();
+    let value = config.into_proto(&mut warnings);
+    Ok(Converted { value, warnings })
+}
+
+/// Write `config` as a pretty-printed output configuration.
+pub fn outputs_to_json(config: &OutputConfig) -> Result<String, serde_json::Error> {
+    serde_json::to_string_pretty(&OutputConfigJ::from(config))
+}
+
+/// JSON Schema of project documents.
+pub fn project_schema() -> String {
+    serde_json::to_string_pretty(&schemars::schema_for!(ProjectWrapperJ)).expect("schema serializes")
+}
+
+/// JSON Schema of output configurations.
+pub fn outputs_schema() -> String {
+    serde_json::to_string_pretty(&schemars::schema_for!(OutputConfigJ)).expect("schema serializes")
+}
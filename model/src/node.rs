@@ -0,0 +1,75 @@
+//! Lookups over a node's components, and the upgrade of the single `comp0`
+//! component older clients send.
+
+use crate::proto::*;
+
+impl From<node::Comp0> for component::Kind {
+    fn from(c: node::Comp0) -> Self {
+        match c {
+            node::Comp0::Mesh(m) => component::Kind::Mesh(m),
+            node::Comp0::Light(l) => component::Kind::Light(l),
+            node::Comp0::Screen(s) => component::Kind::Screen(s),
+            node::Comp0::Camera(c) => component::Kind::Camera(c),
+            node::Comp0::Unknown(u) => component::Kind::Unknown(u),
+        }
+    }
+}
+
+impl Node {
+    /// The node's components, in order. Doesn't see `comp0`; see [`Node::upgrade_components`].
+    pub fn kinds(&self) -> impl Iterator<Item = &component::Kind> {
+        self.components.iter().filter_map(|c| c.kind.as_ref())
+    }
+
+    /// The first screen component.
+    pub fn screen(&self) -> Option<&ScreenComponent> {
+        self.kinds().find_map(|k| match k {
+            component::Kind::Screen(s) => Some(s),
+            _ => None,
+        })
+    }
+
+    /// The first camera component.
+    pub fn camera(&self) -> Option<&CameraComponent> {
+        self.kinds().find_map(|k| match k {
+            component::Kind::Camera(c) => Some(c),
+            _ => None,
+        })
+    }
+
+    /// The mesh components.
+    pub fn meshes(&self) -> impl Iterator<Item = &MeshComponent> {
+        self.kinds().filter_map(|k| match k {
+            component::Kind::Mesh(m) => Some(m),
+            _ => None,
+        })
+    }
+
+    /// Move `comp0` to the front of `components`, in this node and its descendants.
+    pub fn upgrade_components(&mut self) {
+        if let Some(c) = self.comp0.take() {
+            self.components.insert(0, Component { kind: Some(c.into()) });
+        }
+        for child in &mut self.children {
+            child.upgrade_components();
+        }
+    }
+}
+
+impl Scene {
+    /// [`Node::upgrade_components`] for every node.
+    pub fn upgrade_components(&mut self) {
+        for root in &mut self.roots {
+            root.upgrade_components();
+        }
+    }
+}
+
+impl Project {
+    /// [`Node::upgrade_components`] for every node of the scene.
+    pub fn upgrade_components(&mut self) {
+        if let Some(scene) = &mut self.scene {
+            scene.upgrade_components();
+        }
+    }
+}
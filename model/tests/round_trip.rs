@@ -0,0 +1,273 @@
+use constellation_model::json::{NodeJ, ProjectWrapperJ};
+use constellation_model::proto::*;
+use constellation_model::{outputs_from_json, outputs_to_json, project_from_json, project_to_json, Converted};
+use serde_json::{json, Value};
+
+fn transform(x: f32) -> Transform {
+    Transform {
+        position: Some(Vec3 { x, y: 2.0, z: -1.5 }),
+        rotation: Some(Quat { x: 0.0, y: 0.5, z: 0.0, w: 0.75 }),
+        scale: Some(Vec3 { x: 4.0, y: 2.0, z: 1.0 }),
+    }
+}
+
+fn node(id: &str, kinds: Vec<component::Kind>, children: Vec<Node>) -> Node {
+    let components = kinds.into_iter().map(|k| Component { kind: Some(k) }).collect();
+    Node { id: id.into(), name: format!("{id} node"), transform: Some(transform(1.0)), children, components, comp0: None }
+}
+
+/// A project touching every message and enum value the JSON schema covers.
+fn sample_project() -> Project {
+    let white = ColorRgba { r: 1.0, g: 1.0, b: 1.0, a: 1.0 };
+    let mesh = MeshRef { id: "stage".into(), uri: "file:///assets/stage.glb".into(), node: "Stage/Floor".into() };
+    let light = |t: light_component::Type| LightComponent { r#type: t as i32, color: Some(white.clone()), intensity: 800.0, range: 12.0, spot_angle: 30.0 };
+    let roots = vec![
+        node("wall", vec![
+            component::Kind::Screen(ScreenComponent { pixels_x: 1920, pixels_y: 1080, disabled: false }),
+            component::Kind::Mesh(MeshComponent { mesh: Some(mesh.clone()), material_id: "matte".into() }),
+            component::Kind::Light(light(light_component::Type::Point)),
+        ], vec![
+            node("floor", vec![component::Kind::Mesh(MeshComponent { mesh: Some(mesh.clone()), material_id: "matte".into() })], vec![]),
+        ]),
+        node("cam", vec![component::Kind::Camera(CameraComponent { fov_deg: 40.0, near: 0.25, far: 100.0 })], vec![]),
+        node("key", vec![component::Kind::Light(light(light_component::Type::Spot))], vec![]),
+        node("sun", vec![component::Kind::Light(light(light_component::Type::Directional))], vec![]),
+        node("bulb", vec![component::Kind::Light(light(light_component::Type::Point))], vec![]),
+        node("spare", vec![component::Kind::Screen(ScreenComponent { pixels_x: 640, pixels_y: 480, disabled: true })], vec![]),
+        node("fog", vec![component::Kind::Unknown(UnknownComponent { r#type: "volume".into(), json: r#"{"density":2,"type":"volume"}"#.into() })], vec![]),
+        node("empty", vec![], vec![]),
+    ];
+    let materials = vec![MaterialPbr {
+        id: "matte".into(),
+        name: "Matte".into(),
+        base_color: Some(ColorRgba { r: 0.5, g: 0.25, b: 0.125, a: 1.0 }),
+        base_color_tex: Some(TextureRef { uri: "file:///textures/matte_albedo.png".into() }),
+        metallic: 0.0,
+        roughness: 0.75,
+        mr_tex: Some(TextureRef { uri: "file:///textures/matte_mr.png".into() }),
+        emissive: Some(white),
+        emissive_tex: Some(TextureRef { uri: "file:///textures/matte_emissive.png".into() }),
+    }];
+    let tracks = vec![
+        TimelineTrack { kind: Some(timeline_track::Kind::Media(TrackMedia { target_node_id: "wall".into(), clip_id: "intro".into(), in_seconds: 1.0, out_seconds: 9.0, start_at_seconds: 2.0 })) },
+        TimelineTrack {
+            kind: Some(timeline_track::Kind::Transform(TrackTransform {
+                target_node_id: "cam".into(),
+                keys: vec![
+                    TrackTransformKey { t: 0.0, transform: Some(transform(0.0)) },
+                    TrackTransformKey { t: 4.0, transform: Some(transform(3.0)) },
+                    // Partial keys fall back to the node's own transform
+                    TrackTransformKey { t: 6.0, transform: Some(Transform { position: Some(Vec3 { x: 1.0, y: 0.0, z: 0.0 }), ..Default::default() }) },
+                    TrackTransformKey { t: 8.0, transform: None },
+                ],
+            })),
+        },
+    ];
+    let events = vec![TimelineEvent { t: 5.0, action: "pause".into(), params: [("reason".to_string(), "intermission".to_string())].into() }];
+    Project {
+        id: "show".into(),
+        name: "Show".into(),
+        scene: Some(Scene { id: "stage".into(), name: "Stage".into(), materials, meshes: vec![mesh], roots }),
+        media: vec![
+            MediaClip { id: "intro".into(), name: "Intro".into(), uri: "file:///shots/intro.mp4".into(), duration_seconds: 10.0, fps: 0.0 },
+            MediaClip { id: "seq".into(), name: String::new(), uri: "file:///shots/seq/frame_%05d.png".into(), duration_seconds: 4.0, fps: 24.0 },
+        ],
+        timelines: vec![
+            Timeline { id: "main".into(), name: "Main".into(), tracks, events, duration_seconds: 10.0 },
+            Timeline { id: "encore".into(), duration_seconds: 4.0, ..Default::default() },
+        ],
+        active_timeline_id: "encore".into(),
+    }
+}
+
+fn project(doc: &Value) -> Converted<Project> {
+    project_from_json(&doc.to_string()).unwrap()
+}
+
+fn identity() -> Value {
+    json!({"position": {"x": 0.0, "y": 0.0, "z": 0.0}, "rotation": {"x": 0.0, "y": 0.0, "z": 0.0, "w": 1.0}, "scale": {"x": 1.0, "y": 1.0, "z": 1.0}})
+}
+
+fn with_node(node: Value) -> Value {
+    json!({"project": {"id": "p", "scene": {"id": "s", "roots": [node]}}})
+}
+
+#[test]
+fn proto_survives_json() {
+    let project = sample_project();
+    let text = project_to_json(&project).unwrap();
+    let converted = project_from_json(&text).unwrap();
+    assert_eq!(converted.value, project);
+    // The unknown kind is the only thing worth a warning
+    assert_eq!(converted.warnings, ["node 'fog': unknown kind 'volume' is kept but not rendered"]);
+}
+
+#[test]
+fn json_survives_proto() {
+    let doc = json!({"project": {
+        "id": "p",
+        "name": "Demo",
+        "scene": {
+            "id": "s",
+            "materials": [{"id": "m", "base_color": {"r": 1.0, "g": 0.5, "b": 0.0, "a": 1.0}, "base_color_tex": {"uri": "albedo.png"},
+                           "metallic": 0.5, "roughness": 0.25, "mr_tex": {"uri": "mr.png"}, "emissive_tex": {"uri": "glow.png"}}],
+            "meshes": [{"id": "stage", "uri": "stage.glb"}],
+            "roots": [
+                {"id": "wall", "transform": {"position": {"x": 0.0, "y": 2.0, "z": 0.0}, "rotation": {"x": 0.0, "y": 0.0, "z": 0.0, "w": 1.0}, "scale": {"x": 4.0, "y": 2.0, "z": 1.0}},
+                 "kind": {"type": "screen", "pixels": [320, 160], "enabled": true},
+                 "children": [{"id": "lamp", "transform": {"position": {"x": 0.0, "y": 1.0, "z": 0.0}, "rotation": {"x": 0.0, "y": 0.0, "z": 0.0, "w": 1.0}, "scale": {"x": 1.0, "y": 1.0, "z": 1.0}},
+                               "kind": {"type": "light", "light": {"type": "SPOT", "color": {"r": 1.0, "g": 1.0, "b": 1.0, "a": 1.0}, "intensity": 10.0, "range": 5.0, "spot_angle": 45.0}}}]},
+                {"id": "cam", "transform": identity(), "kind": {"type": "camera", "cam": {"fov_deg": 40.0, "near": 0.5, "far": 100.0}}},
+                {"id": "floor", "transform": identity(), "kind": {"type": "mesh", "mesh": {"mesh": {"id": "stage", "uri": "stage.glb"}, "material_id": "m"}}},
+                {"id": "led", "transform": identity(), "kind": {"type": "screen", "pixels": [640, 360], "enabled": true},
+                 "components": [{"type": "mesh", "mesh": {"mesh": {"id": "stage", "uri": "stage.glb", "node": "Wall"}}}, {"type": "volume", "density": 1}]},
+                {"id": "fog", "transform": identity(), "kind": {"type": "volume", "density": 2, "tint": [1, 0, 0]}}
+            ]
+        },
+        "media": [{"id": "g", "uri": "grad.png", "duration_seconds": 10.0, "fps": 0.0}],
+        "timelines": [{"id": "main", "duration_seconds": 10.0, "events": [{"t": 2.0, "action": "pause"}],
+                       "tracks": [{"media": {"target_node_id": "wall", "clip_id": "g", "in_seconds": 0.0, "out_seconds": 10.0, "start_at_seconds": 0.0}},
+                                  {"transform": {"target_node_id": "cam", "keys": [{"t": 0.0, "transform": identity()}, {"t": 5.0, "transform": {"rotation": {"x": 0.0, "y": 1.0, "z": 0.0, "w": 0.0}}}]}}]}],
+        "active_timeline_id": "main"
+    }});
+    let project = project(&doc).value;
+    let back: Value = serde_json::from_str(&project_to_json(&project).unwrap()).unwrap();
+    assert_eq!(back, doc);
+}
+
+#[test]
+fn optional_fields_take_proto_defaults() {
+    let doc = json!({"project": {"id": "p", "scene": {"id": "s", "materials": [{"id": "m"}], "roots": [
+        {"id": "lamp", "transform": {"position": {"x": 0, "y": 0, "z": 0}, "rotation": {"x": 0, "y": 0, "z": 0, "w": 1}, "scale": {"x": 1, "y": 1, "z": 1}},
+         "light": {"type": "NEON", "color": {"r": 1, "g": 1, "b": 1, "a": 1}, "intensity": 1, "range": 1}}
+    ]}}});
+    let converted = project(&doc);
+    assert_eq!(converted.warnings, ["node 'lamp': unknown light type 'NEON', using POINT"]);
+    let scene = converted.value.scene.unwrap();
+    assert_eq!((scene.materials[0].metallic, scene.materials[0].roughness), (0.0, 1.0));
+    let Some(component::Kind::Light(light)) = scene.roots[0].kinds().next() else { panic!("expected a light") };
+    assert_eq!(light.r#type(), light_component::Type::Point);
+    assert_eq!(light.spot_angle, 0.0);
+}
+
+#[test]
+fn legacy_timeline_comes_first() {
+    let doc = json!({"project": {"id": "p", "scene": {"id": "s"},
+        "timeline": {"id": "old", "duration_seconds": 1.0},
+        "timelines": [{"id": "new", "duration_seconds": 2.0}]}});
+    let project = project(&doc).value;
+    let ids: Vec<&str> = project.timelines.iter().map(|t| t.id.as_str()).collect();
+    assert_eq!(ids, ["old", "new"]);
+    // Written back as `timelines` only
+    let wrapper: ProjectWrapperJ = serde_json::from_str(&project_to_json(&project).unwrap()).unwrap();
+    assert!(wrapper.project.timeline.is_none());
+    assert_eq!(wrapper.project.timelines.len(), 2);
+}
+
+#[test]
+fn outputs_round_trip() {
+    let doc = json!({"windows": [
+        {"id": "left", "mode": "FULLSCREEN", "monitor": "DP-1", "x": 0, "y": 0, "screens": [{"node_id": "wall", "x": 0, "y": 0}]},
+        {"id": "right", "mode": "BORDERLESS", "x": 1920, "y": 0, "screens": [{"node_id": "floor", "x": 10, "y": 20}]},
+        {"id": "ops", "mode": "WINDOWED", "x": 0, "y": 0, "screens": []}
+    ]});
+    let config = outputs_from_json(&doc.to_string()).unwrap().value;
+    assert_eq!(config.windows[0].mode(), output_window::Mode::Fullscreen);
+    let back: Value = serde_json::from_str(&outputs_to_json(&config).unwrap()).unwrap();
+    assert_eq!(back, doc);
+    assert_eq!(outputs_from_json(&outputs_to_json(&config).unwrap()).unwrap().value, config);
+
+    let converted = outputs_from_json(r#"{"windows": [{"id": "w", "screens": []}, {"id": "v", "mode": "MAXIMIZED", "screens": []}]}"#).unwrap();
+    assert_eq!(converted.value.windows[0].mode(), output_window::Mode::Windowed);
+    assert_eq!(converted.value.windows[1].mode(), output_window::Mode::Windowed);
+    assert_eq!(converted.warnings, ["output 'v': unknown mode 'MAXIMIZED', using WINDOWED"]);
+}
+
+#[test]
+fn legacy_and_kind_nodes_agree() {
+    let pairs = [
+        (json!({"screen": {"pixels_x": 320, "pixels_y": 160, "enabled": false}}), json!({"kind": {"type": "screen", "pixels": [320, 160], "enabled": false}})),
+        (json!({"camera": {"fov_deg": 40, "near": 0.5, "far": 50}}), json!({"kind": {"type": "camera", "camera": {"fov_deg": 40, "near": 0.5, "far": 50}}})),
+        (json!({"mesh": {"mesh": {"id": "m", "uri": "m.glb"}}}), json!({"kind": {"type": "mesh", "mesh": {"mesh": {"id": "m", "uri": "m.glb"}}}})),
+    ];
+    for (legacy, kind) in pairs {
+        let node = |comp: Value| {
+            let mut n = json!({"id": "n", "transform": identity()});
+            n.as_object_mut().unwrap().extend(comp.as_object().unwrap().clone());
+            project(&with_node(n))
+        };
+        let (legacy, kind) = (node(legacy), node(kind));
+        assert!(legacy.warnings.is_empty() && kind.warnings.is_empty());
+        assert_eq!(legacy.value, kind.value);
+    }
+}
+
+#[test]
+fn screen_kind_defaults_to_enabled() {
+    let scene = project(&with_node(json!({"id": "wall", "transform": identity(), "kind": {"type": "screen", "pixels": [64, 32]}}))).value.scene.unwrap();
+    let Some(screen) = scene.roots[0].screen() else { panic!("expected a screen") };
+    assert_eq!((screen.pixels_x, screen.pixels_y, screen.disabled), (64, 32, false));
+}
+
+#[test]
+fn legacy_fields_add_components() {
+    let converted = project(&with_node(json!({"id": "wall", "transform": identity(),
+        "kind": {"type": "screen", "pixels": [64, 32]}, "light": {"type": "POINT", "color": {"r": 1, "g": 1, "b": 1, "a": 1}, "intensity": 1, "range": 1}})));
+    assert!(converted.warnings.is_empty());
+    let kinds: Vec<_> = converted.value.scene.unwrap().roots[0].kinds().cloned().collect();
+    assert!(matches!(kinds[..], [component::Kind::Screen(_), component::Kind::Light(_)]));
+
+    let converted = project(&with_node(json!({"id": "wall", "transform": identity(),
+        "screen": {"pixels_x": 64, "pixels_y": 32}, "camera": {"fov_deg": 40, "near": 0.5, "far": 50}})));
+    assert!(converted.warnings.is_empty());
+    let kinds: Vec<_> = converted.value.scene.unwrap().roots[0].kinds().cloned().collect();
+    assert!(matches!(kinds[..], [component::Kind::Camera(_), component::Kind::Screen(_)]));
+}
+
+#[test]
+fn legacy_comp0_is_upgraded() {
+    let screen = ScreenComponent { pixels_x: 64, pixels_y: 32, disabled: false };
+    let camera = CameraComponent { fov_deg: 40.0, near: 0.5, far: 50.0 };
+    let mut child = node("cam", vec![], vec![]);
+    child.comp0 = Some(node::Comp0::Camera(camera.clone()));
+    let mut wall = node("wall", vec![component::Kind::Light(LightComponent::default())], vec![child]);
+    wall.comp0 = Some(node::Comp0::Screen(screen.clone()));
+
+    // Written as if it were the first component
+    let written = NodeJ::from(&wall);
+    assert_eq!(written.kind.map(|k| k.type_name().to_string()).as_deref(), Some("screen"));
+    assert_eq!(written.components.len(), 1);
+
+    wall.upgrade_components();
+    assert!(wall.comp0.is_none() && wall.children[0].comp0.is_none());
+    assert_eq!(wall.screen(), Some(&screen));
+    assert_eq!(wall.children[0].camera(), Some(&camera));
+    assert_eq!(wall.kinds().count(), 2);
+}
+
+#[test]
+fn empty_tracks_are_dropped() {
+    let doc = json!({"project": {"id": "p", "scene": {"id": "s"}, "timelines": [{"id": "main", "duration_seconds": 1.0, "tracks": [{}]}]}});
+    let converted = project(&doc);
+    assert_eq!(converted.warnings, ["timeline 'main': track 0 has neither media nor transform and is dropped"]);
+    assert!(converted.value.timelines[0].tracks.is_empty());
+}
+
+#[test]
+fn malformed_kinds_are_rejected() {
+    for kind in [
+        json!({"pixels": [1, 2]}),
+        json!({"type": 3}),
+        json!({"type": "screen", "pixels": [1920]}),
+        json!({"type": "screen", "pixels": "1920x1080"}),
+        json!({"type": "light", "light": {"type": "SPOT"}}),
+        json!({"type": "camera"}),
+        json!({"type": "mesh", "mesh": {"id": "m"}}),
+    ] {
+        let doc = with_node(json!({"id": "n", "transform": identity(), "kind": kind}));
+        let err = project_from_json(&doc.to_string()).unwrap_err();
+        assert!(err.to_string().contains("kind"), "{kind}: {err}");
+        let doc = with_node(json!({"id": "n", "transform": identity(), "components": [kind]}));
+        assert!(project_from_json(&doc.to_string()).is_err(), "{kind}");
+    }
+}
@@ -0,0 +1,24 @@
+use std::path::Path;
+
+use constellation_model::{outputs_schema, project_schema};
+
+/// The published schema must match the Rust types. `UPDATE_SCHEMA=1 cargo test -p
+/// constellation-model` rewrites it.
+fn check(file: &str, generated: String) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("schema").join(file);
+    if std::env::var_os("UPDATE_SCHEMA").is_some() {
+        std::fs::write(&path, &generated).unwrap();
+    }
+    let published = std::fs::read_to_string(&path).unwrap_or_default();
+    assert!(published == generated, "{} is out of date; rerun the tests with UPDATE_SCHEMA=1", path.display());
+}
+
+#[test]
+fn project_schema_is_published() {
+    check("project.schema.json", project_schema());
+}
+
+#[test]
+fn outputs_schema_is_published() {
+    check("outputs.schema.json", outputs_schema());
+}